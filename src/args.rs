@@ -13,6 +13,10 @@ pub struct Opts {
     #[clap(long, default_value = "single-table")]
     pub table_name: String,
 
+    /// Print the read and write capacity consumed by the command
+    #[clap(long)]
+    pub capacity: bool,
+
     #[clap(subcommand)]
     pub commands: Commands,
 }
@@ -25,6 +29,9 @@ pub enum Commands {
     Describe,
     /// Scan for all items in the DynamoDB Table (or an index).
     Scan(ScanOpts),
+    /// Describe, enable or disable Time to Live on the DynamoDB Table.
+    #[clap(name = "ttl")]
+    Ttl(TtlOpts),
 
     /// Put a Model into the DynamdoDB Table.
     PutModel(PutModelOpts),
@@ -32,13 +39,40 @@ pub enum Commands {
     #[clap(name = "put-submodel")]
     PutSubModel(PutSubModelOpts),
 
+    /// Delete a Model, and optionally all of its SubModels.
+    DeleteModel(DeleteModelOpts),
+
+    /// List the revisions of a Model saved with history.
+    History(HistoryOpts),
+    /// Restore a Model from its history, by default as it was before it was deleted.
+    Restore(RestoreOpts),
+
+    /// Append an Event to the log of a Model.
+    LogAppend(LogAppendOpts),
+    /// Show the latest Events in the log of a Model, or those within a time window.
+    LogTail(LogTailOpts),
+
+    /// Link one Model to another with an Edge.
+    Link(LinkOpts),
+    /// Remove the Edge from one Model to another.
+    Unlink(LinkOpts),
+    /// List the Edges to and from a Model.
+    Neighbors(NeighborsOpts),
+
     /// Get a Model by `name`.
     GetModel(GetModelOpts),
     /// Get a SubModel by `parent` Model and `name`.
     #[clap(name = "get-submodel")]
     GetSubModel(GetSubModelOpts),
+    /// Get a Model and some of its SubModels by `name`, as a consistent snapshot.
+    GetMany(GetManyOpts),
     /// Query for Items by `pk` and optional `sk`.
     Query(QueryOpts),
+    /// Tail changes to Items from the DynamoDB Stream.
+    Watch(WatchOpts),
+
+    /// Apply, or show the status of, migrations to the Items in the Table.
+    Migrate(MigrateOpts),
 
     /// Return details about the current IAM user credentials.
     /// This is a demonstration of other rusoto APIs.
@@ -55,9 +89,91 @@ pub struct ScanOpts {
     pub limit: Option<i64>,
 }
 
+#[derive(Clap, Debug)]
+pub struct TtlOpts {
+    #[clap(long)]
+    pub enable: bool,
+
+    #[clap(long, conflicts_with = "enable")]
+    pub disable: bool,
+}
+
+#[derive(Clap, Debug)]
+pub struct DeleteModelOpts {
+    pub name: String,
+
+    /// Also delete the Model's SubModels
+    #[clap(long)]
+    pub cascade: bool,
+
+    /// Keep the Model's history, ending it with a tombstone
+    #[clap(long, conflicts_with = "cascade")]
+    pub history: bool,
+
+    /// Who is deleting the Model, recorded in its history
+    #[clap(long, requires = "history")]
+    pub by: Option<String>,
+}
+
+#[derive(Clap, Debug)]
+pub struct HistoryOpts {
+    pub name: String,
+}
+
+#[derive(Clap, Debug)]
+pub struct RestoreOpts {
+    pub name: String,
+
+    /// The revision to restore, instead of the last one before the Model was deleted
+    #[clap(long)]
+    pub revision: Option<u64>,
+
+    /// Who is restoring the Model, recorded in its history
+    #[clap(long)]
+    pub by: Option<String>,
+}
+
+#[derive(Clap, Debug)]
+pub struct LogAppendOpts {
+    pub parent: String,
+    pub message: String,
+}
+
+#[derive(Clap, Debug)]
+pub struct LogTailOpts {
+    pub parent: String,
+
+    /// The number of Events to show, when no time window is given
+    #[clap(short = 'n', long, default_value = "10")]
+    pub limit: i64,
+
+    /// Show Events from this time (RFC 3339) onwards
+    #[clap(long)]
+    pub since: Option<String>,
+
+    /// Show Events before this time (RFC 3339), defaults to now
+    #[clap(long, requires = "since")]
+    pub until: Option<String>,
+}
+
+#[derive(Clap, Debug)]
+pub struct LinkOpts {
+    pub source: String,
+    pub target: String,
+}
+
+#[derive(Clap, Debug)]
+pub struct NeighborsOpts {
+    pub name: String,
+}
+
 #[derive(Clap, Debug)]
 pub struct GetModelOpts {
     pub name: String,
+
+    /// Also get the Model's SubModels
+    #[clap(long)]
+    pub children: bool,
 }
 
 #[derive(Clap, Debug)]
@@ -66,23 +182,90 @@ pub struct GetSubModelOpts {
     pub name: String,
 }
 
+#[derive(Clap, Debug)]
+pub struct GetManyOpts {
+    pub parent: String,
+    pub names: Vec<String>,
+}
+
 #[derive(Clap, Debug)]
 pub struct QueryOpts {
     pub pk: String,
     pub sk: Option<String>,
 
+    /// Query a GSI, either `model` or `inverted`
     #[clap(long)]
     pub index: Option<String>,
+
+    /// Read the latest items with a strongly consistent read, not possible on a GSI
+    #[clap(long, conflicts_with = "index")]
+    pub consistent: bool,
+}
+
+#[derive(Clap, Debug)]
+pub struct MigrateOpts {
+    #[clap(subcommand)]
+    pub command: MigrateCommand,
+}
+
+#[derive(Clap, Debug)]
+pub enum MigrateCommand {
+    /// Apply every pending migration, resuming one that was interrupted.
+    Up(MigrateUpOpts),
+    /// List the migrations, and which of them have been applied.
+    Status,
+}
+
+#[derive(Clap, Debug)]
+pub struct MigrateUpOpts {
+    /// Count the Items that would be rewritten, without writing anything
+    #[clap(long)]
+    pub dry_run: bool,
+
+    /// Items to scan per page, the progress is saved after each page
+    #[clap(long, default_value = "100")]
+    pub page_size: i64,
+}
+
+#[derive(Clap, Debug)]
+pub struct WatchOpts {
+    /// Resume from, and save the position in the stream to, this file
+    #[clap(long)]
+    pub checkpoint: Option<String>,
+
+    /// Seconds to wait between polling the stream
+    #[clap(long, default_value = "1")]
+    pub interval: u64,
 }
 
 #[derive(Clap, Debug)]
 pub struct PutModelOpts {
     pub name: String,
     pub a_version: i32,
+
+    /// Expire the Model after this many seconds (requires TTL to be enabled)
+    #[clap(long)]
+    pub ttl: Option<i64>,
+
+    /// An email address, which must not belong to any other Model
+    #[clap(long)]
+    pub email: Option<String>,
+
+    /// Also write the Model to its history, as a new revision
+    #[clap(long)]
+    pub history: bool,
+
+    /// Who is saving the Model, recorded in its history
+    #[clap(long, requires = "history")]
+    pub by: Option<String>,
 }
 
 #[derive(Clap, Debug)]
 pub struct PutSubModelOpts {
     pub name: String,
     pub parent: String,
+
+    /// Count the SubModel on its parent, fails if the SubModel already exists
+    #[clap(long)]
+    pub count: bool,
 }
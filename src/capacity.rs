@@ -0,0 +1,233 @@
+use std::{
+    collections::{self, BTreeMap},
+    fmt,
+    sync::Mutex,
+};
+
+use crate::types::{Capacity, ConsumedCapacity};
+
+/// A strongly consistent read of up to this many bytes costs one read capacity unit.
+pub const READ_UNIT_BYTES: usize = 4 * 1024;
+
+/// A write of up to this many bytes costs one write capacity unit.
+pub const WRITE_UNIT_BYTES: usize = 1024;
+
+/// The read capacity units to read `size` bytes: one per 4KB, rounded up,
+/// and half that for an eventually consistent read.
+///
+/// A read costs at least one unit, even of an item that doesn't exist.
+pub fn read_units(size: usize, consistent_read: bool) -> f64 {
+    let units = units(size, READ_UNIT_BYTES);
+    if consistent_read {
+        units
+    } else {
+        units / 2.0
+    }
+}
+
+/// The write capacity units to write `size` bytes: one per 1KB, rounded up.
+///
+/// A write costs at least one unit, even of an item that doesn't exist.
+pub fn write_units(size: usize) -> f64 {
+    units(size, WRITE_UNIT_BYTES)
+}
+
+fn units(size: usize, unit: usize) -> f64 {
+    ((size + unit - 1) / unit).max(1) as f64
+}
+
+/// The kinds of request that consume capacity.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum RequestKind {
+    GetItem,
+    Query,
+    Scan,
+    TransactGetItems,
+    PutItem,
+    UpdateItem,
+    TransactWriteItems,
+}
+
+impl RequestKind {
+    pub fn is_write(self) -> bool {
+        match self {
+            Self::GetItem | Self::Query | Self::Scan | Self::TransactGetItems => false,
+            Self::PutItem | Self::UpdateItem | Self::TransactWriteItems => true,
+        }
+    }
+}
+
+/// The capacity consumed by a single request, added up table and index by index.
+#[derive(Clone, Debug, Default)]
+pub struct Tally {
+    table: f64,
+    indexes: BTreeMap<String, f64>,
+}
+
+impl Tally {
+    pub fn table(&mut self, units: f64) {
+        self.table += units;
+    }
+
+    pub fn index(&mut self, name: &str, units: f64) {
+        *self.indexes.entry(name.to_string()).or_default() += units;
+    }
+
+    /// Transactions cost twice as much as the same reads or writes outside of one.
+    pub fn transactional(mut self) -> Self {
+        self.table *= 2.0;
+        for units in self.indexes.values_mut() {
+            *units *= 2.0;
+        }
+        self
+    }
+
+    /// The tally as DynamoDB reports it with `ReturnConsumedCapacity=INDEXES`.
+    pub fn consumed_capacity(&self, table_name: String) -> ConsumedCapacity {
+        let capacity = |units: f64| Capacity {
+            capacity_units: Some(units),
+            ..Default::default()
+        };
+
+        let global_secondary_indexes: collections::HashMap<String, Capacity> = self
+            .indexes
+            .iter()
+            .map(|(name, units)| (name.clone(), capacity(*units)))
+            .collect();
+
+        ConsumedCapacity {
+            table_name: Some(table_name),
+            capacity_units: Some(self.table + self.indexes.values().sum::<f64>()),
+            table: Some(capacity(self.table)),
+            global_secondary_indexes: if global_secondary_indexes.is_empty() {
+                None
+            } else {
+                Some(global_secondary_indexes)
+            },
+            ..Default::default()
+        }
+    }
+}
+
+/// Read and write capacity units consumed from a table or index.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Usage {
+    pub read_capacity_units: f64,
+    pub write_capacity_units: f64,
+}
+
+impl Usage {
+    fn add(&mut self, kind: RequestKind, units: f64) {
+        if kind.is_write() {
+            self.write_capacity_units += units;
+        } else {
+            self.read_capacity_units += units;
+        }
+    }
+}
+
+/// The requests of one kind, and the capacity units they consumed in total.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Requests {
+    pub count: u64,
+    pub capacity_units: f64,
+}
+
+/// The capacity consumed by every request recorded by a `Meter`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Summary {
+    pub requests: BTreeMap<RequestKind, Requests>,
+    pub table: Usage,
+    pub indexes: BTreeMap<String, Usage>,
+}
+
+impl Summary {
+    pub fn read_capacity_units(&self) -> f64 {
+        let indexes: f64 = self.indexes.values().map(|u| u.read_capacity_units).sum();
+        self.table.read_capacity_units + indexes
+    }
+
+    pub fn write_capacity_units(&self) -> f64 {
+        let indexes: f64 = self.indexes.values().map(|u| u.write_capacity_units).sum();
+        self.table.write_capacity_units + indexes
+    }
+}
+
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (kind, requests) in &self.requests {
+            writeln!(
+                f,
+                "{:?}: {} requests, {} capacity units",
+                kind, requests.count, requests.capacity_units
+            )?;
+        }
+
+        let usage = |f: &mut fmt::Formatter<'_>, name: &str, usage: &Usage| {
+            writeln!(
+                f,
+                "{}: {} RCU, {} WCU",
+                name, usage.read_capacity_units, usage.write_capacity_units
+            )
+        };
+        usage(f, "table", &self.table)?;
+        for (name, index) in &self.indexes {
+            usage(f, &format!("index {}", name), index)?;
+        }
+
+        write!(
+            f,
+            "total: {} RCU, {} WCU",
+            self.read_capacity_units(),
+            self.write_capacity_units()
+        )
+    }
+}
+
+/// Accumulates the capacity consumed by the requests of a client.
+///
+/// A client given a meter asks DynamoDB to return the consumed capacity
+/// of every request that consumes any, and records it here.
+#[derive(Debug, Default)]
+pub struct Meter(Mutex<Summary>);
+
+impl Meter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record a request, with the capacity it consumed from each table.
+    pub fn record<'a, I>(&self, kind: RequestKind, consumed: I)
+    where
+        I: IntoIterator<Item = &'a ConsumedCapacity>,
+    {
+        let mut summary = self.0.lock().expect("meter lock");
+
+        let mut capacity_units = 0.0;
+        for consumed in consumed {
+            let total = consumed.capacity_units.unwrap_or_default();
+            capacity_units += total;
+
+            // Without a breakdown, everything was consumed from the table
+            let table = match &consumed.table {
+                Some(table) => table.capacity_units.unwrap_or_default(),
+                None if consumed.global_secondary_indexes.is_none() => total,
+                None => 0.0,
+            };
+            summary.table.add(kind, table);
+
+            for (name, index) in consumed.global_secondary_indexes.iter().flatten() {
+                let usage = summary.indexes.entry(name.clone()).or_default();
+                usage.add(kind, index.capacity_units.unwrap_or_default());
+            }
+        }
+
+        let requests = summary.requests.entry(kind).or_default();
+        requests.count += 1;
+        requests.capacity_units += capacity_units;
+    }
+
+    pub fn summary(&self) -> Summary {
+        self.0.lock().expect("meter lock").clone()
+    }
+}
@@ -0,0 +1,75 @@
+use std::error::Error;
+
+use crate::{
+    registry::{Item, Registry},
+    traits::{Database, Entity},
+    types,
+};
+
+/// Items of any entity type that share a partition key.
+///
+/// Items are kept in sort key order, and decoded on demand by their `model` attribute.
+#[derive(Clone, Debug, Default)]
+pub struct ItemCollection {
+    items: Vec<types::HashMap>,
+}
+
+impl ItemCollection {
+    pub fn new(items: Vec<types::HashMap>) -> Self {
+        Self { items }
+    }
+
+    /// Load the whole partition `pk` with a single query.
+    ///
+    /// A query returns at most 1MB of items, larger partitions are truncated.
+    pub async fn query<S>(db: &impl Database, pk: S) -> Result<Self, Box<dyn Error>>
+    where
+        S: Into<String>,
+    {
+        let res = db.query(None, pk.into(), String::new()).await?;
+        Ok(Self::new(res.items.unwrap_or_default()))
+    }
+
+    pub fn items(&self) -> &[types::HashMap] {
+        &self.items
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    /// Decode every item of type `E`, ignoring items of other types.
+    pub fn entities<E: Entity>(&self) -> serde_dynamodb::error::Result<Vec<E>> {
+        self.items
+            .iter()
+            .filter(|item| model(item) == Some(E::MODEL))
+            .map(E::from_hashmap)
+            .collect()
+    }
+
+    /// Decode every item, whatever its type.
+    pub fn decode(&self, registry: &Registry) -> serde_dynamodb::error::Result<Vec<Item>> {
+        self.items
+            .iter()
+            .map(|item| registry.decode(item))
+            .collect()
+    }
+
+    /// Decode the first item of type `E`, if there is one.
+    pub fn entity<E: Entity>(&self) -> serde_dynamodb::error::Result<Option<E>> {
+        self.items
+            .iter()
+            .find(|item| model(item) == Some(E::MODEL))
+            .map(E::from_hashmap)
+            .transpose()
+    }
+}
+
+/// The `model` attribute that identifies the type of an item.
+pub fn model(item: &types::HashMap) -> Option<&str> {
+    item.get("model").and_then(|model| model.s.as_deref())
+}
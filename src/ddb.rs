@@ -1,4 +1,5 @@
 use async_trait::async_trait;
+use std::sync::Arc;
 
 #[rustfmt::skip]
 use rusoto_dynamodb::{
@@ -12,18 +13,52 @@ use rusoto_dynamodb::{
 
     GlobalSecondaryIndex,
     Projection,
+    StreamSpecification,
 };
 
 use crate::{
-    traits::{make_key, Database, Key},
+    capacity::{Meter, RequestKind},
+    traits::{
+        add_expression, make_key, Database, Key, QueryOptions, ReadOptions, SortKeyCondition,
+        TransactOptions,
+    },
     types::*,
 };
 
-pub struct DDB(DynamoDbClient, String);
+pub struct DDB(DynamoDbClient, String, Option<Arc<Meter>>);
 
 impl DDB {
     pub fn new<T: Into<String>>(client: DynamoDbClient, table_name: T) -> Self {
-        Self(client, table_name.into())
+        Self(client, table_name.into(), None)
+    }
+
+    /// Ask for the capacity consumed by every request, and record it in `meter`.
+    pub fn with_capacity_meter(mut self, meter: Arc<Meter>) -> Self {
+        self.2 = Some(meter);
+        self
+    }
+
+    // Consumed capacity is only returned when it is being metered
+    fn return_consumed_capacity(&self) -> Option<String> {
+        self.2.as_ref().map(|_| "INDEXES".to_string())
+    }
+
+    fn record<'a, I>(&self, kind: RequestKind, consumed: I)
+    where
+        I: IntoIterator<Item = &'a ConsumedCapacity>,
+    {
+        if let Some(meter) = &self.2 {
+            meter.record(kind, consumed);
+        }
+    }
+}
+
+// Eventually consistent reads are the default, and are left out of the request
+fn consistent_read(consistent_read: bool) -> Option<bool> {
+    if consistent_read {
+        Some(true)
+    } else {
+        None
     }
 }
 
@@ -70,31 +105,60 @@ impl Database for DDB {
                         attribute_type: "S".to_string(),
                     },
                 ],
-                global_secondary_indexes: Some(vec![GlobalSecondaryIndex {
-                    index_name: "model".to_string(),
-                    key_schema: vec![
-                        KeySchemaElement {
-                            attribute_name: "model".to_string(),
-                            key_type: "HASH".to_string(),
+                global_secondary_indexes: Some(vec![
+                    GlobalSecondaryIndex {
+                        index_name: "model".to_string(),
+                        key_schema: vec![
+                            KeySchemaElement {
+                                attribute_name: "model".to_string(),
+                                key_type: "HASH".to_string(),
+                            },
+                            KeySchemaElement {
+                                attribute_name: "sk".to_string(),
+                                key_type: "RANGE".to_string(),
+                            },
+                        ],
+                        projection: Projection {
+                            projection_type: Some("ALL".to_string()),
+                            ..Default::default()
                         },
-                        KeySchemaElement {
-                            attribute_name: "sk".to_string(),
-                            key_type: "RANGE".to_string(),
+                        provisioned_throughput: Some(ProvisionedThroughput {
+                            read_capacity_units: 1,
+                            write_capacity_units: 1,
+                        }),
+                    },
+                    // The adjacency list, for following relationships in reverse
+                    GlobalSecondaryIndex {
+                        index_name: "inverted".to_string(),
+                        key_schema: vec![
+                            KeySchemaElement {
+                                attribute_name: "sk".to_string(),
+                                key_type: "HASH".to_string(),
+                            },
+                            KeySchemaElement {
+                                attribute_name: "pk".to_string(),
+                                key_type: "RANGE".to_string(),
+                            },
+                        ],
+                        projection: Projection {
+                            projection_type: Some("ALL".to_string()),
+                            ..Default::default()
                         },
-                    ],
-                    projection: Projection {
-                        projection_type: Some("ALL".to_string()),
-                        ..Default::default()
+                        provisioned_throughput: Some(ProvisionedThroughput {
+                            read_capacity_units: 1,
+                            write_capacity_units: 1,
+                        }),
                     },
-                    provisioned_throughput: Some(ProvisionedThroughput {
-                        read_capacity_units: 1,
-                        write_capacity_units: 1,
-                    }),
-                }]),
+                ]),
                 provisioned_throughput: Some(ProvisionedThroughput {
                     read_capacity_units: 1,
                     write_capacity_units: 1,
                 }),
+                // Consumed by `stream::StreamConsumer`
+                stream_specification: Some(StreamSpecification {
+                    stream_enabled: true,
+                    stream_view_type: Some("NEW_AND_OLD_IMAGES".to_string()),
+                }),
                 ..Default::default()
             })
             .await
@@ -110,71 +174,129 @@ impl Database for DDB {
     }
 
     async fn scan<S>(&self, index: Option<S>, limit: Option<i64>) -> ScanResult
+    where
+        S: Into<String> + Send,
+    {
+        self.scan_from(index, limit, None).await
+    }
+
+    async fn scan_from<S>(
+        &self,
+        index: Option<S>,
+        limit: Option<i64>,
+        exclusive_start_key: Option<HashMap>,
+    ) -> ScanResult
     where
         S: Into<String> + Send,
     {
         let index_name: Option<String> = index.map(|idx| idx.into());
-        self.0
+        let output = self
+            .0
             .scan(ScanInput {
                 table_name: self.table_name(),
                 index_name,
                 limit,
+                exclusive_start_key,
+                return_consumed_capacity: self.return_consumed_capacity(),
                 ..Default::default()
             })
-            .await
+            .await?;
+
+        self.record(RequestKind::Scan, output.consumed_capacity.as_ref());
+        Ok(output)
     }
 
     async fn get_item<S>(&self, pk: S, sk: S) -> GetItemResult
+    where
+        S: Into<String> + Send,
+    {
+        self.get_item_with(pk, sk, ReadOptions::default()).await
+    }
+
+    async fn get_item_with<S>(&self, pk: S, sk: S, options: ReadOptions) -> GetItemResult
     where
         S: Into<String> + Send,
     {
         let key = make_key(pk, sk);
-        self.0
+        let output = self
+            .0
             .get_item(GetItemInput {
                 table_name: self.table_name(),
                 key,
+                consistent_read: consistent_read(options.consistent_read),
+                return_consumed_capacity: self.return_consumed_capacity(),
                 ..Default::default()
             })
-            .await
+            .await?;
+
+        self.record(RequestKind::GetItem, output.consumed_capacity.as_ref());
+        Ok(output)
     }
 
     async fn put_item<H>(&self, item: H) -> PutItemResult
     where
         H: Into<HashMap> + Key + Send,
     {
-        self.0
+        let output = self
+            .0
             .put_item(PutItemInput {
                 table_name: self.table_name(),
                 item: item.into(),
+                return_consumed_capacity: self.return_consumed_capacity(),
                 ..Default::default()
             })
-            .await
+            .await?;
+
+        self.record(RequestKind::PutItem, output.consumed_capacity.as_ref());
+        Ok(output)
     }
 
     async fn query<S>(&self, index: Option<S>, pk: S, sk: S) -> QueryResult
+    where
+        S: Into<String> + Send,
+    {
+        let condition = SortKeyCondition::BeginsWith(sk.into());
+        self.query_with(index, pk, condition, QueryOptions::default())
+            .await
+    }
+
+    async fn query_with<S>(
+        &self,
+        index: Option<S>,
+        pk: S,
+        condition: SortKeyCondition,
+        options: QueryOptions,
+    ) -> QueryResult
     where
         S: Into<String> + Send,
     {
         let index_name = index.map(|s| s.into());
 
-        let key_condition_expression = Some("#pk = :pk AND begins_with(#sk, :sk)".to_string());
-        let expression_attribute_values = {
-            let mut values = HashMap::new();
-            values.insert(
-                ":pk".to_string(),
-                AttributeValue {
-                    s: Some(pk.into()),
-                    ..Default::default()
-                },
-            );
-            values.insert(
-                ":sk".to_string(),
-                AttributeValue {
-                    s: Some(sk.into()),
-                    ..Default::default()
-                },
-            );
-            Some(values)
+        // An empty sort key prefix selects the whole partition, DynamoDB rejects it as a condition
+        let condition = match condition {
+            SortKeyCondition::BeginsWith(prefix) if prefix.is_empty() => SortKeyCondition::All,
+            condition => condition,
+        };
+
+        let string = |s: String| AttributeValue {
+            s: Some(s),
+            ..Default::default()
+        };
+        let mut values = HashMap::new();
+        values.insert(":pk".to_string(), string(pk.into()));
+
+        let has_sk = condition != SortKeyCondition::All;
+        let key_condition_expression = match condition {
+            SortKeyCondition::All => "#pk = :pk".to_string(),
+            SortKeyCondition::BeginsWith(prefix) => {
+                values.insert(":sk".to_string(), string(prefix));
+                "#pk = :pk AND begins_with(#sk, :sk)".to_string()
+            }
+            SortKeyCondition::Between(low, high) => {
+                values.insert(":low".to_string(), string(low));
+                values.insert(":high".to_string(), string(high));
+                "#pk = :pk AND #sk BETWEEN :low AND :high".to_string()
+            }
         };
 
         type Names = std::collections::HashMap<String, String>;
@@ -182,39 +304,160 @@ impl Database for DDB {
             None => {
                 let mut names = Names::new();
                 names.insert("#pk".to_string(), "pk".to_string());
-                names.insert("#sk".to_string(), "sk".to_string());
+                if has_sk {
+                    names.insert("#sk".to_string(), "sk".to_string());
+                }
                 Some(names)
             }
             Some(index) if index == "model" => {
                 let mut names = Names::new();
                 names.insert("#pk".to_string(), "model".to_string());
-                names.insert("#sk".to_string(), "sk".to_string());
+                if has_sk {
+                    names.insert("#sk".to_string(), "sk".to_string());
+                }
+                Some(names)
+            }
+            // The key attributes swap places in the inverted index
+            Some(index) if index == "inverted" => {
+                let mut names = Names::new();
+                names.insert("#pk".to_string(), "sk".to_string());
+                if has_sk {
+                    names.insert("#sk".to_string(), "pk".to_string());
+                }
                 Some(names)
             }
             Some(_) => None,
         };
 
-        self.0
+        // Ascending is the default, and is left out of the request
+        let scan_index_forward = if options.descending {
+            Some(false)
+        } else {
+            None
+        };
+
+        let output = self
+            .0
             .query(QueryInput {
                 table_name: self.table_name(),
                 index_name,
-                key_condition_expression,
+                key_condition_expression: Some(key_condition_expression),
                 expression_attribute_names,
-                expression_attribute_values,
+                expression_attribute_values: Some(values),
+                scan_index_forward,
+                limit: options.limit,
+                exclusive_start_key: options.exclusive_start_key,
+                consistent_read: consistent_read(options.consistent_read),
+                return_consumed_capacity: self.return_consumed_capacity(),
                 ..Default::default()
             })
-            .await
+            .await?;
+
+        self.record(RequestKind::Query, output.consumed_capacity.as_ref());
+        Ok(output)
+    }
+
+    async fn increment<S>(&self, pk: S, sk: S, attribute: S, by: i64) -> UpdateItemResult
+    where
+        S: Into<String> + Send,
+    {
+        let (update_expression, names, values) = add_expression(attribute, by);
+
+        let output = self
+            .0
+            .update_item(UpdateItemInput {
+                table_name: self.table_name(),
+                key: make_key(pk, sk),
+                update_expression: Some(update_expression),
+                expression_attribute_names: Some(names),
+                expression_attribute_values: Some(values),
+                return_values: Some("UPDATED_NEW".to_string()),
+                return_consumed_capacity: self.return_consumed_capacity(),
+                ..Default::default()
+            })
+            .await?;
+
+        self.record(RequestKind::UpdateItem, output.consumed_capacity.as_ref());
+        Ok(output)
     }
 
     async fn transact_write_items(
         &self,
         transact_items: Vec<TransactWriteItem>,
     ) -> TransactWriteItemsResult {
-        self.0
+        self.transact_write_items_with(transact_items, TransactOptions::default())
+            .await
+    }
+
+    async fn transact_write_items_with(
+        &self,
+        transact_items: Vec<TransactWriteItem>,
+        options: TransactOptions,
+    ) -> TransactWriteItemsResult {
+        let output = self
+            .0
             .transact_write_items(TransactWriteItemsInput {
                 transact_items,
+                client_request_token: options.client_request_token,
+                return_consumed_capacity: self.return_consumed_capacity(),
                 ..Default::default()
             })
+            .await?;
+
+        let consumed = output.consumed_capacity.iter().flatten();
+        self.record(RequestKind::TransactWriteItems, consumed);
+        Ok(output)
+    }
+
+    async fn transact_get_items(&self, keys: Vec<(String, String)>) -> TransactGetItemsResult {
+        let transact_items = keys
+            .into_iter()
+            .map(|(pk, sk)| TransactGetItem {
+                get: Get {
+                    table_name: self.table_name(),
+                    key: make_key(pk, sk),
+                    ..Default::default()
+                },
+            })
+            .collect();
+
+        let output = self
+            .0
+            .transact_get_items(TransactGetItemsInput {
+                transact_items,
+                return_consumed_capacity: self.return_consumed_capacity(),
+            })
+            .await?;
+
+        let consumed = output.consumed_capacity.iter().flatten();
+        self.record(RequestKind::TransactGetItems, consumed);
+        Ok(output)
+    }
+
+    async fn update_time_to_live<S>(
+        &self,
+        attribute_name: S,
+        enabled: bool,
+    ) -> UpdateTimeToLiveResult
+    where
+        S: Into<String> + Send,
+    {
+        self.0
+            .update_time_to_live(UpdateTimeToLiveInput {
+                table_name: self.table_name(),
+                time_to_live_specification: TimeToLiveSpecification {
+                    attribute_name: attribute_name.into(),
+                    enabled,
+                },
+            })
+            .await
+    }
+
+    async fn describe_time_to_live(&self) -> DescribeTimeToLiveResult {
+        self.0
+            .describe_time_to_live(DescribeTimeToLiveInput {
+                table_name: self.table_name(),
+            })
             .await
     }
 }
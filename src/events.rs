@@ -0,0 +1,255 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use std::error::Error;
+
+use crate::{
+    clock::{self, Clock},
+    keys::KeyBuilder,
+    traits::{self, Database, Entity, QueryOptions, SortKeyCondition},
+    types, validation, ItemCollection, Model, ValidationError,
+};
+
+/// An entry in the time ordered log of a Model, such as an audit entry or a measurement.
+///
+/// Each Model's log has a partition of its own, `event#model#{name}`, so that a long log
+/// neither shares throughput with the Model nor is read along with its SubModels.
+/// Events are sorted by the time they happened, `at#{timestamp}#id#{uuid}`, where the
+/// random id keeps events at the same instant apart.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Event {
+    parent: String,
+    at: DateTime<Utc>,
+    message: String,
+
+    // These are used as dynamodb key attributes
+    pk: String,
+    sk: String,
+    model: String,
+}
+
+impl Event {
+    pub fn new<S>(parent: S, message: S) -> Result<Self, ValidationError>
+    where
+        S: Into<String>,
+    {
+        Self::new_with_clock(parent, message, &clock::SystemClock)
+    }
+
+    /// Create an Event in the log of the Model called `parent`, happening now according to `clock`.
+    pub fn new_with_clock<S>(
+        parent: S,
+        message: S,
+        clock: &dyn Clock,
+    ) -> Result<Self, ValidationError>
+    where
+        S: Into<String>,
+    {
+        let parent = parent.into();
+        validation::validate_name(Model::MODEL, &parent)?;
+
+        let at = clock.now();
+        let pk = Self::partition_of(&parent);
+        let sk = Self::key_of(at)
+            .segment("id", &Uuid::new_v4().to_simple().to_string())
+            .build();
+        validation::validate_key(&pk, &sk)?;
+
+        Ok(Self {
+            pk,
+            sk,
+            model: Self::MODEL.to_string(),
+
+            parent,
+            at,
+            message: message.into(),
+        })
+    }
+
+    /// The partition key of the log of the Model called `parent`.
+    pub fn partition_of(parent: &str) -> String {
+        let label = KeyBuilder::new().label_prefix(Self::MODEL);
+        format!("{}{}", label, Model::key_of(parent).build())
+    }
+
+    /// The start of the `sk` of Events happening at `at`.
+    pub fn key_of(at: DateTime<Utc>) -> KeyBuilder {
+        KeyBuilder::new().sortable("at", &at)
+    }
+
+    pub fn from_hashmap(hashmap: &types::HashMap) -> serde_dynamodb::error::Result<Self> {
+        serde_dynamodb::from_hashmap(hashmap.to_owned())
+    }
+
+    pub fn to_hashmap(&self) -> serde_dynamodb::error::Result<types::HashMap> {
+        serde_dynamodb::to_hashmap(&self)
+    }
+
+    /// Add a new Event to the log of the Model called `parent`, which must exist.
+    pub async fn append<S>(
+        db: &impl Database,
+        parent: S,
+        message: S,
+    ) -> Result<Self, Box<dyn Error>>
+    where
+        S: Into<String>,
+    {
+        let event = Self::new(parent, message)?;
+        event.save(db).await?;
+        Ok(event)
+    }
+
+    /// Save the Event, in a transaction that checks its parent exists.
+    pub async fn save(&self, db: &impl Database) -> Result<(), Box<dyn Error>> {
+        let parent = Model::key_of(&self.parent).build();
+
+        let transact_items = vec![
+            db.condition_check_exists(&parent, &parent, Model::MODEL),
+            db.put_new(self.to_hashmap()?),
+        ];
+        db.transact_write_items(transact_items).await?;
+
+        Ok(())
+    }
+
+    /// The latest `n` Events in the log of the Model called `parent`, newest first.
+    pub async fn latest<S>(
+        db: &impl Database,
+        parent: S,
+        n: i64,
+    ) -> Result<Vec<Self>, Box<dyn Error>>
+    where
+        S: Into<String>,
+    {
+        let pk = Self::partition_of(&parent.into());
+        let prefix = KeyBuilder::new().label_prefix("at");
+        let options = QueryOptions {
+            descending: true,
+            limit: Some(n),
+            ..Default::default()
+        };
+
+        let res = db
+            .query_with(None, pk, SortKeyCondition::BeginsWith(prefix), options)
+            .await?;
+        Ok(ItemCollection::new(res.items.unwrap_or_default()).entities()?)
+    }
+
+    /// The Events in the log of the Model called `parent` from `since` up to,
+    /// but not including, `until`, newest first.
+    ///
+    /// Every page of the query is read, however many Events there are.
+    pub async fn between<S>(
+        db: &impl Database,
+        parent: S,
+        since: DateTime<Utc>,
+        until: DateTime<Utc>,
+    ) -> Result<Vec<Self>, Box<dyn Error>>
+    where
+        S: Into<String>,
+    {
+        let pk = Self::partition_of(&parent.into());
+        if until <= since {
+            return Ok(vec![]);
+        }
+
+        // An Event's `sk` continues past its timestamp, so one at `until` sorts after the bound
+        let condition =
+            SortKeyCondition::Between(Self::key_of(since).build(), Self::key_of(until).build());
+
+        let mut events = vec![];
+        let mut exclusive_start_key = None;
+        loop {
+            let options = QueryOptions {
+                descending: true,
+                exclusive_start_key,
+                ..Default::default()
+            };
+            let res = db
+                .query_with(None, pk.clone(), condition.clone(), options)
+                .await?;
+
+            let page = ItemCollection::new(res.items.unwrap_or_default());
+            events.append(&mut page.entities()?);
+
+            exclusive_start_key = res.last_evaluated_key;
+            if exclusive_start_key.is_none() {
+                return Ok(events);
+            }
+        }
+    }
+
+    /// The keys of every Event in the log of the Model called `parent`,
+    /// which is deleted along with it.
+    pub async fn keys_of(
+        db: &impl Database,
+        parent: &str,
+    ) -> Result<Vec<(String, String)>, Box<dyn Error>> {
+        let pk = Self::partition_of(parent);
+
+        let mut keys = vec![];
+        let mut exclusive_start_key = None;
+        loop {
+            let options = QueryOptions {
+                exclusive_start_key,
+                consistent_read: true,
+                ..Default::default()
+            };
+            let res = db
+                .query_with(None, pk.clone(), SortKeyCondition::All, options)
+                .await?;
+            keys.extend(res.items.unwrap_or_default().iter().map(traits::Key::key));
+
+            exclusive_start_key = res.last_evaluated_key;
+            if exclusive_start_key.is_none() {
+                return Ok(keys);
+            }
+        }
+    }
+
+    /// The name of the Model whose log this Event is in.
+    pub fn parent(&self) -> &str {
+        &self.parent
+    }
+
+    pub fn at(&self) -> DateTime<Utc> {
+        self.at
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    pub fn pk(&self) -> String {
+        self.pk.clone()
+    }
+
+    pub fn sk(&self) -> String {
+        self.sk.clone()
+    }
+
+    pub fn model(&self) -> String {
+        self.model.clone()
+    }
+}
+
+impl Entity for Event {
+    const MODEL: &'static str = "event";
+
+    fn from_hashmap(hashmap: &types::HashMap) -> serde_dynamodb::error::Result<Self> {
+        Self::from_hashmap(hashmap)
+    }
+}
+
+impl traits::Transactable for Event {}
+
+impl traits::Key for Event {
+    fn key(&self) -> (String, String) {
+        (self.pk(), self.sk())
+    }
+
+    fn model_key(&self) -> (String, String) {
+        (self.model(), self.sk())
+    }
+}
@@ -0,0 +1,272 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use std::{collections, error::Error};
+
+use crate::{
+    clock::{self, Clock},
+    traits::{self, Database, Entity, QueryOptions, SortKeyCondition, TransactionalOperations},
+    types, ItemCollection, Model, TTL_ATTRIBUTE,
+};
+
+/// The attribute holding the revision a Model was last saved as, see `Model::save_with_history`.
+pub const REVISION_ATTRIBUTE: &str = "revision";
+
+type Names = collections::HashMap<String, String>;
+
+// Attributes describing a change, kept only on the copy in the history
+const CHANGE_ATTRIBUTES: &[&str] = &["changed_at", "changed_by", "deleted"];
+
+/// Who is making a change, and when, recorded with each revision.
+#[derive(Clone, Debug)]
+pub struct Audit {
+    changed_by: Option<String>,
+    changed_at: DateTime<Utc>,
+}
+
+impl Audit {
+    pub fn new(changed_by: Option<String>) -> Self {
+        Self::new_with_clock(changed_by, &clock::SystemClock)
+    }
+
+    /// Record a change made now, according to `clock`.
+    pub fn new_with_clock(changed_by: Option<String>, clock: &dyn Clock) -> Self {
+        Self {
+            changed_by,
+            changed_at: clock.now(),
+        }
+    }
+
+    pub fn changed_by(&self) -> Option<&str> {
+        self.changed_by.as_deref()
+    }
+
+    pub fn changed_at(&self) -> DateTime<Utc> {
+        self.changed_at
+    }
+}
+
+// The attributes of a revision besides the Model it holds
+#[derive(Serialize, Deserialize)]
+struct Change {
+    revision: u64,
+    changed_at: DateTime<Utc>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    changed_by: Option<String>,
+    #[serde(default)]
+    deleted: bool,
+}
+
+/// An immutable copy of a Model, written alongside it each time it is saved with history.
+///
+/// Revisions share the partition of the Model, with the sort key `model#{name}#v#{n}`.
+/// Deleting a Model with history writes a final revision, a tombstone, marked `deleted`.
+#[derive(Clone, Debug)]
+pub struct Revision {
+    revision: u64,
+    changed_at: DateTime<Utc>,
+    changed_by: Option<String>,
+    deleted: bool,
+
+    // The Model as it was saved, or as it was when it was deleted
+    snapshot: Model,
+}
+
+impl Revision {
+    /// The `sk` of revision `n` of the Model called `name`.
+    ///
+    /// Revision numbers are sortable, so that revisions sort in order.
+    pub fn key_of(name: &str, revision: u64) -> String {
+        Model::key_of(name).sortable("v", &revision).build()
+    }
+
+    /// A `begins_with` prefix of the `sk` of every revision of the Model called `name`.
+    pub fn prefix(name: &str) -> String {
+        Model::key_of(name).label_prefix("v")
+    }
+
+    /// Copy the `model` into the history as revision `n`.
+    pub(crate) fn to_hashmap(
+        model: &Model,
+        revision: u64,
+        audit: &Audit,
+        deleted: bool,
+    ) -> Result<types::HashMap, Box<dyn Error>> {
+        let mut hashmap = model.to_hashmap()?;
+        hashmap.insert(
+            "sk".to_string(),
+            types::string(Self::key_of(model.name(), revision)),
+        );
+        hashmap.insert("model".to_string(), types::string(Self::MODEL));
+
+        // History is kept, even once the Model itself expires
+        hashmap.remove(TTL_ATTRIBUTE);
+
+        let change = Change {
+            revision,
+            changed_at: audit.changed_at,
+            changed_by: audit.changed_by.clone(),
+            deleted,
+        };
+        hashmap.extend(serde_dynamodb::to_hashmap(&change)?);
+
+        Ok(hashmap)
+    }
+
+    pub fn from_hashmap(hashmap: &types::HashMap) -> serde_dynamodb::error::Result<Self> {
+        let change: Change = serde_dynamodb::from_hashmap(hashmap.to_owned())?;
+
+        // The rest of the item is the Model, under its own key and type
+        let mut snapshot = hashmap.clone();
+        for attribute in CHANGE_ATTRIBUTES {
+            snapshot.remove(*attribute);
+        }
+        if let Some(pk) = snapshot.get("pk").cloned() {
+            snapshot.insert("sk".to_string(), pk);
+        }
+        snapshot.insert("model".to_string(), types::string(Model::MODEL));
+
+        Ok(Self {
+            revision: change.revision,
+            changed_at: change.changed_at,
+            changed_by: change.changed_by,
+            deleted: change.deleted,
+            snapshot: Model::from_hashmap(&snapshot)?,
+        })
+    }
+
+    /// Every revision of the Model called `name`, oldest first.
+    ///
+    /// A query returns at most 1MB of items, longer histories are truncated.
+    pub async fn history<S>(db: &impl Database, name: S) -> Result<Vec<Self>, Box<dyn Error>>
+    where
+        S: Into<String>,
+    {
+        let name = name.into();
+        let pk = Model::key_of(&name).build();
+
+        let res = db.query(None, pk, Self::prefix(&name)).await?;
+        Ok(ItemCollection::new(res.items.unwrap_or_default()).entities()?)
+    }
+
+    /// The number of the latest revision of the Model called `name`, or 0 if it has no history.
+    ///
+    /// Only the latest revision is read, however long the history,
+    /// with a consistent read so that a revision saved a moment ago is not missed.
+    pub async fn latest(db: &impl Database, name: &str) -> Result<u64, Box<dyn Error>> {
+        let pk = Model::key_of(name).build();
+        let options = QueryOptions {
+            descending: true,
+            limit: Some(1),
+            consistent_read: true,
+            ..Default::default()
+        };
+
+        let res = db
+            .query_with(
+                None,
+                pk,
+                SortKeyCondition::BeginsWith(Self::prefix(name)),
+                options,
+            )
+            .await?;
+        match res.items.unwrap_or_default().first() {
+            Some(hashmap) => Ok(Self::from_hashmap(hashmap)?.revision),
+            None => Ok(0),
+        }
+    }
+
+    pub fn revision(&self) -> u64 {
+        self.revision
+    }
+
+    pub fn changed_at(&self) -> DateTime<Utc> {
+        self.changed_at
+    }
+
+    pub fn changed_by(&self) -> Option<&str> {
+        self.changed_by.as_deref()
+    }
+
+    /// Whether this is the tombstone of a deleted Model.
+    pub fn is_deleted(&self) -> bool {
+        self.deleted
+    }
+
+    pub fn model(&self) -> &Model {
+        &self.snapshot
+    }
+
+    pub fn into_model(self) -> Model {
+        self.snapshot
+    }
+}
+
+impl Entity for Revision {
+    const MODEL: &'static str = "revision";
+
+    fn from_hashmap(hashmap: &types::HashMap) -> serde_dynamodb::error::Result<Self> {
+        Self::from_hashmap(hashmap)
+    }
+}
+
+// The condition that a Model is still at revision `expected`, or has no history if `None`,
+// which fails when another save with history got there first
+fn condition(expected: Option<u64>) -> (String, Names, Option<types::HashMap>) {
+    let mut names = collections::HashMap::new();
+    names.insert("#revision".to_string(), REVISION_ATTRIBUTE.to_string());
+
+    match expected {
+        Some(revision) => {
+            let mut values = types::HashMap::new();
+            values.insert(
+                ":revision".to_string(),
+                types::AttributeValue {
+                    n: Some(revision.to_string()),
+                    ..Default::default()
+                },
+            );
+            ("#revision = :revision".to_string(), names, Some(values))
+        }
+        None => ("attribute_not_exists(#revision)".to_string(), names, None),
+    }
+}
+
+/// Make a write of the Model's latest item conditional on it being at revision `expected`.
+pub(crate) fn at_revision(
+    mut write: types::TransactWriteItem,
+    expected: Option<u64>,
+) -> types::TransactWriteItem {
+    if let Some((condition_expression, names, values)) = traits::condition_mut(&mut write) {
+        let (expression, revision_names, revision_values) = condition(expected);
+        *condition_expression = Some(match condition_expression.take() {
+            Some(existing) => format!("{} AND {}", existing, expression),
+            None => expression,
+        });
+        names
+            .get_or_insert_with(Default::default)
+            .extend(revision_names);
+        if let Some(revision_values) = revision_values {
+            values
+                .get_or_insert_with(Default::default)
+                .extend(revision_values);
+        }
+    }
+    write
+}
+
+/// A delete of the Model's latest item, conditional on it being at revision `expected`.
+pub(crate) fn delete_at_revision(
+    db: &impl TransactionalOperations,
+    model: &Model,
+    expected: Option<u64>,
+) -> types::TransactWriteItem {
+    let mut delete = db.delete(model.pk(), model.sk());
+    if let Some(delete) = delete.delete.as_mut() {
+        let (expression, names, values) = condition(expected);
+        delete.condition_expression = Some(expression);
+        delete.expression_attribute_names = Some(names);
+        delete.expression_attribute_values = values;
+    }
+    delete
+}
@@ -0,0 +1,187 @@
+use std::str::Split;
+
+use crate::sortable::SortKey;
+
+/// Separates the segments of a key.
+pub const DELIMITER: char = '#';
+
+#[derive(thiserror::Error, Debug, PartialEq)]
+pub enum KeyError {
+    #[error("expected a {expected} segment in key: {key}")]
+    UnexpectedLabel { expected: String, key: String },
+    #[error("key has a label without a value: {0}")]
+    MissingValue(String),
+    #[error("key has more segments than expected: {0}")]
+    TrailingSegments(String),
+    #[error("invalid escape sequence in key: {0}")]
+    InvalidEscape(String),
+    #[error("invalid sortable value in key: {0}")]
+    InvalidSortKey(String),
+}
+
+/// A labelled value within a key, such as `model#foo`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Segment {
+    pub label: String,
+    pub value: String,
+}
+
+/// Builds a key out of labelled segments, escaping the delimiter in values.
+///
+/// ```
+/// use single_table::keys::KeyBuilder;
+///
+/// let key = KeyBuilder::new()
+///     .segment("model", "a#b")
+///     .segment("submodel", "c")
+///     .build();
+/// assert_eq!(key, "model#a%23b#submodel#c");
+/// ```
+///
+/// Labels are expected to be constants, and must not contain the delimiter.
+#[derive(Clone, Debug, Default)]
+pub struct KeyBuilder {
+    key: String,
+}
+
+impl KeyBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn segment(mut self, label: &str, value: &str) -> Self {
+        debug_assert!(!label.contains(DELIMITER), "label contains a delimiter");
+
+        if !self.key.is_empty() {
+            self.key.push(DELIMITER);
+        }
+        self.key.push_str(label);
+        self.key.push(DELIMITER);
+        self.key.push_str(&escape(value));
+        self
+    }
+
+    /// A segment whose value sorts in the same order as `value`, see `SortKey`.
+    pub fn sortable<T: SortKey>(self, label: &str, value: &T) -> Self {
+        self.segment(label, &value.encode())
+    }
+
+    pub fn build(self) -> String {
+        self.key
+    }
+
+    /// A `begins_with` prefix matching the keys of all segments nested under this one,
+    /// but not this key itself.
+    pub fn prefix(self) -> String {
+        let mut key = self.key;
+        key.push(DELIMITER);
+        key
+    }
+
+    /// A `begins_with` prefix matching the keys of every segment labelled `label`,
+    /// nested under this one.
+    pub fn label_prefix(self, label: &str) -> String {
+        let mut key = self.key;
+        if !key.is_empty() {
+            key.push(DELIMITER);
+        }
+        key.push_str(label);
+        key.push(DELIMITER);
+        key
+    }
+}
+
+/// Reads the segments of a key written by a `KeyBuilder`, unescaping their values.
+pub struct KeyParser<'a> {
+    key: &'a str,
+    parts: Split<'a, char>,
+}
+
+impl<'a> KeyParser<'a> {
+    pub fn new(key: &'a str) -> Self {
+        Self {
+            key,
+            parts: key.split(DELIMITER),
+        }
+    }
+
+    /// Parse the next segment, which must be labelled `label`, returning its value.
+    pub fn segment(&mut self, label: &str) -> Result<String, KeyError> {
+        match self.next() {
+            Some(Ok(segment)) if segment.label == label => Ok(segment.value),
+            Some(Err(e)) => Err(e),
+            _ => Err(KeyError::UnexpectedLabel {
+                expected: label.to_string(),
+                key: self.key.to_string(),
+            }),
+        }
+    }
+
+    /// Parse the next segment, which must be labelled `label`, decoding a sortable value.
+    pub fn sortable<T: SortKey>(&mut self, label: &str) -> Result<T, KeyError> {
+        T::decode(&self.segment(label)?)
+    }
+
+    /// Check that every segment of the key has been parsed.
+    pub fn finish(mut self) -> Result<(), KeyError> {
+        match self.parts.next() {
+            None => Ok(()),
+            Some(_) => Err(KeyError::TrailingSegments(self.key.to_string())),
+        }
+    }
+}
+
+impl<'a> Iterator for KeyParser<'a> {
+    type Item = Result<Segment, KeyError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let label = self.parts.next()?;
+        let value = match self.parts.next() {
+            Some(value) => value,
+            None => return Some(Err(KeyError::MissingValue(self.key.to_string()))),
+        };
+
+        Some(unescape(value).map(|value| Segment {
+            label: label.to_string(),
+            value,
+        }))
+    }
+}
+
+/// Parse every segment of a key.
+pub fn segments(key: &str) -> Result<Vec<Segment>, KeyError> {
+    KeyParser::new(key).collect()
+}
+
+// Escaping is done a character at a time, so that escaped prefixes
+// remain prefixes of the escaped values for `begins_with` queries.
+fn escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '%' => escaped.push_str("%25"),
+            DELIMITER => escaped.push_str("%23"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn unescape(value: &str) -> Result<String, KeyError> {
+    let mut unescaped = String::with_capacity(value.len());
+    let mut rest = value;
+
+    while let Some(i) = rest.find('%') {
+        unescaped.push_str(&rest[..i]);
+        let sequence = rest.get(i..i + 3);
+        match sequence {
+            Some("%25") => unescaped.push('%'),
+            Some("%23") => unescaped.push(DELIMITER),
+            _ => return Err(KeyError::InvalidEscape(value.to_string())),
+        }
+        rest = &rest[i + 3..];
+    }
+    unescaped.push_str(rest);
+
+    Ok(unescaped)
+}
@@ -1,24 +1,64 @@
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, TimeZone, Utc};
 use serde::{Deserialize, Serialize};
 
 use std::error::Error;
 
 pub mod args;
+pub mod capacity;
+pub mod clock;
+pub mod collection;
 pub mod ddb;
 pub mod env;
+pub mod events;
+pub mod history;
+pub mod keys;
 pub mod mem;
+pub mod migrate;
+pub mod record;
+pub mod registry;
+pub mod schema;
+pub mod shard;
+pub mod sortable;
+pub mod stream;
 pub mod sts;
 pub mod traits;
+pub mod transaction;
 pub mod types;
+pub mod unique;
+pub mod validation;
 
+pub use clock::Clock;
+pub use collection::ItemCollection;
 pub use ddb::DDB;
+pub use keys::{KeyBuilder, KeyParser};
+pub use registry::{Item, Registry};
 pub use sts::STS;
-pub use traits::{Database, SecurityTokens};
+pub use traits::{Child, Database, Entity, SecurityTokens, Transactable, TransactionalOperations};
+
+use traits::{QueryOptions, ReadOptions};
+pub use transaction::Transaction;
+pub use unique::UniqueConflict;
+pub use validation::ValidationError;
+
+/// The attribute used for DynamoDB Time to Live, holding seconds since the epoch.
+pub const TTL_ATTRIBUTE: &str = "expires_at";
+
+/// The unique attribute of a Model, claimed with a `unique::Guard`.
+pub const EMAIL_ATTRIBUTE: &str = "email";
+
+/// The attribute of a Model counting its SubModels, see `SaveOptions::update_parent_count`.
+pub const SUBMODEL_COUNT_ATTRIBUTE: &str = "submodel_count";
 
 #[derive(thiserror::Error, Debug)]
 enum ProgramError {
     #[error("item not found: {0}")]
     GetNone(String),
+    #[error("model has submodels, delete them first or cascade: {0}")]
+    HasChildren(String),
+    #[error("model has history, soft delete it instead: {0}")]
+    HasHistory(String),
+    #[error("no revision to restore: {0}")]
+    NoRevision(String),
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -27,6 +67,29 @@ pub struct Model {
     a_number: i32,
 
     created_at: DateTime<Utc>,
+    updated_at: DateTime<Utc>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    expires_at: Option<i64>,
+
+    // Unique across all Models
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    email: Option<String>,
+
+    // The email as last read from, or written to, the table
+    #[serde(skip)]
+    stored_email: Option<String>,
+
+    // Maintained by `SubModel::save_with`, and left as it is when the Model is saved
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    submodel_count: Option<i64>,
+
+    // The latest revision, when the Model is saved with history
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    revision: Option<u64>,
+
+    // Always the current version once decoded, see `Model::SCHEMA_VERSION`
+    schema_version: u32,
 
     // These are used as dynamodb key attributes
     pk: String,
@@ -34,55 +97,519 @@ pub struct Model {
     model: String,
 }
 
+// Each upgrades a Model item from one schema version to the next
+const MODEL_UPCASTERS: &[schema::Upcaster] = &[model_v1_to_v2];
+
+// Version 2 added `updated_at`, which starts out as `created_at`
+fn model_v1_to_v2(mut item: types::HashMap) -> types::HashMap {
+    if let Some(created_at) = item.get("created_at").cloned() {
+        item.entry("updated_at".to_string()).or_insert(created_at);
+    }
+    item
+}
+
 impl Model {
-    pub fn new<S>(name: S, a_number: i32) -> Self
+    /// The schema version written with every Model, older items are upcast when read.
+    pub const SCHEMA_VERSION: u32 = schema::current(MODEL_UPCASTERS);
+
+    pub fn new<S>(name: S, a_number: i32) -> Result<Self, ValidationError>
+    where
+        S: Into<String>,
+    {
+        Self::new_with_clock(name, a_number, &clock::SystemClock)
+    }
+
+    /// Create a Model, taking `created_at` from `clock`.
+    ///
+    /// The name must not be empty, and must fit within DynamoDB's key size limits.
+    pub fn new_with_clock<S>(
+        name: S,
+        a_number: i32,
+        clock: &dyn Clock,
+    ) -> Result<Self, ValidationError>
     where
         S: Into<String>,
     {
         let name = name.into();
-        Self {
-            pk: format!("model#{}", name.clone()),
-            sk: format!("model#{}", name.clone()),
-            model: "model".to_string(),
+        validation::validate_name(Self::MODEL, &name)?;
+
+        let key = Self::key_of(&name).build();
+        validation::validate_key(&key, &key)?;
+
+        let now = clock.now();
+
+        Ok(Self {
+            pk: key.clone(),
+            sk: key,
+            model: Self::MODEL.to_string(),
+            schema_version: Self::SCHEMA_VERSION,
 
             name: name,
-            created_at: Utc::now(),
+            created_at: now,
+            updated_at: now,
+            expires_at: None,
+            email: None,
+            stored_email: None,
+            submodel_count: None,
+            revision: None,
             a_number,
-        }
+        })
+    }
+
+    /// Have DynamoDB delete this Model after `expires_at`, once TTL is enabled on the table.
+    pub fn expire_at(mut self, expires_at: DateTime<Utc>) -> Self {
+        self.expires_at = Some(expires_at.timestamp());
+        self
+    }
+
+    /// Give the Model an email address, which no other Model may have.
+    ///
+    /// To change or remove the email of a saved Model, `get` it first,
+    /// so that the guard claiming its current email is released when it is saved.
+    pub fn with_email<S>(mut self, email: S) -> Result<Self, ValidationError>
+    where
+        S: Into<String>,
+    {
+        let email = email.into();
+        unique::validate_value(EMAIL_ATTRIBUTE, &email)?;
+
+        self.email = Some(email);
+        Ok(self)
+    }
+
+    /// Remove the Model's email, releasing it when the Model is saved.
+    pub fn without_email(mut self) -> Self {
+        self.email = None;
+        self
     }
 
+    /// The key of the Model called `name`, both its `pk` and `sk`.
+    pub fn key_of(name: &str) -> KeyBuilder {
+        KeyBuilder::new().segment(Self::MODEL, name)
+    }
+
+    /// Decode a Model, upcasting items written with older versions of its schema.
     pub fn from_hashmap(hashmap: &types::HashMap) -> serde_dynamodb::error::Result<Self> {
-        serde_dynamodb::from_hashmap(hashmap.to_owned())
+        let hashmap = schema::upcast(hashmap, MODEL_UPCASTERS)?;
+        let mut model: Self = serde_dynamodb::from_hashmap(hashmap)?;
+        model.stored_email = model.email.clone();
+        Ok(model)
     }
 
     pub fn to_hashmap(&self) -> serde_dynamodb::error::Result<types::HashMap> {
         serde_dynamodb::to_hashmap(&self)
     }
 
+    // Every attribute is written but the count of SubModels, which only `add` changes,
+    // so that saving the Model neither resets it nor loses an increment made meanwhile
+    fn write(
+        &self,
+        db: &impl TransactionalOperations,
+    ) -> serde_dynamodb::error::Result<types::TransactWriteItem> {
+        let mut hashmap = self.to_hashmap()?;
+        hashmap.remove(SUBMODEL_COUNT_ATTRIBUTE);
+
+        // Optional attributes the Model no longer has
+        let optional = [TTL_ATTRIBUTE, EMAIL_ATTRIBUTE, history::REVISION_ATTRIBUTE];
+        Ok(db.update_attributes(hashmap, &optional))
+    }
+
+    fn constraints(&self) -> [unique::Constraint; 1] {
+        [unique::Constraint {
+            attribute: EMAIL_ATTRIBUTE,
+            stored: self.stored_email.as_deref(),
+            value: self.email.as_deref(),
+        }]
+    }
+
     pub async fn get<S>(db: &impl Database, name: S) -> Result<Self, Box<dyn Error>>
     where
         S: Into<String>,
     {
         let name = name.into();
-        let pk = format!("model#{}", name);
+        match Self::find(db, &name).await? {
+            Some(model) => Ok(model),
+            None => Err(Box::new(ProgramError::GetNone(name))),
+        }
+    }
+
+    /// Get the Model called `name`, if there is one.
+    pub async fn find(db: &impl Database, name: &str) -> Result<Option<Self>, Box<dyn Error>> {
+        let pk = Self::key_of(name).build();
         let sk = pk.clone();
 
         let res = db.get_item(pk, sk).await?;
-        if let Some(hashmap) = res.item {
-            return Ok(Self::from_hashmap(&hashmap)?);
+        match res.item {
+            Some(hashmap) => Ok(Some(Self::from_hashmap(&hashmap)?)),
+            None => Ok(None),
         }
+    }
 
-        Err(Box::new(ProgramError::GetNone(name)))
+    /// Get the Model called `name`, if there is one, with `options` such as `consistent_read`.
+    pub async fn find_with(
+        db: &impl Database,
+        name: &str,
+        options: ReadOptions,
+    ) -> Result<Option<Self>, Box<dyn Error>> {
+        let pk = Self::key_of(name).build();
+        let sk = pk.clone();
+
+        let res = db.get_item_with(pk, sk, options).await?;
+        match res.item {
+            Some(hashmap) => Ok(Some(Self::from_hashmap(&hashmap)?)),
+            None => Ok(None),
+        }
     }
 
-    pub async fn save(&mut self, db: &impl Database) -> Result<(), Box<dyn Error>>
+    // Deletes act on the Model as of the latest write, rather than a copy that may be behind
+    async fn get_latest<S>(db: &impl Database, name: S) -> Result<Self, Box<dyn Error>>
+    where
+        S: Into<String>,
     {
-        let hashmap = self.to_hashmap()?;
-        let _ = db.put_item(hashmap).await?;
+        let name = name.into();
+        match Self::find_with(db, &name, CONSISTENT).await? {
+            Some(model) => Ok(model),
+            None => Err(Box::new(ProgramError::GetNone(name))),
+        }
+    }
+
+    /// Take the place of the `existing` Model of the same name, when saving a new one over it.
+    ///
+    /// The guard of its email is released, and its history continues.
+    pub fn replacing(mut self, existing: &Model) -> Self {
+        self.stored_email = existing.email.clone();
+        self.revision = existing.revision;
+        self
+    }
 
+    /// Save the Model, claiming its email and releasing the one it replaces.
+    ///
+    /// Fails with a `UniqueConflict` if another Model has the same email. Also fails if the
+    /// stored Model has a different email than it was read with, or has an email at all
+    /// when this is a new Model, unless it is `replacing` the stored one.
+    pub async fn save(&mut self, db: &impl Database) -> Result<(), Box<dyn Error>> {
+        let write = self.write(db)?;
+        let key = traits::Key::key(self);
+        match unique::Save::new(db, key, &self.constraints(), write.clone())? {
+            Some(save) => {
+                save.run(db).await?;
+            }
+            None => {
+                let write = unique::if_unchanged(&self.constraints(), write);
+                db.transact_write_items(vec![write]).await?;
+            }
+        }
+
+        self.stored_email = self.email.clone();
         Ok(())
     }
 
+    /// Save the Model, and an immutable copy of it as the next revision in its history,
+    /// in a single transaction. Returns the number of the new revision.
+    ///
+    /// Fails if the Model was saved with history since it was read.
+    /// Once a Model has history, it should only be saved with history.
+    pub async fn save_with_history(
+        &mut self,
+        db: &impl Database,
+        audit: &history::Audit,
+    ) -> Result<u64, Box<dyn Error>> {
+        let expected = self.revision;
+        let revision = match expected {
+            Some(revision) => revision + 1,
+            // A new Model continues the history of a deleted one with the same name
+            None => history::Revision::latest(db, &self.name).await? + 1,
+        };
+
+        let mut saved = self.clone();
+        saved.revision = Some(revision);
+        let copy = history::Revision::to_hashmap(&saved, revision, audit, false)?;
+
+        let write = history::at_revision(saved.write(db)?, expected);
+        let key = traits::Key::key(self);
+        match unique::Save::new(db, key, &self.constraints(), write.clone())? {
+            Some(mut save) => {
+                save.transact_items.push(db.put_new(copy));
+                save.run(db).await?;
+            }
+            None => {
+                let write = unique::if_unchanged(&self.constraints(), write);
+                db.transact_write_items(vec![write, db.put_new(copy)])
+                    .await?;
+            }
+        }
+
+        self.revision = Some(revision);
+        self.stored_email = self.email.clone();
+        Ok(revision)
+    }
+
+    /// Get a Model together with all of its SubModels, using a single query,
+    /// plus one per shard when SubModels are sharded.
+    pub async fn get_with_children<S>(
+        db: &impl Database,
+        name: S,
+    ) -> Result<(Self, Vec<SubModel>), Box<dyn Error>>
+    where
+        S: Into<String>,
+    {
+        Self::get_with(db, name).await
+    }
+
+    /// Get a Model together with all of its children of type `C`, see `get_with_children`.
+    pub async fn get_with<C, S>(
+        db: &impl Database,
+        name: S,
+    ) -> Result<(Self, Vec<C>), Box<dyn Error>>
+    where
+        C: Child,
+        S: Into<String>,
+    {
+        let name = name.into();
+        let collection = ItemCollection::query(db, Self::key_of(&name).build()).await?;
+
+        let model = match collection.entity::<Self>()? {
+            Some(model) => model,
+            None => return Err(Box::new(ProgramError::GetNone(name))),
+        };
+
+        // Sharded children live outside the Model's own partition
+        let children = if C::SHARDS > 1 {
+            children_of(db, &name).await?
+        } else {
+            collection.entities::<C>()?
+        };
+
+        Ok((model, children))
+    }
+
+    /// Get a Model together with the SubModels called `names`, as a consistent snapshot
+    /// read with a single `TransactGetItems`.
+    ///
+    /// There is one entry for each name, in the same order, which is `None` if missing.
+    pub async fn get_snapshot<S>(
+        db: &impl Database,
+        name: S,
+        names: &[String],
+    ) -> Result<(Self, Vec<Option<SubModel>>), Box<dyn Error>>
+    where
+        S: Into<String>,
+    {
+        Self::get_snapshot_with(db, name, names).await
+    }
+
+    /// Get a Model together with its children of type `C` called `names`, see `get_snapshot`.
+    pub async fn get_snapshot_with<C, S>(
+        db: &impl Database,
+        name: S,
+        names: &[String],
+    ) -> Result<(Self, Vec<Option<C>>), Box<dyn Error>>
+    where
+        C: Child,
+        S: Into<String>,
+    {
+        let name = name.into();
+        let pk = Self::key_of(&name).build();
+
+        let mut keys = vec![(pk.clone(), pk)];
+        for child in names {
+            let sk = C::key_of(child).build();
+            let pk = shard::key(Self::key_of(&name), &sk, C::SHARDS);
+            keys.push((pk, sk));
+        }
+
+        let res = db.transact_get_items(keys).await?;
+        let mut items = res
+            .responses
+            .unwrap_or_default()
+            .into_iter()
+            .map(|response| response.item);
+
+        let model = match items.next().flatten() {
+            Some(hashmap) => Self::from_hashmap(&hashmap)?,
+            None => return Err(Box::new(ProgramError::GetNone(name))),
+        };
+
+        let mut children = vec![];
+        for item in items {
+            children.push(match item {
+                Some(hashmap) => Some(C::from_hashmap(&hashmap)?),
+                None => None,
+            });
+        }
+
+        Ok((model, children))
+    }
+
+    /// Delete a Model that has no SubModels.
+    ///
+    /// The SubModels are looked for before the delete, rather than as a condition of it,
+    /// since a condition can only check the Model's own item. A SubModel saved after the
+    /// check but before the delete is orphaned, and is left without a parent.
+    ///
+    /// The Edges leaving and pointing at the Model, and its event log, are deleted along with it.
+    ///
+    /// A Model with history can only be deleted with `soft_delete`, which keeps it.
+    pub async fn delete<S>(db: &impl Database, name: S) -> Result<(), Box<dyn Error>>
+    where
+        S: Into<String>,
+    {
+        let model = Self::get_latest(db, name).await?;
+        model.check_no_children(db).await?;
+        model.check_no_history(db).await?;
+
+        let mut keys = Edge::keys_touching(db, model.name()).await?;
+        keys.extend(events::Event::keys_of(db, model.name()).await?);
+        delete_before(db, keys, model.deletion(db)).await?;
+        Ok(())
+    }
+
+    /// Delete a Model that has no SubModels, keeping its history,
+    /// which ends with a tombstone revision. Returns the number of the tombstone.
+    ///
+    /// The Model can be brought back with `restore`. Its Edges and event log are kept,
+    /// and belong to it again once it is restored.
+    pub async fn soft_delete<S>(
+        db: &impl Database,
+        name: S,
+        audit: &history::Audit,
+    ) -> Result<u64, Box<dyn Error>>
+    where
+        S: Into<String>,
+    {
+        let model = Self::get_latest(db, name).await?;
+        model.check_no_children(db).await?;
+
+        let revision = match model.revision {
+            Some(revision) => revision + 1,
+            None => history::Revision::latest(db, &model.name).await? + 1,
+        };
+        let mut tombstone = model.clone();
+        tombstone.revision = Some(revision);
+        let tombstone = history::Revision::to_hashmap(&tombstone, revision, audit, true)?;
+
+        let mut transact_items = unique::release(db, &model.constraints());
+        transact_items.push(history::delete_at_revision(db, &model, model.revision));
+        transact_items.push(db.put_new(tombstone));
+
+        db.transact_write_items(transact_items).await?;
+        Ok(revision)
+    }
+
+    /// Bring back the Model called `name` as it was at `revision`,
+    /// or by default as it was before it was last deleted.
+    ///
+    /// The restored Model is saved with history, as a new revision.
+    /// Revisions do not keep `expires_at`, so a restored Model does not expire.
+    pub async fn restore<S>(
+        db: &impl Database,
+        name: S,
+        revision: Option<u64>,
+        audit: &history::Audit,
+    ) -> Result<Self, Box<dyn Error>>
+    where
+        S: Into<String>,
+    {
+        let name = name.into();
+        let found = history::Revision::history(db, &name)
+            .await?
+            .into_iter()
+            .rev()
+            .find(|found| match revision {
+                Some(revision) => found.revision() == revision,
+                None => !found.is_deleted(),
+            });
+        let found = match found {
+            Some(found) if !found.is_deleted() => found,
+            _ => return Err(Box::new(ProgramError::NoRevision(name))),
+        };
+
+        let mut model = found.into_model();
+        model.revision = None;
+        model.stored_email = None;
+        if let Some(current) = Self::find(db, &name).await? {
+            model = model.replacing(&current);
+        }
+        model.updated_at = audit.changed_at();
+
+        model.save_with_history(db, audit).await?;
+        Ok(model)
+    }
+
+    // The Model is deleted together with the guards of its unique values
+    fn deletion(&self, db: &impl Database) -> Vec<types::TransactWriteItem> {
+        let mut transact_items = unique::release(db, &self.constraints());
+        transact_items.push(db.delete(self.pk(), self.sk()));
+        transact_items
+    }
+
+    // Models with SubModels can only be deleted by a cascade.
+    // This is a separate read, so it races with SubModels being saved, see `delete`.
+    async fn check_no_children(&self, db: &impl Database) -> Result<(), Box<dyn Error>> {
+        let partitions = SubModel::partitions(self.name());
+
+        let children = shard::query_with(db, partitions, SubModel::prefix(), consistent()).await?;
+        if children.count.unwrap_or_default() > 0 {
+            return Err(Box::new(ProgramError::HasChildren(self.name.clone())));
+        }
+        Ok(())
+    }
+
+    // Models with history can only be soft deleted, which keeps their revisions.
+    // Like `check_no_children`, this races with saves with history.
+    async fn check_no_history(&self, db: &impl Database) -> Result<(), Box<dyn Error>> {
+        if history::Revision::latest(db, &self.name).await? > 0 {
+            return Err(Box::new(ProgramError::HasHistory(self.name.clone())));
+        }
+        Ok(())
+    }
+
+    /// Delete a Model and all of its SubModels, returning the number of items deleted.
+    ///
+    /// When the Model and its SubModels fit in a single transaction
+    /// (`traits::MAX_TRANSACTION_ITEMS`), they are deleted atomically.
+    ///
+    /// Otherwise the SubModels are deleted in chunks, one transaction per chunk,
+    /// followed by the Model itself. This is not atomic: if it is interrupted,
+    /// the Model remains with some of its SubModels, and it is safe to try again.
+    /// SubModels saved while a cascade is in progress may be orphaned.
+    ///
+    /// The Edges leaving and pointing at the Model, and its event log, are deleted along with
+    /// the SubModels. A Model with history cannot be deleted this way, see `delete`.
+    pub async fn delete_cascade<S>(db: &impl Database, name: S) -> Result<usize, Box<dyn Error>>
+    where
+        S: Into<String>,
+    {
+        Self::delete_cascade_with::<SubModel, _>(db, name).await
+    }
+
+    /// Delete a Model and all of its children of type `C`, see `delete_cascade`.
+    pub async fn delete_cascade_with<C, S>(
+        db: &impl Database,
+        name: S,
+    ) -> Result<usize, Box<dyn Error>>
+    where
+        C: Child,
+        S: Into<String>,
+    {
+        let model = Self::get_latest(db, name).await?;
+        model.check_no_history(db).await?;
+        let mut related = Edge::keys_touching(db, model.name()).await?;
+        related.extend(events::Event::keys_of(db, model.name()).await?);
+
+        let partitions = partitions_of::<C>(model.name());
+        let res = shard::query_with(db, partitions, C::prefix(), consistent()).await?;
+        let mut keys: Vec<(String, String)> = res
+            .items
+            .unwrap_or_default()
+            .iter()
+            .map(traits::Key::key)
+            .collect();
+        keys.extend(related);
+
+        delete_before(db, keys, model.deletion(db)).await
+    }
+
     pub fn name(&self) -> &str {
         &self.name
     }
@@ -91,6 +618,41 @@ impl Model {
         self.a_number
     }
 
+    /// The number of SubModels saved with `SaveOptions::update_parent_count`.
+    pub fn submodel_count(&self) -> i64 {
+        self.submodel_count.unwrap_or_default()
+    }
+
+    pub fn email(&self) -> Option<&str> {
+        self.email.as_deref()
+    }
+
+    pub fn expires_at(&self) -> Option<DateTime<Utc>> {
+        self.expires_at.map(|ts| Utc.timestamp(ts, 0))
+    }
+
+    pub fn created_at(&self) -> DateTime<Utc> {
+        self.created_at
+    }
+
+    pub fn updated_at(&self) -> DateTime<Utc> {
+        self.updated_at
+    }
+
+    /// Record that the Model has changed, taking `updated_at` from `clock`.
+    pub fn touch(&mut self, clock: &dyn Clock) {
+        self.updated_at = clock.now();
+    }
+
+    /// The revision the Model was last saved as with history, if it has any.
+    pub fn revision(&self) -> Option<u64> {
+        self.revision
+    }
+
+    pub fn schema_version(&self) -> u32 {
+        self.schema_version
+    }
+
     pub fn pk(&self) -> String {
         self.pk.clone()
     }
@@ -104,6 +666,100 @@ impl Model {
     }
 }
 
+const CONSISTENT: ReadOptions = ReadOptions {
+    consistent_read: true,
+};
+
+// Queries made before deleting, so that they see items saved a moment ago
+fn consistent() -> QueryOptions {
+    QueryOptions {
+        consistent_read: true,
+        ..Default::default()
+    }
+}
+
+// The partitions holding the children of type `C` of the Model called `parent`, one per shard
+fn partitions_of<C: Child>(parent: &str) -> Vec<String> {
+    shard::partitions(Model::key_of(parent), C::SHARDS)
+}
+
+// Every child of type `C` of the Model called `parent`, in sort key order
+async fn children_of<C: Child>(db: &impl Database, parent: &str) -> Result<Vec<C>, Box<dyn Error>> {
+    let res = shard::query(db, partitions_of::<C>(parent), C::prefix()).await?;
+    Ok(ItemCollection::new(res.items.unwrap_or_default()).entities()?)
+}
+
+// Delete the items at `keys`, and then apply `last`, returning the number of items deleted.
+//
+// This is a single transaction when everything fits in one. Otherwise the items at `keys`
+// are deleted in chunks first, so that `last` is only applied once they are all gone.
+async fn delete_before(
+    db: &impl Database,
+    keys: Vec<(String, String)>,
+    mut last: Vec<types::TransactWriteItem>,
+) -> Result<usize, Box<dyn Error>> {
+    let mut deleted = 0;
+
+    if keys.len() + last.len() > traits::MAX_TRANSACTION_ITEMS {
+        for chunk in keys.chunks(traits::MAX_TRANSACTION_ITEMS) {
+            let transact_items = chunk.iter().map(|(pk, sk)| db.delete(pk, sk)).collect();
+            db.transact_write_items(transact_items).await?;
+            deleted += chunk.len();
+        }
+
+        deleted += last.len();
+        db.transact_write_items(last).await?;
+        return Ok(deleted);
+    }
+
+    let mut transact_items: Vec<types::TransactWriteItem> =
+        keys.iter().map(|(pk, sk)| db.delete(pk, sk)).collect();
+    transact_items.append(&mut last);
+
+    deleted += transact_items.len();
+    db.transact_write_items(transact_items).await?;
+    Ok(deleted)
+}
+
+impl Entity for Model {
+    const MODEL: &'static str = "model";
+
+    fn from_hashmap(hashmap: &types::HashMap) -> serde_dynamodb::error::Result<Self> {
+        Self::from_hashmap(hashmap)
+    }
+}
+
+impl traits::Transactable for Model {
+    // Written as `save` does, keeping its SubModel count
+    fn put(
+        &self,
+        db: &impl TransactionalOperations,
+    ) -> serde_dynamodb::error::Result<types::TransactWriteItem> {
+        self.write(db)
+    }
+
+    // Updated as `save` does, keeping its SubModel count
+    fn update(
+        &self,
+        db: &impl TransactionalOperations,
+    ) -> serde_dynamodb::error::Result<types::TransactWriteItem> {
+        self.write(db)
+    }
+
+    // Claiming its email and releasing the one it replaces, as `save` does
+    fn transact_items(
+        &self,
+        db: &impl TransactionalOperations,
+        write: types::TransactWriteItem,
+    ) -> serde_dynamodb::error::Result<Vec<types::TransactWriteItem>> {
+        let key = traits::Key::key(self);
+        match unique::Save::new(db, key, &self.constraints(), write.clone())? {
+            Some(save) => Ok(save.transact_items),
+            None => Ok(vec![unique::if_unchanged(&self.constraints(), write)]),
+        }
+    }
+}
+
 impl traits::Key for Model {
     fn key(&self) -> (String, String) {
         (self.pk(), self.sk())
@@ -114,6 +770,21 @@ impl traits::Key for Model {
     }
 }
 
+/// Options for `SubModel::save_with`.
+#[derive(Clone, Debug, Default)]
+pub struct SaveOptions {
+    /// Add one to the parent's `submodel_count`, in the same transaction as the put.
+    ///
+    /// The SubModel must not exist yet, so that it is only ever counted once.
+    pub update_parent_count: bool,
+
+    /// Save idempotently: retrying the save of the same SubModel with the same key,
+    /// within ten minutes, succeeds without saving it again.
+    ///
+    /// Saving a different SubModel, or the same one with other options, with the key fails.
+    pub idempotency_key: Option<String>,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct SubModel {
     name: String,
@@ -121,26 +792,85 @@ pub struct SubModel {
 
     created_at: DateTime<Utc>,
 
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    expires_at: Option<i64>,
+
     pk: String,
     sk: String,
     model: String,
 }
 
 impl SubModel {
-    pub fn new<'a, S>(name: S, parent: Model) -> Self
+    pub fn new<'a, S>(name: S, parent: Model) -> Result<Self, ValidationError>
+    where
+        S: Into<String>,
+    {
+        Self::new_with_clock(name, parent, &clock::SystemClock)
+    }
+
+    /// Create a SubModel, taking `created_at` from `clock`.
+    ///
+    /// The name must not be empty, and must fit within DynamoDB's key size limits.
+    pub fn new_with_clock<S>(
+        name: S,
+        parent: Model,
+        clock: &dyn Clock,
+    ) -> Result<Self, ValidationError>
     where
         S: Into<String>,
     {
         let name = name.into();
-        Self {
-            pk: format!("model#{}", parent.name()),
-            sk: format!("model#{}#submodel#{}", parent.name(), &name),
-            model: "submodel".to_string(),
+        validation::validate_name(Self::MODEL, &name)?;
+
+        let sk = Self::key_of(&name).build();
+        let pk = shard::key(Model::key_of(parent.name()), &sk, Self::SHARDS);
+        validation::validate_key(&pk, &sk)?;
+
+        Ok(Self {
+            pk,
+            sk,
+            model: Self::MODEL.to_string(),
 
             name: name,
             parent: parent.sk(),
-            created_at: Utc::now(),
-        }
+            created_at: clock.now(),
+            expires_at: None,
+        })
+    }
+
+    /// Have DynamoDB delete this SubModel after `expires_at`, once TTL is enabled on the table.
+    pub fn expire_at(mut self, expires_at: DateTime<Utc>) -> Self {
+        self.expires_at = Some(expires_at.timestamp());
+        self
+    }
+
+    /// The `sk` of the SubModel called `name`, within the partition of its parent.
+    ///
+    /// This does not include the parent, so that the `inverted` index
+    /// collects every SubModel with the same name.
+    pub fn key_of(name: &str) -> KeyBuilder {
+        KeyBuilder::new().segment(Self::MODEL, name)
+    }
+
+    /// A `begins_with` prefix of the `sk` of every SubModel in a partition.
+    pub fn prefix() -> String {
+        KeyBuilder::new().label_prefix(Self::MODEL)
+    }
+
+    /// The partitions holding the SubModels of the Model called `parent`, one per shard.
+    pub fn partitions(parent: &str) -> Vec<String> {
+        partitions_of::<Self>(parent)
+    }
+
+    /// Every SubModel of the Model called `parent`, in sort key order.
+    ///
+    /// When SubModels are sharded, every shard is queried.
+    pub async fn children_of<S>(db: &impl Database, parent: S) -> Result<Vec<Self>, Box<dyn Error>>
+    where
+        S: Into<String>,
+    {
+        let parent = parent.into();
+        children_of(db, &parent).await
     }
 
     pub fn from_hashmap(hashmap: &types::HashMap) -> serde_dynamodb::error::Result<Self> {
@@ -157,8 +887,8 @@ impl SubModel {
     {
         let parent = parent.into();
         let name = name.into();
-        let pk = format!("model#{}", parent);
-        let sk = format!("model#{}#submodel#{}", parent, name);
+        let sk = Self::key_of(&name).build();
+        let pk = shard::key(Model::key_of(&parent), &sk, Self::SHARDS);
 
         let res = db.get_item(pk, sk).await?;
         if let Some(hashmap) = res.item {
@@ -168,16 +898,71 @@ impl SubModel {
         Err(Box::new(ProgramError::GetNone(name)))
     }
 
+    /// Every SubModel called `name`, whatever its parent, using the `inverted` index.
+    ///
+    /// SubModels are returned in the order of their parents' keys.
+    pub async fn all_named<S>(db: &impl Database, name: S) -> Result<Vec<Self>, Box<dyn Error>>
+    where
+        S: Into<String>,
+    {
+        let sk = Self::key_of(&name.into()).build();
+
+        let res = db
+            .query(Some("inverted".to_string()), sk, String::new())
+            .await?;
+        Ok(ItemCollection::new(res.items.unwrap_or_default()).entities()?)
+    }
+
+    /// The names of the Models that have a SubModel called `name`.
+    pub async fn parents_of<S>(db: &impl Database, name: S) -> Result<Vec<String>, Box<dyn Error>>
+    where
+        S: Into<String>,
+    {
+        let mut parents = vec![];
+        for submodel in Self::all_named(db, name).await? {
+            parents.push(submodel.parent_name()?);
+        }
+
+        Ok(parents)
+    }
+
     pub async fn save(&mut self, db: &impl Database) -> Result<(), Box<dyn Error>> {
+        self.save_with(db, SaveOptions::default()).await
+    }
+
+    /// Save the SubModel, in a transaction that checks its parent exists.
+    pub async fn save_with(
+        &mut self,
+        db: &impl Database,
+        options: SaveOptions,
+    ) -> Result<(), Box<dyn Error>> {
         let hashmap = self.to_hashmap()?;
-        let res = db
-            .transact_write_items(vec![
+
+        // Updating the count also checks the parent, a transaction
+        // may only include one operation on each item
+        let transact_items = if options.update_parent_count {
+            vec![
+                db.add_to_existing(
+                    &self.parent,
+                    &self.parent,
+                    Model::MODEL,
+                    SUBMODEL_COUNT_ATTRIBUTE,
+                    1,
+                ),
+                db.put_new(hashmap),
+            ]
+        } else {
+            vec![
                 db.condition_check_exists(&self.parent, &self.parent, "model"),
                 db.put(hashmap),
-            ])
-            .await?;
+            ]
+        };
 
-        println!("{:?}", res);
+        let transact_options = traits::TransactOptions {
+            client_request_token: options.idempotency_key,
+        };
+        db.transact_write_items_with(transact_items, transact_options)
+            .await?;
         Ok(())
     }
 
@@ -185,6 +970,19 @@ impl SubModel {
         self.name.clone()
     }
 
+    /// The name of the Model this SubModel belongs to.
+    pub fn parent_name(&self) -> Result<String, keys::KeyError> {
+        let mut parser = KeyParser::new(&self.parent);
+        let name = parser.segment(Model::MODEL)?;
+        parser.finish()?;
+
+        Ok(name)
+    }
+
+    pub fn expires_at(&self) -> Option<DateTime<Utc>> {
+        self.expires_at.map(|ts| Utc.timestamp(ts, 0))
+    }
+
     pub fn pk(&self) -> String {
         self.pk.clone()
     }
@@ -198,6 +996,30 @@ impl SubModel {
     }
 }
 
+impl Entity for SubModel {
+    const MODEL: &'static str = "submodel";
+
+    // Raise this to spread the SubModels of popular Models across partitions,
+    // existing SubModels must then be moved to their shards
+    const SHARDS: u32 = 1;
+
+    fn from_hashmap(hashmap: &types::HashMap) -> serde_dynamodb::error::Result<Self> {
+        Self::from_hashmap(hashmap)
+    }
+}
+
+impl traits::Transactable for SubModel {}
+
+impl Child for SubModel {
+    fn key_of(name: &str) -> KeyBuilder {
+        Self::key_of(name)
+    }
+
+    fn prefix() -> String {
+        Self::prefix()
+    }
+}
+
 impl traits::Key for SubModel {
     fn key(&self) -> (String, String) {
         (self.pk(), self.sk())
@@ -207,3 +1029,197 @@ impl traits::Key for SubModel {
         (self.model(), self.sk())
     }
 }
+
+/// A directed relationship from one Model to another.
+///
+/// Edges are stored in the partition of their `source` Model, with the sort key
+/// `edge#{target}`. Following them in reverse uses the `inverted` index, where the
+/// sort key becomes the partition key. This is the adjacency list pattern.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Edge {
+    source: String,
+    target: String,
+
+    created_at: DateTime<Utc>,
+
+    pk: String,
+    sk: String,
+    model: String,
+}
+
+impl Edge {
+    pub fn new<S>(source: S, target: S) -> Result<Self, ValidationError>
+    where
+        S: Into<String>,
+    {
+        Self::new_with_clock(source, target, &clock::SystemClock)
+    }
+
+    /// Create an Edge from the Model called `source` to `target`, taking `created_at` from `clock`.
+    pub fn new_with_clock<S>(
+        source: S,
+        target: S,
+        clock: &dyn Clock,
+    ) -> Result<Self, ValidationError>
+    where
+        S: Into<String>,
+    {
+        let source = source.into();
+        let target = target.into();
+        validation::validate_name(Model::MODEL, &source)?;
+        validation::validate_name(Model::MODEL, &target)?;
+
+        let pk = Model::key_of(&source).build();
+        let sk = Self::key_of(&target).build();
+        validation::validate_key(&pk, &sk)?;
+
+        Ok(Self {
+            pk,
+            sk,
+            model: Self::MODEL.to_string(),
+
+            source,
+            target,
+            created_at: clock.now(),
+        })
+    }
+
+    /// The `sk` of an Edge pointing at the Model called `target`,
+    /// and its partition key in the `inverted` index.
+    pub fn key_of(target: &str) -> KeyBuilder {
+        KeyBuilder::new().segment(Self::MODEL, target)
+    }
+
+    pub fn from_hashmap(hashmap: &types::HashMap) -> serde_dynamodb::error::Result<Self> {
+        serde_dynamodb::from_hashmap(hashmap.to_owned())
+    }
+
+    pub fn to_hashmap(&self) -> serde_dynamodb::error::Result<types::HashMap> {
+        serde_dynamodb::to_hashmap(&self)
+    }
+
+    /// Link two Models, in a transaction that checks that both of them exist.
+    pub async fn link<S>(db: &impl Database, source: S, target: S) -> Result<Self, Box<dyn Error>>
+    where
+        S: Into<String>,
+    {
+        let edge = Self::new(source, target)?;
+
+        let source_key = Model::key_of(&edge.source).build();
+        let target_key = Model::key_of(&edge.target).build();
+
+        // A transaction may only include one operation on each item
+        let mut transact_items =
+            vec![db.condition_check_exists(&source_key, &source_key, Model::MODEL)];
+        if target_key != source_key {
+            transact_items.push(db.condition_check_exists(&target_key, &target_key, Model::MODEL));
+        }
+        transact_items.push(db.put(edge.to_hashmap()?));
+
+        db.transact_write_items(transact_items).await?;
+        Ok(edge)
+    }
+
+    /// Remove the Edge between two Models, if there is one.
+    pub async fn unlink<S>(db: &impl Database, source: S, target: S) -> Result<(), Box<dyn Error>>
+    where
+        S: Into<String>,
+    {
+        let pk = Model::key_of(&source.into()).build();
+        let sk = Self::key_of(&target.into()).build();
+
+        db.transact_write_items(vec![db.delete(pk, sk)]).await?;
+        Ok(())
+    }
+
+    /// The Edges leaving the Model called `name`.
+    pub async fn outgoing<S>(db: &impl Database, name: S) -> Result<Vec<Self>, Box<dyn Error>>
+    where
+        S: Into<String>,
+    {
+        let pk = Model::key_of(&name.into()).build();
+        let prefix = KeyBuilder::new().label_prefix(Self::MODEL);
+
+        let res = db.query(None, pk, prefix).await?;
+        Ok(ItemCollection::new(res.items.unwrap_or_default()).entities()?)
+    }
+
+    /// The keys of every Edge leaving or pointing at the Model called `name`,
+    /// which are deleted along with it.
+    ///
+    /// The Edges pointing at it are found through the `inverted` index, which is eventually
+    /// consistent, so an Edge linked a moment before the Model is deleted may be missed.
+    pub async fn keys_touching(
+        db: &impl Database,
+        name: &str,
+    ) -> Result<Vec<(String, String)>, Box<dyn Error>> {
+        let mut keys: Vec<(String, String)> = Self::outgoing(db, name)
+            .await?
+            .iter()
+            .map(traits::Key::key)
+            .collect();
+
+        // An Edge from a Model to itself is both outgoing and incoming
+        for edge in Self::incoming(db, name).await? {
+            let key = traits::Key::key(&edge);
+            if !keys.contains(&key) {
+                keys.push(key);
+            }
+        }
+
+        Ok(keys)
+    }
+
+    /// The Edges pointing at the Model called `name`, found through the `inverted` index.
+    pub async fn incoming<S>(db: &impl Database, name: S) -> Result<Vec<Self>, Box<dyn Error>>
+    where
+        S: Into<String>,
+    {
+        let pk = Self::key_of(&name.into()).build();
+
+        let res = db
+            .query(Some("inverted".to_string()), pk, String::new())
+            .await?;
+        Ok(ItemCollection::new(res.items.unwrap_or_default()).entities()?)
+    }
+
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    pub fn target(&self) -> &str {
+        &self.target
+    }
+
+    pub fn pk(&self) -> String {
+        self.pk.clone()
+    }
+
+    pub fn sk(&self) -> String {
+        self.sk.clone()
+    }
+
+    pub fn model(&self) -> String {
+        self.model.clone()
+    }
+}
+
+impl Entity for Edge {
+    const MODEL: &'static str = "edge";
+
+    fn from_hashmap(hashmap: &types::HashMap) -> serde_dynamodb::error::Result<Self> {
+        Self::from_hashmap(hashmap)
+    }
+}
+
+impl traits::Transactable for Edge {}
+
+impl traits::Key for Edge {
+    fn key(&self) -> (String, String) {
+        (self.pk(), self.sk())
+    }
+
+    fn model_key(&self) -> (String, String) {
+        (self.model(), self.sk())
+    }
+}
@@ -1,8 +1,22 @@
+use chrono::{DateTime, Duration, Utc};
 use clap::Clap;
 use rusoto_dynamodb::DynamoDbClient;
+use rusoto_dynamodbstreams::DynamoDbStreamsClient;
 use rusoto_sts::StsClient;
-use single_table::{args::*, env, Database, Model, SecurityTokens, SubModel};
-use std::error::Error;
+use single_table::{
+    args::*,
+    capacity::Meter,
+    clock::SystemClock,
+    env,
+    events::Event,
+    history::{Audit, Revision},
+    migrate::{MigrateOptions, MigrationState, Migrations},
+    stream::{Checkpoints, StreamConsumer},
+    traits::{QueryOptions, SortKeyCondition},
+    Clock, Database, Edge, Entity, Model, Registry, SaveOptions, SecurityTokens, SubModel,
+    TTL_ATTRIBUTE,
+};
+use std::{error::Error, sync::Arc};
 
 fn main() -> Result<(), Box<dyn Error>> {
     let opts = Opts::parse();
@@ -11,27 +25,57 @@ fn main() -> Result<(), Box<dyn Error>> {
     let region = env::resolve_region(opts.aws_region.clone(), opts.aws_endpoint_url.clone())?;
     println!("{:?}", region);
 
-    let db = single_table::DDB::new(DynamoDbClient::new(region.clone()), &opts.table_name);
+    let capacity = opts.capacity;
+    let meter = Arc::new(Meter::new());
+    let mut db = single_table::DDB::new(DynamoDbClient::new(region.clone()), &opts.table_name);
+    if capacity {
+        db = db.with_capacity_meter(meter.clone());
+    }
     let sts = single_table::STS::new(StsClient::new(region.clone()));
 
-    smol::run(async {
+    let result: Result<(), Box<dyn Error>> = smol::run(async {
         match opts.commands {
             Commands::Create => create(db).await?,
             Commands::Describe => describe(db).await?,
 
             Commands::GetModel(opts) => get_model(db, opts).await?,
             Commands::GetSubModel(opts) => get_submodel(db, opts).await?,
+            Commands::GetMany(opts) => get_many(db, opts).await?,
 
             Commands::Query(opts) => query(db, opts).await?,
+            Commands::Watch(opts) => {
+                let client = DynamoDbStreamsClient::new(region.clone());
+                watch(db, client, opts).await?
+            }
             Commands::Scan(opts) => scan(db, opts).await?,
+            Commands::Migrate(opts) => migrate(db, opts).await?,
+            Commands::Ttl(opts) => ttl(db, opts).await?,
 
             Commands::PutModel(opts) => put_model(db, opts).await?,
             Commands::PutSubModel(opts) => put_submodel(db, opts).await?,
 
+            Commands::DeleteModel(opts) => delete_model(db, opts).await?,
+
+            Commands::History(opts) => history(db, opts).await?,
+            Commands::Restore(opts) => restore(db, opts).await?,
+
+            Commands::LogAppend(opts) => log_append(db, opts).await?,
+            Commands::LogTail(opts) => log_tail(db, opts).await?,
+
+            Commands::Link(opts) => link(db, opts).await?,
+            Commands::Unlink(opts) => unlink(db, opts).await?,
+            Commands::Neighbors(opts) => neighbors(db, opts).await?,
+
             Commands::WhoAmI => whoami(sts).await?,
         }
         Ok(())
-    })
+    });
+
+    // Also printed when the command fails, for the requests made before it did
+    if capacity {
+        println!("consumed capacity:\n{}", meter.summary());
+    }
+    result
 }
 
 async fn create(db: impl Database) -> Result<(), Box<dyn Error>> {
@@ -50,6 +94,16 @@ async fn describe(db: impl Database) -> Result<(), Box<dyn Error>> {
 }
 
 async fn get_model(db: impl Database, opts: GetModelOpts) -> Result<(), Box<dyn Error>> {
+    if opts.children {
+        let (model, children) = Model::get_with_children(&db, opts.name).await?;
+        println!("{:#?}", model);
+        for child in children {
+            println!("{:#?}", child);
+        }
+
+        return Ok(());
+    }
+
     let res = Model::get(&db, opts.name).await?;
     println!("{:#?}", res);
 
@@ -63,32 +117,66 @@ async fn get_submodel(db: impl Database, opts: GetSubModelOpts) -> Result<(), Bo
     Ok(())
 }
 
+async fn get_many(db: impl Database, opts: GetManyOpts) -> Result<(), Box<dyn Error>> {
+    let (model, children) = Model::get_snapshot(&db, opts.parent, &opts.names).await?;
+    println!("{:#?}", model);
+    for (name, child) in opts.names.iter().zip(children) {
+        match child {
+            Some(child) => println!("{:#?}", child),
+            None => println!("{}: not found", name),
+        }
+    }
+
+    Ok(())
+}
+
 async fn query(db: impl Database, opts: QueryOpts) -> Result<(), Box<dyn Error>> {
+    // A partial `sk` matches every SubModel whose name begins with it
+    let mut parent = None;
     let (pk, sk) = match &opts.index {
+        // The index holds the SubModels of every Model, only those of `pk` are kept
         Some(index) if index == "model" => match opts.sk {
-            Some(sk) => (
-                "submodel".to_string(),
-                format!("model#{}#submodel#{}", opts.pk, sk),
-            ),
-            None => ("model".to_string(), format!("model#{}", opts.pk)),
+            Some(sk) => {
+                parent = Some(Model::key_of(&opts.pk).build());
+                (SubModel::MODEL.to_string(), SubModel::key_of(&sk).build())
+            }
+            None => (Model::MODEL.to_string(), Model::key_of(&opts.pk).build()),
         },
+        // Find the parents of SubModels called `pk`, optionally only those beginning with `sk`
+        Some(index) if index == "inverted" => {
+            let pk = SubModel::key_of(&opts.pk).build();
+            let sk = match opts.sk {
+                Some(sk) => Model::key_of(&sk).build(),
+                None => String::new(),
+            };
+            (pk, sk)
+        }
         None | Some(_) => {
-            let pk = format!("model#{}", opts.pk);
+            let pk = Model::key_of(&opts.pk).build();
             let sk = match opts.sk {
-                Some(sk) => format!("model#{}#submodel#{}", opts.pk, sk),
-                None => format!("model#{}", opts.pk),
+                Some(sk) => SubModel::key_of(&sk).build(),
+                None => String::new(),
             };
             (pk, sk)
         }
     };
 
-    let res = db.query(opts.index, pk, sk).await?;
-    if let Some(hashmaps) = res.items {
-        for hashmap in hashmaps {
-            let _ = Model::from_hashmap(&hashmap).map(|item| println!("{:#?}", item));
-
-            let _ = SubModel::from_hashmap(&hashmap).map(|item| println!("{:#?}", item));
+    let options = QueryOptions {
+        consistent_read: opts.consistent,
+        ..Default::default()
+    };
+    let res = db
+        .query_with(opts.index, pk, SortKeyCondition::BeginsWith(sk), options)
+        .await?;
+    let registry = Registry::default();
+    for hashmap in res.items.unwrap_or_default() {
+        if let Some(parent) = &parent {
+            let of = hashmap.get("parent").and_then(|value| value.s.as_ref());
+            if of != Some(parent) {
+                continue;
+            }
         }
+        println!("{:#?}", registry.decode(&hashmap)?);
     }
 
     Ok(())
@@ -103,19 +191,114 @@ async fn scan(db: impl Database, opts: ScanOpts) -> Result<(), Box<dyn Error>> {
         println!("IndexName: {}", index);
     }
 
-    if let Some(hashmaps) = res.items {
-        for hashmap in hashmaps {
-            let _ = Model::from_hashmap(&hashmap).map(|item| println!("{:#?}", item));
+    let registry = Registry::default();
+    for hashmap in res.items.unwrap_or_default() {
+        println!("{:#?}", registry.decode(&hashmap)?);
+    }
+
+    Ok(())
+}
+
+async fn migrate(db: impl Database, opts: MigrateOpts) -> Result<(), Box<dyn Error>> {
+    let migrations = Migrations::default();
+
+    match opts.command {
+        MigrateCommand::Up(opts) => {
+            let options = MigrateOptions {
+                dry_run: opts.dry_run,
+                page_size: opts.page_size,
+            };
+            for report in migrations.up(&db, &options).await? {
+                println!(
+                    "{:>4} {}: rewrote {} of {} items",
+                    report.version, report.name, report.rewritten, report.scanned
+                );
+            }
+        }
+        MigrateCommand::Status => {
+            let state = MigrationState::load(&db).await?;
+            for migration in migrations.migrations() {
+                let status = if migration.version <= state.version() {
+                    "applied"
+                } else if state.in_progress() == Some(migration.version) {
+                    "in progress"
+                } else {
+                    "pending"
+                };
+                println!("{:>4} {}: {}", migration.version, migration.name, status);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+async fn watch(
+    db: impl Database,
+    client: DynamoDbStreamsClient,
+    opts: WatchOpts,
+) -> Result<(), Box<dyn Error>> {
+    let stream_arn = db
+        .describe_table()
+        .await?
+        .table
+        .and_then(|table| table.latest_stream_arn)
+        .ok_or("the table does not have a stream")?;
+
+    let checkpoints = match &opts.checkpoint {
+        Some(path) => Checkpoints::load(path)?,
+        None => Checkpoints::default(),
+    };
+    let mut consumer = StreamConsumer::new(client, stream_arn, checkpoints);
+
+    loop {
+        for record in consumer.poll().await? {
+            println!("{:#?}", record);
+        }
 
-            let _ = SubModel::from_hashmap(&hashmap).map(|item| println!("{:#?}", item));
+        if let Some(path) = &opts.checkpoint {
+            consumer.checkpoints().save(path)?;
         }
+
+        smol::Timer::after(std::time::Duration::from_secs(opts.interval)).await;
+    }
+}
+
+async fn ttl(db: impl Database, opts: TtlOpts) -> Result<(), Box<dyn Error>> {
+    if opts.enable || opts.disable {
+        let res = db.update_time_to_live(TTL_ATTRIBUTE, opts.enable).await?;
+        println!("{:#?}", res);
     }
 
+    let res = db.describe_time_to_live().await?;
+    println!("{}: {:#?}", db.table_name(), res);
     Ok(())
 }
 
 async fn put_model(db: impl Database, opts: PutModelOpts) -> Result<(), Box<dyn Error>> {
-    let mut model = Model::new(opts.name, opts.a_version);
+    let clock = SystemClock;
+    let mut model = Model::new_with_clock(opts.name, opts.a_version, &clock)?;
+    if let Some(ttl) = opts.ttl {
+        model = model.expire_at(clock.now() + Duration::seconds(ttl));
+    }
+    if let Some(email) = opts.email {
+        model = model.with_email(email)?;
+    }
+
+    // Saving over an existing Model releases its email, and continues its history
+    if let Some(existing) = Model::find(&db, model.name()).await? {
+        model = model.replacing(&existing);
+    }
+
+    if opts.history {
+        let revision = model
+            .save_with_history(&db, &Audit::new_with_clock(opts.by, &clock))
+            .await?;
+        println!("saved revision {}", revision);
+
+        return Ok(());
+    }
+
     let res = model.save(&db).await?;
     println!("{:#?}", res);
 
@@ -124,14 +307,109 @@ async fn put_model(db: impl Database, opts: PutModelOpts) -> Result<(), Box<dyn
 
 async fn put_submodel(db: impl Database, opts: PutSubModelOpts) -> Result<(), Box<dyn Error>> {
     let parent = Model::get(&db, &opts.parent).await?;
-    let mut submodel = SubModel::new(opts.name, parent);
+    let mut submodel = SubModel::new(opts.name, parent)?;
 
-    let res = submodel.save(&db).await?;
+    let options = SaveOptions {
+        update_parent_count: opts.count,
+        ..Default::default()
+    };
+    let res = submodel.save_with(&db, options).await?;
     println!("{:#?}", res);
 
     Ok(())
 }
 
+async fn delete_model(db: impl Database, opts: DeleteModelOpts) -> Result<(), Box<dyn Error>> {
+    if opts.cascade {
+        let deleted = Model::delete_cascade(&db, opts.name).await?;
+        println!("deleted {} items", deleted);
+    } else if opts.history {
+        let revision = Model::soft_delete(&db, opts.name, &Audit::new(opts.by)).await?;
+        println!("deleted, tombstone revision {}", revision);
+    } else {
+        Model::delete(&db, opts.name).await?;
+    }
+
+    Ok(())
+}
+
+async fn history(db: impl Database, opts: HistoryOpts) -> Result<(), Box<dyn Error>> {
+    for revision in Revision::history(&db, opts.name).await? {
+        let status = if revision.is_deleted() {
+            "deleted"
+        } else {
+            "saved"
+        };
+        println!(
+            "{:>4} {} {} by {}",
+            revision.revision(),
+            revision.changed_at().to_rfc3339(),
+            status,
+            revision.changed_by().unwrap_or("unknown")
+        );
+    }
+
+    Ok(())
+}
+
+async fn restore(db: impl Database, opts: RestoreOpts) -> Result<(), Box<dyn Error>> {
+    let model = Model::restore(&db, opts.name, opts.revision, &Audit::new(opts.by)).await?;
+    println!("{:#?}", model);
+
+    Ok(())
+}
+
+async fn log_append(db: impl Database, opts: LogAppendOpts) -> Result<(), Box<dyn Error>> {
+    let event = Event::append(&db, opts.parent, opts.message).await?;
+    println!("{:#?}", event);
+
+    Ok(())
+}
+
+async fn log_tail(db: impl Database, opts: LogTailOpts) -> Result<(), Box<dyn Error>> {
+    let events = match opts.since {
+        Some(since) => {
+            let since = DateTime::parse_from_rfc3339(&since)?.with_timezone(&Utc);
+            let until = match opts.until {
+                Some(until) => DateTime::parse_from_rfc3339(&until)?.with_timezone(&Utc),
+                None => SystemClock.now(),
+            };
+            Event::between(&db, opts.parent, since, until).await?
+        }
+        None => Event::latest(&db, opts.parent, opts.limit).await?,
+    };
+
+    for event in events {
+        println!("{} {}", event.at().to_rfc3339(), event.message());
+    }
+
+    Ok(())
+}
+
+async fn link(db: impl Database, opts: LinkOpts) -> Result<(), Box<dyn Error>> {
+    let edge = Edge::link(&db, opts.source, opts.target).await?;
+    println!("{:#?}", edge);
+
+    Ok(())
+}
+
+async fn unlink(db: impl Database, opts: LinkOpts) -> Result<(), Box<dyn Error>> {
+    Edge::unlink(&db, opts.source, opts.target).await?;
+
+    Ok(())
+}
+
+async fn neighbors(db: impl Database, opts: NeighborsOpts) -> Result<(), Box<dyn Error>> {
+    for edge in Edge::outgoing(&db, &opts.name).await? {
+        println!("{} -> {}", edge.source(), edge.target());
+    }
+    for edge in Edge::incoming(&db, &opts.name).await? {
+        println!("{} <- {}", edge.target(), edge.source());
+    }
+
+    Ok(())
+}
+
 async fn whoami<STS: SecurityTokens>(sts: STS) -> Result<(), Box<dyn Error>> {
     let caller_id = sts.get_caller_identity().await?;
     println!("{:?}", caller_id);
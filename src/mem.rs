@@ -1,17 +1,50 @@
 use async_mutex::Mutex;
 use async_trait::async_trait;
-use std::collections::BTreeMap;
+use chrono::{DateTime, Utc};
+use futures::channel::mpsc::{self, UnboundedReceiver, UnboundedSender};
+use std::{
+    collections::{self, BTreeMap, VecDeque},
+    ops::Bound,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
 use uuid::Uuid;
 
 use crate::{
-    traits::{Database, Key},
+    capacity::{self, Meter, RequestKind, Tally},
+    clock::{Clock, SystemClock},
+    stream::ChangeRecord,
+    traits::{
+        add_expression, make_key, transact_item_key, Database, Key, QueryOptions, ReadOptions,
+        SortKeyCondition, TransactOptions, MAX_TRANSACTION_ITEMS,
+    },
     types::*,
+    validation::{self, ValidationError},
 };
 
 pub struct MemoryDB {
     table_name: String,
-    table: Mutex<BTreeMap<(String, String), HashMap>>,
-    index: Mutex<BTreeMap<(String, String), HashMap>>,
+    table: Mutex<Table>,
+    indexes: Mutex<Indexes>,
+
+    // The attribute holding each item's expiry time, when TTL is enabled
+    ttl_attribute: Mutex<Option<String>>,
+    clock: Arc<dyn Clock>,
+
+    // Transactions applied with a client request token, by token
+    requests: Mutex<collections::HashMap<String, IdempotentRequest>>,
+
+    // Read by eventually consistent reads, when simulating replication lag
+    replica: std::sync::Mutex<Option<Replica>>,
+
+    // Records the capacity consumed by each request, which is then returned with it
+    meter: Option<Arc<Meter>>,
+
+    subscribers: std::sync::Mutex<Vec<UnboundedSender<ChangeRecord>>>,
+    sequence_number: AtomicU64,
 }
 
 pub fn memorydb() -> MemoryDB {
@@ -21,10 +54,397 @@ pub fn memorydb() -> MemoryDB {
             format!("single-table-{}", uuid.to_hyphenated())
         },
         table: Mutex::new(BTreeMap::new()),
-        index: Mutex::new(BTreeMap::new()),
+        indexes: Mutex::new(Indexes::new()),
+
+        ttl_attribute: Mutex::new(None),
+        clock: Arc::new(SystemClock),
+
+        requests: Mutex::new(collections::HashMap::new()),
+        replica: std::sync::Mutex::new(None),
+        meter: None,
+
+        subscribers: std::sync::Mutex::new(vec![]),
+        sequence_number: AtomicU64::new(0),
+    }
+}
+
+/// Periodically delete expired items in the background, with `MemoryDB::sweep_expired`.
+///
+/// DynamoDB deletes expired items eventually, typically within 48 hours,
+/// until then they are still returned by reads.
+///
+/// Whether an item has expired is decided by the database's `Clock`, but the `interval`
+/// between sweeps is wall-clock time. Tests with a `ManualClock` should advance it and
+/// call `sweep_expired` themselves, rather than wait for the sweeper.
+pub fn spawn_sweeper(db: Arc<MemoryDB>, interval: Duration) -> smol::Task<()> {
+    smol::Task::spawn(async move {
+        loop {
+            smol::Timer::after(interval).await;
+            db.sweep_expired().await;
+        }
+    })
+}
+
+impl MemoryDB {
+    /// Replace the clock used to decide when items and client request tokens expire.
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Simulate eventual consistency: writes reach the global secondary indexes,
+    /// and eventually consistent reads of the table, only once `lag` has passed on the clock.
+    ///
+    /// Reads with `consistent_read` see every write straight away.
+    /// Without a lag, which is the default, every read does.
+    /// The items already in the table have been replicated.
+    pub fn with_replication_lag(mut self, lag: chrono::Duration) -> Self {
+        let replica = Replica::new(lag, self.table.get_mut());
+        *self.replica.get_mut().expect("replica lock") = Some(replica);
+        self
+    }
+
+    /// Work out the capacity consumed by every request from the size of the items it
+    /// reads and writes, as DynamoDB would, and record it in `meter`.
+    pub fn with_capacity_meter(mut self, meter: Arc<Meter>) -> Self {
+        self.meter = Some(meter);
+        self
+    }
+
+    // The capacity consumed by a request, when it is being metered
+    fn consume(&self, kind: RequestKind, tally: Tally) -> Option<ConsumedCapacity> {
+        let meter = self.meter.as_ref()?;
+        let consumed = tally.consumed_capacity(self.table_name());
+        meter.record(kind, Some(&consumed));
+        Some(consumed)
+    }
+
+    /// Receive a `ChangeRecord` for every item written or deleted from now on.
+    ///
+    /// Records are delivered in the order the changes were applied.
+    pub fn subscribe(&self) -> UnboundedReceiver<ChangeRecord> {
+        let (sender, receiver) = mpsc::unbounded();
+        self.subscribers
+            .lock()
+            .expect("subscribers lock")
+            .push(sender);
+
+        receiver
+    }
+
+    // Read the table, or its replica unless `consistent_read` is set
+    async fn read_table<F, R>(&self, consistent_read: bool, read: F) -> R
+    where
+        F: FnOnce(&Table) -> R + Send,
+        R: Send,
+    {
+        if !consistent_read {
+            let mut replica = self.replica.lock().expect("replica lock");
+            if let Some(replica) = replica.as_mut() {
+                replica.catch_up(self.clock.now());
+                return read(&replica.table);
+            }
+        }
+
+        read(&*self.table.lock().await)
+    }
+
+    // Read the indexes, or their replica, as they are only ever eventually consistent
+    async fn read_indexes<F, R>(&self, read: F) -> R
+    where
+        F: FnOnce(&Indexes) -> R + Send,
+        R: Send,
+    {
+        {
+            let mut replica = self.replica.lock().expect("replica lock");
+            if let Some(replica) = replica.as_mut() {
+                replica.catch_up(self.clock.now());
+                return read(&replica.indexes);
+            }
+        }
+
+        read(&*self.indexes.lock().await)
+    }
+
+    // Called with both locks held
+    fn store(&self, table: &mut Table, indexes: &mut Indexes, item: HashMap) {
+        let old_image = table.insert(item.key(), item.clone());
+        if let Some(old_image) = &old_image {
+            indexes.remove(old_image);
+        }
+        indexes.insert(&item);
+        self.publish(old_image, Some(item));
+    }
+
+    // Called with the table lock held, so that records are published,
+    // and replicated, in order
+    fn publish(&self, old_image: Option<HashMap>, new_image: Option<HashMap>) {
+        if let Some(replica) = self.replica.lock().expect("replica lock").as_mut() {
+            let change = (self.clock.now(), old_image.clone(), new_image.clone());
+            replica.pending.push_back(change);
+        }
+
+        let sequence_number = self.sequence_number.fetch_add(1, Ordering::SeqCst) + 1;
+        let record =
+            match ChangeRecord::new(format!("{:021}", sequence_number), old_image, new_image) {
+                Some(record) => record,
+                None => return,
+            };
+
+        // Forget about subscribers that have gone away
+        self.subscribers
+            .lock()
+            .expect("subscribers lock")
+            .retain(|subscriber| subscriber.unbounded_send(record.clone()).is_ok());
+    }
+
+    /// Delete all items whose TTL attribute is earlier than the current time on the `Clock`,
+    /// in a single sweep.
+    ///
+    /// Returns the number of items deleted.
+    pub async fn sweep_expired(&self) -> usize {
+        let attribute_name = match &*self.ttl_attribute.lock().await {
+            Some(attribute_name) => attribute_name.clone(),
+            None => return 0,
+        };
+        let now = self.clock.now().timestamp();
+
+        let mut table = self.table.lock().await;
+        let mut indexes = self.indexes.lock().await;
+
+        let expired: Vec<(String, String)> = table
+            .iter()
+            .filter(|(_, item)| match expires_at(item, &attribute_name) {
+                Some(expires_at) => expires_at < now,
+                None => false,
+            })
+            .map(|(key, _)| key.clone())
+            .collect();
+
+        for key in &expired {
+            if let Some(item) = table.remove(key) {
+                indexes.remove(&item);
+                self.publish(Some(item), None);
+            }
+        }
+
+        expired.len()
     }
 }
 
+type Table = BTreeMap<(String, String), HashMap>;
+
+// A copy of the table and its indexes which receives each change `lag` after it was made
+struct Replica {
+    lag: chrono::Duration,
+    table: Table,
+    indexes: Indexes,
+
+    // Changes not yet applied, as the time they were made and their old and new images
+    pending: VecDeque<(DateTime<Utc>, Option<HashMap>, Option<HashMap>)>,
+}
+
+impl Replica {
+    // Starting out with every item in `table`
+    fn new(lag: chrono::Duration, table: &Table) -> Self {
+        let mut indexes = Indexes::new();
+        for item in table.values() {
+            indexes.insert(item);
+        }
+
+        Self {
+            lag,
+            table: table.clone(),
+            indexes,
+            pending: VecDeque::new(),
+        }
+    }
+
+    // Apply the changes made at least `lag` before `now`
+    fn catch_up(&mut self, now: DateTime<Utc>) {
+        while let Some((made_at, _, _)) = self.pending.front() {
+            if *made_at + self.lag > now {
+                break;
+            }
+
+            if let Some((_, old_image, new_image)) = self.pending.pop_front() {
+                if let Some(old_image) = old_image {
+                    self.table.remove(&old_image.key());
+                    self.indexes.remove(&old_image);
+                }
+                if let Some(new_image) = new_image {
+                    self.indexes.insert(&new_image);
+                    self.table.insert(new_image.key(), new_image);
+                }
+            }
+        }
+    }
+
+    fn clear(&mut self) {
+        self.table.clear();
+        self.indexes.clear();
+        self.pending.clear();
+    }
+}
+
+// How long DynamoDB remembers a client request token, after the request it was first used for
+fn idempotency_window() -> chrono::Duration {
+    chrono::Duration::minutes(10)
+}
+
+// A transaction applied with a client request token
+struct IdempotentRequest {
+    transact_items: Vec<TransactWriteItem>,
+    output: TransactWriteItemsOutput,
+    expires_at: DateTime<Utc>,
+}
+
+// Global secondary indexes, with the attributes of their hash and range keys
+const INDEXES: &[(&str, &str, &str)] = &[("model", "model", "sk"), ("inverted", "sk", "pk")];
+
+// Unlike in the table, keys need not be unique within an index,
+// so entries are also keyed by the item's primary key.
+type IndexKey = (String, String, String, String);
+type Index = BTreeMap<IndexKey, HashMap>;
+
+struct Indexes(BTreeMap<&'static str, Index>);
+
+impl Indexes {
+    fn new() -> Self {
+        Self(
+            INDEXES
+                .iter()
+                .map(|(name, _, _)| (*name, Index::new()))
+                .collect(),
+        )
+    }
+
+    fn get(&self, name: &str) -> Option<&Index> {
+        self.0.get(name)
+    }
+
+    // Indexes are sparse, items without the key attributes are left out
+    fn insert(&mut self, item: &HashMap) {
+        for (name, hash, range) in INDEXES {
+            if let (Some(index), Some(key)) = (self.0.get_mut(name), index_key(item, hash, range)) {
+                index.insert(key, item.clone());
+            }
+        }
+    }
+
+    fn remove(&mut self, item: &HashMap) {
+        for (name, hash, range) in INDEXES {
+            if let (Some(index), Some(key)) = (self.0.get_mut(name), index_key(item, hash, range)) {
+                index.remove(&key);
+            }
+        }
+    }
+
+    fn clear(&mut self) {
+        for index in self.0.values_mut() {
+            index.clear();
+        }
+    }
+}
+
+fn index_key(item: &HashMap, hash: &str, range: &str) -> Option<IndexKey> {
+    let attribute = |name: &str| item.get(name).and_then(|value| value.s.clone());
+    let (pk, sk) = item.key();
+
+    Some((attribute(hash)?, attribute(range)?, pk, sk))
+}
+
+// Up to `limit + 1` items following the `start` key, in key order
+fn page<K: Ord>(items: &BTreeMap<K, HashMap>, start: Option<K>, limit: usize) -> Vec<HashMap> {
+    let range = match start {
+        Some(start) => items.range((Bound::Excluded(start), Bound::Unbounded)),
+        None => items.range(..),
+    };
+
+    range
+        .map(|(_, item)| item.clone())
+        .take(limit.saturating_add(1))
+        .collect()
+}
+
+// Cut `items` down to `limit`, returning the key attributes of the last item
+// as the `last_evaluated_key` when any were left out
+fn truncate_page(
+    items: &mut Vec<HashMap>,
+    limit: usize,
+    key_attributes: &[&str],
+) -> Option<HashMap> {
+    if items.len() <= limit {
+        return None;
+    }
+
+    items.truncate(limit);
+    items.last().map(|item| {
+        key_attributes
+            .iter()
+            .filter_map(|name| Some((name.to_string(), item.get(*name)?.clone())))
+            .collect()
+    })
+}
+
+// The key attributes of an item in the order they sort it within a table or index
+fn key_order(item: &HashMap, key_attributes: &[&str]) -> Vec<Option<String>> {
+    key_attributes
+        .iter()
+        .map(|name| item.get(*name).and_then(|value| value.s.clone()))
+        .collect()
+}
+
+fn unknown_index<E>(name: &str) -> RusotoError<E> {
+    RusotoError::Validation(format!(
+        "The table does not have the specified index: {}",
+        name
+    ))
+}
+
+// Reading `items` costs capacity for their total size, from the index they were read from
+fn tally_read<'a, I>(index: Option<&str>, items: I, consistent_read: bool) -> Tally
+where
+    I: IntoIterator<Item = &'a HashMap>,
+{
+    let size = items.into_iter().map(validation::item_size).sum();
+    let units = capacity::read_units(size, consistent_read);
+
+    let mut tally = Tally::default();
+    match index {
+        Some(name) => tally.index(name, units),
+        None => tally.table(units),
+    }
+    tally
+}
+
+// Replacing `old_image` with `new_image` costs the table capacity for the larger of the two,
+// and each index capacity for every image it writes or deletes
+fn tally_write(tally: &mut Tally, old_image: Option<&HashMap>, new_image: Option<&HashMap>) {
+    let size = |image: Option<&HashMap>| image.map_or(0, validation::item_size);
+    tally.table(capacity::write_units(size(old_image).max(size(new_image))));
+
+    for (name, hash, range) in INDEXES {
+        let key = |image: Option<&HashMap>| image.and_then(|item| index_key(item, hash, range));
+        let units = match (key(old_image), key(new_image)) {
+            (None, None) => continue,
+            (Some(_), None) => capacity::write_units(size(old_image)),
+            (Some(old), Some(new)) if old != new => {
+                capacity::write_units(size(old_image)) + capacity::write_units(size(new_image))
+            }
+            (_, Some(_)) => capacity::write_units(size(new_image)),
+        };
+        tally.index(name, units);
+    }
+}
+
+// TTL attributes must be a Number holding seconds since the epoch, other items never expire
+fn expires_at(item: &HashMap, attribute_name: &str) -> Option<i64> {
+    item.get(attribute_name)
+        .and_then(|value| value.n.as_ref())
+        .and_then(|n| n.parse().ok())
+}
+
 #[async_trait]
 impl Database for MemoryDB {
     fn table_name(&self) -> String {
@@ -33,7 +453,10 @@ impl Database for MemoryDB {
 
     async fn delete_table(&self) -> DeleteTableResult {
         self.table.lock().await.clear();
-        self.index.lock().await.clear();
+        self.indexes.lock().await.clear();
+        if let Some(replica) = self.replica.lock().expect("replica lock").as_mut() {
+            replica.clear();
+        }
         Ok(Default::default())
     }
 
@@ -57,47 +480,89 @@ impl Database for MemoryDB {
     where
         S: Into<String> + Send,
     {
-        let mut items: Vec<HashMap> = vec![];
+        self.scan_from(index, limit, None).await
+    }
 
-        let index = index.map(|s| s.into());
-        let db = match &index {
-            None => self.table.lock().await,
-            Some(_) => self.index.lock().await,
-        };
+    async fn scan_from<S>(
+        &self,
+        index: Option<S>,
+        limit: Option<i64>,
+        exclusive_start_key: Option<HashMap>,
+    ) -> ScanResult
+    where
+        S: Into<String> + Send,
+    {
+        let limit = limit.map_or(usize::MAX, |limit| limit as usize);
 
-        for (i, item) in db.values().cloned().enumerate() {
-            if let Some(limit) = limit {
-                if i as i64 >= limit {
-                    break;
-                }
+        let index: Option<String> = index.map(|s| s.into());
+        let index_name = index.clone();
+        let (mut items, key_attributes): (Vec<HashMap>, Vec<&str>) = match index {
+            None => {
+                let start = exclusive_start_key.as_ref().map(Key::key);
+                let items = self
+                    .read_table(false, |table| page(table, start, limit))
+                    .await;
+                (items, vec!["pk", "sk"])
             }
+            Some(name) => {
+                let (_, hash, range) = INDEXES
+                    .iter()
+                    .find(|(index, _, _)| *index == name)
+                    .ok_or_else(|| unknown_index(&name))?;
 
-            items.push(item);
-        }
+                let start = match &exclusive_start_key {
+                    Some(key) => Some(index_key(key, hash, range).ok_or_else(|| {
+                        RusotoError::Validation("The provided starting key is invalid".to_string())
+                    })?),
+                    None => None,
+                };
+                let items = self
+                    .read_indexes(|indexes| Some(page(indexes.get(&name)?, start, limit)))
+                    .await
+                    .ok_or_else(|| unknown_index(&name))?;
+                (items, vec![*hash, *range, "pk", "sk"])
+            }
+        };
+
+        // One more item than the limit is read, to tell whether the scan is complete
+        let last_evaluated_key = truncate_page(&mut items, limit, &key_attributes);
 
         let count = Some(items.len() as i64);
         let scanned_count = Some(items.len() as i64);
+        let tally = tally_read(index_name.as_deref(), &items, false);
 
         Ok(ScanOutput {
             items: Some(items),
             count,
             scanned_count,
+            last_evaluated_key,
+            consumed_capacity: self.consume(RequestKind::Scan, tally),
             ..Default::default()
         })
     }
 
     async fn get_item<S>(&self, pk: S, sk: S) -> GetItemResult
+    where
+        S: Into<String> + Send,
+    {
+        self.get_item_with(pk, sk, ReadOptions::default()).await
+    }
+
+    async fn get_item_with<S>(&self, pk: S, sk: S, options: ReadOptions) -> GetItemResult
     where
         S: Into<String> + Send,
     {
         let key = (pk.into(), sk.into());
+        validation::validate_key(&key.0, &key.1).map_err(invalid)?;
 
-        let db = self.table.lock().await;
-        let item = db.get(&key);
+        let item = self
+            .read_table(options.consistent_read, |table| table.get(&key).cloned())
+            .await;
+        let tally = tally_read(None, &item, options.consistent_read);
 
         Ok(GetItemOutput {
-            item: item.cloned(),
-            ..Default::default()
+            item,
+            consumed_capacity: self.consume(RequestKind::GetItem, tally),
         })
     }
 
@@ -106,48 +571,162 @@ impl Database for MemoryDB {
         H: Into<HashMap> + Key + Send,
     {
         let hash_map = item.into();
-        self.table
-            .lock()
-            .await
-            .insert(hash_map.key(), hash_map.clone());
-        self.index
-            .lock()
-            .await
-            .insert(hash_map.model_key(), hash_map.clone());
+        validation::validate_item(&hash_map).map_err(invalid)?;
 
-        Ok(Default::default())
+        let mut table = self.table.lock().await;
+        let mut indexes = self.indexes.lock().await;
+
+        let mut tally = Tally::default();
+        tally_write(&mut tally, table.get(&hash_map.key()), Some(&hash_map));
+        self.store(&mut table, &mut indexes, hash_map);
+
+        Ok(PutItemOutput {
+            consumed_capacity: self.consume(RequestKind::PutItem, tally),
+            ..Default::default()
+        })
     }
 
     async fn query<S>(&self, index: Option<S>, pk: S, sk: S) -> QueryResult
     where
         S: Into<String> + Send,
     {
-        let index = index.map(|s| s.into());
+        let condition = SortKeyCondition::BeginsWith(sk.into());
+        self.query_with(index, pk, condition, QueryOptions::default())
+            .await
+    }
+
+    async fn query_with<S>(
+        &self,
+        index: Option<S>,
+        pk: S,
+        condition: SortKeyCondition,
+        options: QueryOptions,
+    ) -> QueryResult
+    where
+        S: Into<String> + Send,
+    {
         let pk = pk.into();
-        let sk = sk.into();
-        let mut items: Vec<HashMap> = vec![];
-        let mut count: i64 = 0;
+        if let SortKeyCondition::Between(low, high) = &condition {
+            if low > high {
+                return Err(RusotoError::Validation(
+                    "Invalid KeyConditionExpression: the BETWEEN bounds are out of order"
+                        .to_string(),
+                ));
+            }
+        }
 
-        let db = match &index {
-            None => self.table.lock().await,
-            Some(_) => self.index.lock().await,
-        };
+        let index: Option<String> = index.map(|s| s.into());
+        let index_name = index.clone();
+        if index.is_some() && options.consistent_read {
+            return Err(RusotoError::Validation(
+                "Consistent reads are not supported on global secondary indexes".to_string(),
+            ));
+        }
 
-        'maxitems: for (key, hash_map) in db.iter() {
-            if key.0 == pk && key.1.starts_with(&sk) {
-                count = match count.checked_add(1) {
-                    Some(count) => {
-                        items.push(hash_map.clone());
-                        count
-                    }
-                    None => break 'maxitems,
-                };
+        // Items are matched in ascending order, and the page is taken from there
+        let (mut items, key_attributes): (Vec<HashMap>, Vec<&str>) = match index {
+            None => {
+                let items = self
+                    .read_table(options.consistent_read, |table| {
+                        table
+                            .iter()
+                            .filter(|((hash, range), _)| *hash == pk && condition.matches(range))
+                            .map(|(_, item)| item.clone())
+                            .collect::<Vec<_>>()
+                    })
+                    .await;
+                (items, vec!["pk", "sk"])
+            }
+            Some(name) => {
+                let (_, hash, range) = INDEXES
+                    .iter()
+                    .find(|(index, _, _)| *index == name)
+                    .ok_or_else(|| unknown_index(&name))?;
+
+                let items = self
+                    .read_indexes(|indexes| {
+                        let index = indexes.get(&name)?;
+                        let items: Vec<HashMap> = index
+                            .iter()
+                            .filter(|((hash, range, _, _), _)| {
+                                *hash == pk && condition.matches(range)
+                            })
+                            .map(|(_, item)| item.clone())
+                            .collect();
+                        Some(items)
+                    })
+                    .await
+                    .ok_or_else(|| unknown_index(&name))?;
+                (items, vec![*hash, *range, "pk", "sk"])
             }
+        };
+
+        if options.descending {
+            items.reverse();
+        }
+        // The start key need not be an item, which may have been deleted since the last page
+        if let Some(start) = &options.exclusive_start_key {
+            let start = key_order(start, &key_attributes);
+            items.retain(|item| {
+                let key = key_order(item, &key_attributes);
+                if options.descending {
+                    key < start
+                } else {
+                    key > start
+                }
+            });
         }
 
+        let limit = options.limit.map_or(usize::MAX, |limit| limit as usize);
+        let last_evaluated_key = truncate_page(&mut items, limit, &key_attributes);
+        let count = Some(items.len() as i64);
+        let tally = tally_read(index_name.as_deref(), &items, options.consistent_read);
+
         Ok(QueryOutput {
             items: Some(items),
-            count: Some(count),
+            count,
+            last_evaluated_key,
+            consumed_capacity: self.consume(RequestKind::Query, tally),
+            ..Default::default()
+        })
+    }
+
+    async fn increment<S>(&self, pk: S, sk: S, attribute: S, by: i64) -> UpdateItemResult
+    where
+        S: Into<String> + Send,
+    {
+        let key = make_key(pk, sk);
+        let (pk, sk) = key.key();
+        validation::validate_key(&pk, &sk).map_err(invalid)?;
+
+        let (expression, names, values) = add_expression(attribute, by);
+
+        let mut table = self.table.lock().await;
+        let mut indexes = self.indexes.lock().await;
+
+        let item = apply_update(
+            table.get(&(pk, sk)),
+            &key,
+            &expression,
+            Some(&names),
+            Some(&values),
+        )
+        .map_err(RusotoError::Validation)?;
+        validation::validate_item(&item).map_err(invalid)?;
+
+        // Only the updated attribute is returned, as with `UPDATED_NEW`
+        let attributes = names
+            .values()
+            .filter_map(|name| Some((name.clone(), item.get(name)?.clone())))
+            .collect();
+
+        let mut tally = Tally::default();
+        tally_write(&mut tally, table.get(&item.key()), Some(&item));
+        self.store(&mut table, &mut indexes, item);
+
+        Ok(UpdateItemOutput {
+            attributes: Some(attributes),
+            consumed_capacity: self.consume(RequestKind::UpdateItem, tally),
             ..Default::default()
         })
     }
@@ -156,13 +735,503 @@ impl Database for MemoryDB {
         &self,
         transact_items: Vec<TransactWriteItem>,
     ) -> TransactWriteItemsResult {
-        for transact_item in transact_items {
+        self.transact_write_items_with(transact_items, TransactOptions::default())
+            .await
+    }
+
+    async fn transact_write_items_with(
+        &self,
+        transact_items: Vec<TransactWriteItem>,
+        options: TransactOptions,
+    ) -> TransactWriteItemsResult {
+        // Invalid requests are rejected before any condition is evaluated
+        if transact_items.len() > MAX_TRANSACTION_ITEMS {
+            return Err(RusotoError::Validation(format!(
+                "Member must have length less than or equal to {}",
+                MAX_TRANSACTION_ITEMS
+            )));
+        }
+
+        let mut keys = collections::HashSet::new();
+        for transact_item in &transact_items {
+            validate_transact_item(transact_item).map_err(invalid)?;
+
+            if !keys.insert(transact_item_key(transact_item)) {
+                return Err(RusotoError::Validation(
+                    "Transaction request cannot include multiple operations on one item"
+                        .to_string(),
+                ));
+            }
+        }
+
+        // Held until the transaction is recorded, so that a request
+        // retried while the first is still running is not applied twice
+        let mut requests = self.requests.lock().await;
+        let now = self.clock.now();
+        requests.retain(|_, request| request.expires_at > now);
+
+        if let Some(token) = &options.client_request_token {
+            if let Some(request) = requests.get(token) {
+                if request.transact_items != transact_items {
+                    return Err(RusotoError::Service(
+                        TransactWriteItemsError::IdempotentParameterMismatch(
+                            "The request uses the same client token as a previous, \
+                             but non-identical request."
+                                .to_string(),
+                        ),
+                    ));
+                }
+                return Ok(request.output.clone());
+            }
+        }
+
+        // Hold both locks for the whole transaction,
+        // so that no other writer can interleave with the condition checks.
+        let mut table = self.table.lock().await;
+        let mut indexes = self.indexes.lock().await;
+
+        let mut reasons: Vec<Option<&str>> = vec![];
+        let mut updated: Vec<Option<HashMap>> = vec![];
+        for transact_item in &transact_items {
+            let reason = match condition_of(transact_item) {
+                Some((key, expression, names, values)) => {
+                    let item = table.get(&key.key());
+                    match condition_holds(item, expression, names, values) {
+                        Ok(true) => None,
+                        Ok(false) => Some("ConditionalCheckFailed"),
+                        Err(e) => return Err(RusotoError::Validation(e)),
+                    }
+                }
+                None => None,
+            };
+            reasons.push(reason);
+
+            // Updates are worked out up front, so that all of them apply or none do
+            let new_image = match &transact_item.update {
+                Some(update) => {
+                    let item = apply_update(
+                        table.get(&update.key.key()),
+                        &update.key,
+                        &update.update_expression,
+                        update.expression_attribute_names.as_ref(),
+                        update.expression_attribute_values.as_ref(),
+                    )
+                    .map_err(RusotoError::Validation)?;
+                    validation::validate_item(&item).map_err(invalid)?;
+                    Some(item)
+                }
+                None => None,
+            };
+            updated.push(new_image);
+        }
+
+        let mut tally = Tally::default();
+        for (transact_item, new_image) in transact_items.iter().zip(&updated) {
+            let old_image = table.get(&transact_item_key(transact_item));
+            if let Some(put_op) = &transact_item.put {
+                tally_write(&mut tally, old_image, Some(&put_op.item));
+            } else if new_image.is_some() {
+                tally_write(&mut tally, old_image, new_image.as_ref());
+            } else if transact_item.delete.is_some() {
+                tally_write(&mut tally, old_image, None);
+            } else {
+                // A condition check costs as much as writing the item it checks
+                let size = old_image.map_or(0, validation::item_size);
+                tally.table(capacity::write_units(size));
+            }
+        }
+        let consumed = self.consume(RequestKind::TransactWriteItems, tally.transactional());
+
+        // A cancelled transaction still consumes capacity, which is metered above
+        if reasons.iter().any(Option::is_some) {
+            return Err(RusotoError::Service(
+                TransactWriteItemsError::TransactionCanceled(cancellation_message(&reasons)),
+            ));
+        }
+
+        // Only applied transactions are recorded, one that was cancelled may be retried
+        let output = TransactWriteItemsOutput {
+            consumed_capacity: consumed.map(|consumed| vec![consumed]),
+            ..Default::default()
+        };
+        if let Some(token) = options.client_request_token {
+            let request = IdempotentRequest {
+                transact_items: transact_items.clone(),
+                output: output.clone(),
+                expires_at: now + idempotency_window(),
+            };
+            requests.insert(token, request);
+        }
+
+        for (transact_item, new_image) in transact_items.into_iter().zip(updated) {
             if let Some(put_op) = transact_item.put {
-                let hashmap = put_op.item;
-                let _ = self.put_item(hashmap).await;
+                self.store(&mut table, &mut indexes, put_op.item);
+            }
+
+            if let Some(new_image) = new_image {
+                self.store(&mut table, &mut indexes, new_image);
+            }
+
+            if let Some(delete_op) = transact_item.delete {
+                if let Some(item) = table.remove(&delete_op.key.key()) {
+                    indexes.remove(&item);
+                    self.publish(Some(item), None);
+                }
             }
         }
 
-        Ok(Default::default())
+        Ok(output)
+    }
+
+    async fn transact_get_items(&self, keys: Vec<(String, String)>) -> TransactGetItemsResult {
+        if keys.len() > MAX_TRANSACTION_ITEMS {
+            return Err(RusotoError::Validation(format!(
+                "Member must have length less than or equal to {}",
+                MAX_TRANSACTION_ITEMS
+            )));
+        }
+
+        let mut seen = collections::HashSet::new();
+        for (pk, sk) in &keys {
+            validation::validate_key(pk, sk).map_err(invalid)?;
+            if !seen.insert((pk, sk)) {
+                return Err(RusotoError::Validation(
+                    "Transaction request cannot include multiple operations on one item"
+                        .to_string(),
+                ));
+            }
+        }
+
+        // Take both locks, in the same order as writers, and hold them for the whole read,
+        // so that no write can land between the items.
+        let table = self.table.lock().await;
+        let _indexes = self.indexes.lock().await;
+
+        let mut tally = Tally::default();
+        let responses = keys
+            .into_iter()
+            .map(|key| {
+                let item = table.get(&key).cloned();
+                let size = item.as_ref().map_or(0, validation::item_size);
+                tally.table(capacity::read_units(size, true));
+                ItemResponse { item }
+            })
+            .collect();
+        let consumed = self.consume(RequestKind::TransactGetItems, tally.transactional());
+
+        Ok(TransactGetItemsOutput {
+            responses: Some(responses),
+            consumed_capacity: consumed.map(|consumed| vec![consumed]),
+        })
+    }
+
+    async fn update_time_to_live<S>(
+        &self,
+        attribute_name: S,
+        enabled: bool,
+    ) -> UpdateTimeToLiveResult
+    where
+        S: Into<String> + Send,
+    {
+        let attribute_name = attribute_name.into();
+        *self.ttl_attribute.lock().await = if enabled {
+            Some(attribute_name.clone())
+        } else {
+            None
+        };
+
+        Ok(UpdateTimeToLiveOutput {
+            time_to_live_specification: Some(TimeToLiveSpecification {
+                attribute_name,
+                enabled,
+            }),
+        })
+    }
+
+    async fn describe_time_to_live(&self) -> DescribeTimeToLiveResult {
+        let description = match &*self.ttl_attribute.lock().await {
+            Some(attribute_name) => TimeToLiveDescription {
+                attribute_name: Some(attribute_name.clone()),
+                time_to_live_status: Some("ENABLED".to_string()),
+            },
+            None => TimeToLiveDescription {
+                attribute_name: None,
+                time_to_live_status: Some("DISABLED".to_string()),
+            },
+        };
+
+        Ok(DescribeTimeToLiveOutput {
+            time_to_live_description: Some(description),
+        })
+    }
+}
+
+// DynamoDB rejects invalid requests with a `ValidationException`
+fn invalid<E>(e: ValidationError) -> RusotoError<E> {
+    RusotoError::Validation(e.to_string())
+}
+
+fn validate_transact_item(transact_item: &TransactWriteItem) -> Result<(), ValidationError> {
+    if let Some(put) = &transact_item.put {
+        validation::validate_item(&put.item)?;
+    }
+
+    let keys = transact_item
+        .condition_check
+        .as_ref()
+        .map(|check| &check.key)
+        .into_iter()
+        .chain(transact_item.delete.as_ref().map(|delete| &delete.key))
+        .chain(transact_item.update.as_ref().map(|update| &update.key));
+    for key in keys {
+        let (pk, sk) = key.key();
+        validation::validate_key(&pk, &sk)?;
+    }
+
+    Ok(())
+}
+
+type Names = collections::HashMap<String, String>;
+
+// The key and condition expression guarding a single transactional operation
+fn condition_of(
+    transact_item: &TransactWriteItem,
+) -> Option<(&HashMap, &str, Option<&Names>, Option<&HashMap>)> {
+    if let Some(check) = &transact_item.condition_check {
+        return Some((
+            &check.key,
+            &check.condition_expression,
+            check.expression_attribute_names.as_ref(),
+            check.expression_attribute_values.as_ref(),
+        ));
+    }
+
+    if let Some(put) = &transact_item.put {
+        if let Some(expression) = &put.condition_expression {
+            return Some((
+                &put.item,
+                expression,
+                put.expression_attribute_names.as_ref(),
+                put.expression_attribute_values.as_ref(),
+            ));
+        }
+    }
+
+    if let Some(delete) = &transact_item.delete {
+        if let Some(expression) = &delete.condition_expression {
+            return Some((
+                &delete.key,
+                expression,
+                delete.expression_attribute_names.as_ref(),
+                delete.expression_attribute_values.as_ref(),
+            ));
+        }
+    }
+
+    if let Some(update) = &transact_item.update {
+        if let Some(expression) = &update.condition_expression {
+            return Some((
+                &update.key,
+                expression,
+                update.expression_attribute_names.as_ref(),
+                update.expression_attribute_values.as_ref(),
+            ));
+        }
+    }
+
+    None
+}
+
+// Evaluate the subset of the condition expression syntax used by this crate.
+//
+//     attribute_exists(path)
+//     attribute_not_exists(path)
+//     path = :value
+//
+// Clauses may be joined with `AND`.
+fn condition_holds(
+    item: Option<&HashMap>,
+    expression: &str,
+    names: Option<&Names>,
+    values: Option<&HashMap>,
+) -> Result<bool, String> {
+    for clause in expression.split(" AND ") {
+        let clause = clause.trim();
+
+        let holds = if let Some(path) = function_argument(clause, "attribute_exists") {
+            attribute(item, path, names).is_some()
+        } else if let Some(path) = function_argument(clause, "attribute_not_exists") {
+            attribute(item, path, names).is_none()
+        } else {
+            let operands: Vec<&str> = clause.splitn(2, '=').map(str::trim).collect();
+            match operands[..] {
+                [path, placeholder] => {
+                    attribute(item, path, names) == Some(value_of(values, placeholder)?)
+                }
+                _ => return Err(format!("Invalid ConditionExpression: {}", clause)),
+            }
+        };
+
+        if !holds {
+            return Ok(false);
+        }
     }
+
+    Ok(true)
+}
+
+fn function_argument<'a>(clause: &'a str, function: &str) -> Option<&'a str> {
+    clause
+        .strip_prefix(function)
+        .map(str::trim)
+        .and_then(|argument| argument.strip_prefix('('))
+        .and_then(|argument| argument.strip_suffix(')'))
+        .map(str::trim)
+}
+
+// Apply the subset of the update expression syntax used by this crate,
+// returning the new image of the item.
+//
+//     SET path = :value, ...
+//     ADD path :number, ...
+//     REMOVE path, ...
+//
+// Missing items are created from their key.
+fn apply_update(
+    item: Option<&HashMap>,
+    key: &HashMap,
+    expression: &str,
+    names: Option<&Names>,
+    values: Option<&HashMap>,
+) -> Result<HashMap, String> {
+    let mut item = item.cloned().unwrap_or_else(|| key.clone());
+
+    for (action, clauses) in update_actions(expression)? {
+        for clause in clauses.split(',').map(str::trim) {
+            match action {
+                "SET" => {
+                    let operands: Vec<&str> = clause.splitn(2, '=').map(str::trim).collect();
+                    match operands[..] {
+                        [path, placeholder] => {
+                            let name = updatable(path, names)?;
+                            item.insert(name, value_of(values, placeholder)?.clone());
+                        }
+                        _ => return Err(format!("Invalid UpdateExpression: {}", clause)),
+                    }
+                }
+                "ADD" => {
+                    let operands: Vec<&str> = clause.split_whitespace().collect();
+                    match operands[..] {
+                        [path, placeholder] => {
+                            let name = updatable(path, names)?;
+                            let by = number(value_of(values, placeholder)?)?;
+                            let current = match item.get(&name) {
+                                Some(value) => number(value)?,
+                                None => 0,
+                            };
+
+                            let sum = AttributeValue {
+                                n: Some((current + by).to_string()),
+                                ..Default::default()
+                            };
+                            item.insert(name, sum);
+                        }
+                        _ => return Err(format!("Invalid UpdateExpression: {}", clause)),
+                    }
+                }
+                _ => {
+                    item.remove(&updatable(clause, names)?);
+                }
+            }
+        }
+    }
+
+    Ok(item)
+}
+
+// Split an update expression into its actions, and their comma separated clauses
+fn update_actions(expression: &str) -> Result<Vec<(&'static str, String)>, String> {
+    let mut actions: Vec<(&'static str, String)> = vec![];
+
+    for token in expression.split_whitespace() {
+        match token.to_uppercase().as_str() {
+            "SET" => actions.push(("SET", String::new())),
+            "ADD" => actions.push(("ADD", String::new())),
+            "REMOVE" => actions.push(("REMOVE", String::new())),
+            _ => match actions.last_mut() {
+                Some((_, clauses)) => {
+                    if !clauses.is_empty() {
+                        clauses.push(' ');
+                    }
+                    clauses.push_str(token);
+                }
+                None => return Err(format!("Invalid UpdateExpression: {}", expression)),
+            },
+        }
+    }
+
+    Ok(actions)
+}
+
+// Key attributes can not be changed by an update
+fn updatable(path: &str, names: Option<&Names>) -> Result<String, String> {
+    let name = resolve(path, names);
+    if name == "pk" || name == "sk" {
+        return Err(format!(
+            "One or more parameter values were invalid: Cannot update attribute {}. This attribute is part of the key",
+            name
+        ));
+    }
+
+    Ok(name.to_string())
+}
+
+fn number(value: &AttributeValue) -> Result<i64, String> {
+    value
+        .n
+        .as_ref()
+        .and_then(|n| n.parse().ok())
+        .ok_or_else(|| "An operand in the update expression has an incorrect data type".to_string())
+}
+
+fn value_of<'a>(
+    values: Option<&'a HashMap>,
+    placeholder: &str,
+) -> Result<&'a AttributeValue, String> {
+    values
+        .and_then(|values| values.get(placeholder))
+        .ok_or_else(|| {
+            format!(
+                "An expression attribute value used in expression is not defined; attribute value: {}",
+                placeholder
+            )
+        })
+}
+
+fn resolve<'a>(path: &'a str, names: Option<&'a Names>) -> &'a str {
+    match names.and_then(|names| names.get(path)) {
+        Some(name) => name.as_str(),
+        None => path,
+    }
+}
+
+fn attribute<'a>(
+    item: Option<&'a HashMap>,
+    path: &str,
+    names: Option<&Names>,
+) -> Option<&'a AttributeValue> {
+    let name = resolve(path, names);
+    item.and_then(|item| item.get(name))
+}
+
+// DynamoDB reports one reason per operation, in request order
+fn cancellation_message(reasons: &[Option<&str>]) -> String {
+    let reasons: Vec<&str> = reasons
+        .iter()
+        .map(|reason| reason.unwrap_or("None"))
+        .collect();
+    format!(
+        "Transaction cancelled, please refer cancellation reasons for specific reasons [{}]",
+        reasons.join(", ")
+    )
 }
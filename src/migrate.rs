@@ -0,0 +1,343 @@
+use serde::{Deserialize, Serialize};
+
+use std::error::Error;
+
+use crate::{
+    collection,
+    keys::{self, KeyBuilder, KeyParser},
+    shard,
+    traits::{self, Database, Entity, Key},
+    types, Model, SubModel,
+};
+
+/// Rewrite a single item, returning `None` to leave it as it is.
+///
+/// Rewrites must be idempotent. Items are rewritten again when an interrupted backfill
+/// resumes, and an item moved to a new key may be scanned a second time.
+pub type Rewrite = fn(&types::HashMap) -> Option<types::HashMap>;
+
+/// A numbered change to the items in the table, applied by backfilling every item.
+#[derive(Clone, Debug)]
+pub struct Migration {
+    pub version: u32,
+    pub name: &'static str,
+    pub rewrite: Rewrite,
+}
+
+/// Which migrations have been applied to the table, stored as an item in the table itself.
+///
+/// While a migration is being backfilled, this also holds the key that its scan resumes after.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MigrationState {
+    version: u32,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    in_progress: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    cursor_pk: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    cursor_sk: Option<String>,
+
+    // Progress of the migration in progress
+    #[serde(default)]
+    scanned: usize,
+    #[serde(default)]
+    rewritten: usize,
+
+    // These are used as dynamodb key attributes
+    pk: String,
+    sk: String,
+    model: String,
+}
+
+impl MigrationState {
+    fn new() -> Self {
+        let key = Self::key();
+
+        Self {
+            version: 0,
+            in_progress: None,
+            cursor_pk: None,
+            cursor_sk: None,
+            scanned: 0,
+            rewritten: 0,
+
+            pk: key.clone(),
+            sk: key,
+            model: Self::MODEL.to_string(),
+        }
+    }
+
+    /// The key of the state item, both its `pk` and `sk`.
+    pub fn key() -> String {
+        KeyBuilder::new().segment(Self::MODEL, "state").build()
+    }
+
+    /// Load the state, which starts at version 0 in a table that has never been migrated.
+    pub async fn load(db: &impl Database) -> Result<Self, Box<dyn Error>> {
+        let key = Self::key();
+        match db.get_item(key.clone(), key).await?.item {
+            Some(hashmap) => Ok(Self::from_hashmap(&hashmap)?),
+            None => Ok(Self::new()),
+        }
+    }
+
+    async fn save(&self, db: &impl Database) -> Result<(), Box<dyn Error>> {
+        db.put_item(self.to_hashmap()?).await?;
+        Ok(())
+    }
+
+    pub fn from_hashmap(hashmap: &types::HashMap) -> serde_dynamodb::error::Result<Self> {
+        serde_dynamodb::from_hashmap(hashmap.to_owned())
+    }
+
+    pub fn to_hashmap(&self) -> serde_dynamodb::error::Result<types::HashMap> {
+        serde_dynamodb::to_hashmap(&self)
+    }
+
+    /// The version of the last migration to complete.
+    pub fn version(&self) -> u32 {
+        self.version
+    }
+
+    /// The version of a migration that was interrupted, and will be resumed.
+    pub fn in_progress(&self) -> Option<u32> {
+        self.in_progress
+    }
+
+    // The `exclusive_start_key` of the next page of an interrupted backfill
+    fn cursor(&self, version: u32) -> Option<types::HashMap> {
+        match (self.in_progress, &self.cursor_pk, &self.cursor_sk) {
+            (Some(in_progress), Some(pk), Some(sk)) if in_progress == version => {
+                Some(traits::make_key(pk, sk))
+            }
+            _ => None,
+        }
+    }
+}
+
+impl Entity for MigrationState {
+    const MODEL: &'static str = "migration";
+
+    fn from_hashmap(hashmap: &types::HashMap) -> serde_dynamodb::error::Result<Self> {
+        Self::from_hashmap(hashmap)
+    }
+}
+
+impl traits::Key for MigrationState {
+    fn key(&self) -> (String, String) {
+        (self.pk.clone(), self.sk.clone())
+    }
+
+    fn model_key(&self) -> (String, String) {
+        (self.model.clone(), self.sk.clone())
+    }
+}
+
+/// Options for `Migrations::up`.
+#[derive(Clone, Copy, Debug)]
+pub struct MigrateOptions {
+    /// Count the items that would be rewritten, without writing anything.
+    pub dry_run: bool,
+
+    /// The number of items read by each page of the scan, the state is saved after every page.
+    pub page_size: i64,
+}
+
+impl Default for MigrateOptions {
+    fn default() -> Self {
+        Self {
+            dry_run: false,
+            page_size: 100,
+        }
+    }
+}
+
+/// What a migration did, or would do in a dry run.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Report {
+    pub version: u32,
+    pub name: &'static str,
+    pub scanned: usize,
+    pub rewritten: usize,
+}
+
+/// The migrations known to this version of the code, in order.
+pub struct Migrations {
+    migrations: Vec<Migration>,
+}
+
+impl Migrations {
+    /// A list without any migrations.
+    pub fn empty() -> Self {
+        Self { migrations: vec![] }
+    }
+
+    /// Add a migration, versions must be registered in increasing order.
+    pub fn register(mut self, version: u32, name: &'static str, rewrite: Rewrite) -> Self {
+        let latest = self
+            .migrations
+            .last()
+            .map_or(0, |migration| migration.version);
+        assert!(
+            version > latest,
+            "migration {} registered out of order",
+            version
+        );
+
+        self.migrations.push(Migration {
+            version,
+            name,
+            rewrite,
+        });
+        self
+    }
+
+    pub fn migrations(&self) -> &[Migration] {
+        &self.migrations
+    }
+
+    /// The migrations that have not been completed, in the order they will be applied.
+    pub fn pending(&self, state: &MigrationState) -> Vec<&Migration> {
+        self.migrations
+            .iter()
+            .filter(|migration| migration.version > state.version)
+            .collect()
+    }
+
+    /// Apply every pending migration, resuming one that was interrupted.
+    pub async fn up(
+        &self,
+        db: &impl Database,
+        options: &MigrateOptions,
+    ) -> Result<Vec<Report>, Box<dyn Error>> {
+        let mut state = MigrationState::load(db).await?;
+        let mut reports = vec![];
+
+        for migration in self.pending(&state) {
+            reports.push(backfill(db, &mut state, migration, options).await?);
+        }
+
+        Ok(reports)
+    }
+}
+
+impl Default for Migrations {
+    fn default() -> Self {
+        Self::empty().register(1, "submodel-sk-by-name", submodel_sk_by_name)
+    }
+}
+
+// Scan the whole table, rewriting each item, saving the state after every page
+async fn backfill(
+    db: &impl Database,
+    state: &mut MigrationState,
+    migration: &Migration,
+    options: &MigrateOptions,
+) -> Result<Report, Box<dyn Error>> {
+    let mut cursor = state.cursor(migration.version);
+    let mut report = Report {
+        version: migration.version,
+        name: migration.name,
+        scanned: 0,
+        rewritten: 0,
+    };
+    if cursor.is_some() {
+        report.scanned = state.scanned;
+        report.rewritten = state.rewritten;
+    }
+
+    loop {
+        let res = db
+            .scan_from(None::<String>, Some(options.page_size), cursor)
+            .await?;
+
+        for item in res.items.unwrap_or_default() {
+            if collection::model(&item) == Some(MigrationState::MODEL) {
+                continue;
+            }
+            report.scanned += 1;
+
+            let rewritten = match (migration.rewrite)(&item) {
+                Some(rewritten) if rewritten != item => rewritten,
+                _ => continue,
+            };
+            report.rewritten += 1;
+
+            if !options.dry_run {
+                rewrite(db, item, rewritten).await?;
+            }
+        }
+
+        cursor = res.last_evaluated_key;
+        if options.dry_run {
+            if cursor.is_none() {
+                return Ok(report);
+            }
+            continue;
+        }
+
+        match &cursor {
+            Some(key) => {
+                let (pk, sk) = key.key();
+                state.in_progress = Some(migration.version);
+                state.cursor_pk = Some(pk);
+                state.cursor_sk = Some(sk);
+                state.scanned = report.scanned;
+                state.rewritten = report.rewritten;
+                state.save(db).await?;
+            }
+            None => {
+                *state = MigrationState {
+                    version: migration.version,
+                    ..MigrationState::new()
+                };
+                state.save(db).await?;
+                return Ok(report);
+            }
+        }
+    }
+}
+
+// An item that moves to a new key is deleted from the old one in the same transaction
+async fn rewrite(
+    db: &impl Database,
+    item: types::HashMap,
+    rewritten: types::HashMap,
+) -> Result<(), Box<dyn Error>> {
+    let (pk, sk) = item.key();
+    let transact_items = if (pk.clone(), sk.clone()) == rewritten.key() {
+        vec![db.put(rewritten)]
+    } else {
+        vec![db.delete(pk, sk), db.put(rewritten)]
+    };
+
+    db.transact_write_items(transact_items).await?;
+    Ok(())
+}
+
+/// Migration 1: SubModels were keyed `model#{parent}#submodel#{name}`,
+/// and are now keyed by their name alone, see `SubModel::key_of`.
+pub fn submodel_sk_by_name(item: &types::HashMap) -> Option<types::HashMap> {
+    if collection::model(item) != Some(SubModel::MODEL) {
+        return None;
+    }
+
+    // The name was appended to the parent's key as it was, without escaping,
+    // so it is taken as the raw rest of the key rather than parsed
+    let (_, sk) = item.key();
+    let separator = KeyBuilder::new().label_prefix(SubModel::MODEL);
+    let (parent_key, name) = sk.split_once(&format!("{}{}", keys::DELIMITER, separator))?;
+
+    let mut parser = KeyParser::new(parent_key);
+    let parent = parser.segment(Model::MODEL).ok()?;
+    parser.finish().ok()?;
+
+    let sk = SubModel::key_of(name).build();
+    let pk = shard::key(Model::key_of(&parent), &sk, SubModel::SHARDS);
+
+    let mut item = item.clone();
+    item.insert("pk".to_string(), types::string(pk));
+    item.insert("sk".to_string(), types::string(sk));
+    Some(item)
+}
@@ -0,0 +1,233 @@
+use rusoto_core::{
+    credential::{DefaultCredentialsProvider, StaticProvider},
+    request::{DispatchSignedRequestFuture, HttpResponse},
+    signature::{SignedRequest, SignedRequestPayload},
+    ByteStream, DispatchSignedRequest, HttpClient, HttpDispatchError, Region,
+};
+use rusoto_dynamodb::DynamoDbClient;
+use rusoto_dynamodbstreams::DynamoDbStreamsClient;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use std::{
+    collections::VecDeque,
+    error::Error,
+    fs,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use crate::DDB;
+
+/// A single request/response pair, as sent over the wire to DynamoDB.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Exchange {
+    /// The API operation, from the `X-Amz-Target` header.
+    pub target: String,
+    pub request: Value,
+    pub status: u16,
+    pub response: Value,
+}
+
+/// Handle to the exchanges captured by a recording `DDB`.
+pub struct Recording {
+    path: PathBuf,
+    exchanges: Arc<Mutex<Vec<Exchange>>>,
+}
+
+impl Recording {
+    /// Write the captured exchanges to the fixture file.
+    pub fn save(&self) -> Result<(), Box<dyn Error>> {
+        let exchanges = self.exchanges.lock().map_err(|e| e.to_string())?;
+        let fixture = serde_json::to_string_pretty(&*exchanges)?;
+        fs::write(&self.path, fixture + "\n")?;
+
+        Ok(())
+    }
+}
+
+/// Create a `DDB` that captures all of its traffic with the real service.
+///
+/// Call `Recording::save` to write the fixture file once the requests have been made.
+pub fn recording<T, P>(
+    region: Region,
+    table_name: T,
+    path: P,
+) -> Result<(DDB, Recording), Box<dyn Error>>
+where
+    T: Into<String>,
+    P: AsRef<Path>,
+{
+    let exchanges = Arc::new(Mutex::new(vec![]));
+    let recorder = Recorder {
+        client: HttpClient::new()?,
+        exchanges: exchanges.clone(),
+    };
+
+    let client = DynamoDbClient::new_with(recorder, DefaultCredentialsProvider::new()?, region);
+    let recording = Recording {
+        path: path.as_ref().to_path_buf(),
+        exchanges,
+    };
+
+    Ok((DDB::new(client, table_name), recording))
+}
+
+/// Create a `DDB` that serves responses from a fixture file written by a `Recording`.
+///
+/// Requests are answered in the order they were recorded,
+/// no connection to DynamoDB (or credentials) is needed.
+/// Each request must match the recorded one exactly,
+/// so anything time dependent should be created with a `clock::FixedClock`.
+pub fn replaying<T, P>(table_name: T, path: P) -> Result<DDB, Box<dyn Error>>
+where
+    T: Into<String>,
+    P: AsRef<Path>,
+{
+    let (replayer, credentials, region) = replay(path)?;
+
+    let client = DynamoDbClient::new_with(replayer, credentials, region);
+    Ok(DDB::new(client, table_name))
+}
+
+/// Create a `DynamoDbStreamsClient` that serves responses from a fixture file,
+/// in the same way as `replaying`.
+pub fn replaying_stream<P>(path: P) -> Result<DynamoDbStreamsClient, Box<dyn Error>>
+where
+    P: AsRef<Path>,
+{
+    let (replayer, credentials, region) = replay(path)?;
+
+    let client = DynamoDbStreamsClient::new_with(replayer, credentials, region);
+    Ok(client)
+}
+
+fn replay<P: AsRef<Path>>(path: P) -> Result<(Replayer, StaticProvider, Region), Box<dyn Error>> {
+    let fixture = fs::read_to_string(path)?;
+    let exchanges: VecDeque<Exchange> = serde_json::from_str(&fixture)?;
+
+    let replayer = Replayer {
+        exchanges: Mutex::new(exchanges),
+    };
+    let credentials = StaticProvider::new_minimal("replay".to_string(), "replay".to_string());
+    let region = Region::Custom {
+        name: "replay".to_string(),
+        endpoint: "http://replay.invalid".to_string(),
+    };
+
+    Ok((replayer, credentials, region))
+}
+
+struct Recorder {
+    client: HttpClient,
+    exchanges: Arc<Mutex<Vec<Exchange>>>,
+}
+
+impl DispatchSignedRequest for Recorder {
+    fn dispatch(
+        &self,
+        request: SignedRequest,
+        timeout: Option<Duration>,
+    ) -> DispatchSignedRequestFuture {
+        let target = target(&request);
+        let body = payload(&request);
+
+        let response = self.client.dispatch(request, timeout);
+        let exchanges = self.exchanges.clone();
+
+        Box::pin(async move {
+            let response = response.await?.buffer().await?;
+
+            let exchange = Exchange {
+                target,
+                request: body,
+                status: response.status.as_u16(),
+                response: json(&response.body),
+            };
+            exchanges
+                .lock()
+                .map_err(|e| HttpDispatchError::new(e.to_string()))?
+                .push(exchange);
+
+            Ok::<_, HttpDispatchError>(HttpResponse {
+                status: response.status,
+                body: ByteStream::from(response.body.to_vec()),
+                headers: response.headers,
+            })
+        })
+    }
+}
+
+struct Replayer {
+    exchanges: Mutex<VecDeque<Exchange>>,
+}
+
+impl Replayer {
+    fn next(&self, target: &str, request: &Value) -> Result<HttpResponse, HttpDispatchError> {
+        let exchange = self
+            .exchanges
+            .lock()
+            .map_err(|e| HttpDispatchError::new(e.to_string()))?
+            .pop_front()
+            .ok_or_else(|| HttpDispatchError::new(format!("replay: no fixture for {}", target)))?;
+
+        if exchange.target != target {
+            return Err(HttpDispatchError::new(format!(
+                "replay: expected {}, got {}",
+                exchange.target, target
+            )));
+        }
+
+        if &exchange.request != request {
+            return Err(HttpDispatchError::new(format!(
+                "replay: {} request does not match the fixture\nexpected: {}\ngot: {}",
+                target, exchange.request, request
+            )));
+        }
+
+        let status = http::StatusCode::from_u16(exchange.status)
+            .map_err(|e| HttpDispatchError::new(e.to_string()))?;
+        let body = serde_json::to_vec(&exchange.response)
+            .map_err(|e| HttpDispatchError::new(e.to_string()))?;
+
+        Ok(HttpResponse {
+            status,
+            body: ByteStream::from(body),
+            headers: http::HeaderMap::new(),
+        })
+    }
+}
+
+impl DispatchSignedRequest for Replayer {
+    fn dispatch(
+        &self,
+        request: SignedRequest,
+        _timeout: Option<Duration>,
+    ) -> DispatchSignedRequestFuture {
+        let response = self.next(&target(&request), &payload(&request));
+        Box::pin(async move { response })
+    }
+}
+
+// Headers are stored in lowercase by `SignedRequest`
+fn target(request: &SignedRequest) -> String {
+    request
+        .headers()
+        .get("x-amz-target")
+        .and_then(|values| values.first())
+        .map(|value| String::from_utf8_lossy(value).into_owned())
+        .unwrap_or_default()
+}
+
+fn payload(request: &SignedRequest) -> Value {
+    match &request.payload {
+        Some(SignedRequestPayload::Buffer(bytes)) => json(bytes),
+        _ => Value::Null,
+    }
+}
+
+fn json(bytes: &[u8]) -> Value {
+    serde_json::from_slice(bytes)
+        .unwrap_or_else(|_| Value::String(String::from_utf8_lossy(bytes).into_owned()))
+}
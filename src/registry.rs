@@ -0,0 +1,119 @@
+use std::collections;
+
+use crate::{
+    collection, events::Event, history::Revision, migrate::MigrationState, traits::Entity, types,
+    unique::Guard, Edge, Model, SubModel,
+};
+
+/// An item decoded according to its `model` attribute.
+#[derive(Debug)]
+pub enum Item {
+    Model(Model),
+    SubModel(SubModel),
+    Edge(Edge),
+    Guard(Guard),
+    Migration(MigrationState),
+    Revision(Revision),
+    Event(Event),
+
+    /// An item whose `model` attribute is missing, or not registered.
+    Unknown(types::HashMap),
+}
+
+impl From<Model> for Item {
+    fn from(model: Model) -> Self {
+        Item::Model(model)
+    }
+}
+
+impl From<SubModel> for Item {
+    fn from(submodel: SubModel) -> Self {
+        Item::SubModel(submodel)
+    }
+}
+
+impl From<Edge> for Item {
+    fn from(edge: Edge) -> Self {
+        Item::Edge(edge)
+    }
+}
+
+impl From<Guard> for Item {
+    fn from(guard: Guard) -> Self {
+        Item::Guard(guard)
+    }
+}
+
+impl From<MigrationState> for Item {
+    fn from(state: MigrationState) -> Self {
+        Item::Migration(state)
+    }
+}
+
+impl From<Revision> for Item {
+    fn from(revision: Revision) -> Self {
+        Item::Revision(revision)
+    }
+}
+
+impl From<Event> for Item {
+    fn from(event: Event) -> Self {
+        Item::Event(event)
+    }
+}
+
+type Decoder = fn(&types::HashMap) -> serde_dynamodb::error::Result<Item>;
+
+fn decode_as<E>(hashmap: &types::HashMap) -> serde_dynamodb::error::Result<Item>
+where
+    E: Entity + Into<Item>,
+{
+    E::from_hashmap(hashmap).map(Into::into)
+}
+
+/// Maps values of the `model` attribute to the entity type that decodes them.
+///
+/// The default registry knows about every entity in this crate.
+pub struct Registry {
+    decoders: collections::HashMap<&'static str, Decoder>,
+}
+
+impl Registry {
+    /// A registry without any entities.
+    pub fn empty() -> Self {
+        Self {
+            decoders: collections::HashMap::new(),
+        }
+    }
+
+    pub fn register<E>(mut self) -> Self
+    where
+        E: Entity + Into<Item>,
+    {
+        self.decoders.insert(E::MODEL, decode_as::<E>);
+        self
+    }
+
+    /// Decode an item with the entity registered for its `model` attribute.
+    ///
+    /// Items of unregistered types are returned as `Item::Unknown`.
+    pub fn decode(&self, hashmap: &types::HashMap) -> serde_dynamodb::error::Result<Item> {
+        match collection::model(hashmap).and_then(|model| self.decoders.get(model)) {
+            Some(decoder) => decoder(hashmap),
+            None => Ok(Item::Unknown(hashmap.clone())),
+        }
+    }
+}
+
+impl Default for Registry {
+    fn default() -> Self {
+        Self::empty()
+            .register::<Model>()
+            .register::<SubModel>()
+            .register::<Edge>()
+            .register::<Guard>()
+            .register::<MigrationState>()
+            .register::<Revision>()
+            .register::<Event>()
+    }
+}
@@ -0,0 +1,64 @@
+use serde::de::Error as _;
+
+use crate::types;
+
+/// The attribute holding the version of the schema an item was written with.
+pub const VERSION_ATTRIBUTE: &str = "schema_version";
+
+/// Upgrade an item from one version of its schema to the next.
+///
+/// Upcasters work on the raw attributes, before the item is deserialized,
+/// so they can fill in attributes that older items are missing.
+pub type Upcaster = fn(types::HashMap) -> types::HashMap;
+
+/// The current version of a schema, one more than its number of upcasters.
+///
+/// Items written before versioning was introduced are version 1.
+pub const fn current(upcasters: &[Upcaster]) -> u32 {
+    upcasters.len() as u32 + 1
+}
+
+/// The schema version of an item, items without one are version 1.
+pub fn version(item: &types::HashMap) -> serde_dynamodb::error::Result<u32> {
+    let attribute = item.get(VERSION_ATTRIBUTE);
+    let n = match attribute.and_then(|value| value.n.as_ref()) {
+        Some(n) => n,
+        None => return Ok(1),
+    };
+
+    n.parse().map_err(|_| {
+        serde_dynamodb::error::Error::custom(format!("invalid {}: {}", VERSION_ATTRIBUTE, n))
+    })
+}
+
+/// Upgrade an item to the current version, applying each upcaster after its own version in turn.
+///
+/// `upcasters[0]` upgrades version 1 to 2, `upcasters[1]` version 2 to 3, and so on.
+/// Items from a newer version than this code knows about are rejected.
+pub fn upcast(
+    item: &types::HashMap,
+    upcasters: &[Upcaster],
+) -> serde_dynamodb::error::Result<types::HashMap> {
+    let version = version(item)?;
+    let current = current(upcasters);
+    if version == 0 || version > current {
+        return Err(serde_dynamodb::error::Error::custom(format!(
+            "unsupported {} {}, the latest is {}",
+            VERSION_ATTRIBUTE, version, current
+        )));
+    }
+
+    let mut item = item.clone();
+    for (upcaster, version) in upcasters[version as usize - 1..].iter().zip(version..) {
+        item = upcaster(item);
+        item.insert(
+            VERSION_ATTRIBUTE.to_string(),
+            types::AttributeValue {
+                n: Some((version + 1).to_string()),
+                ..Default::default()
+            },
+        );
+    }
+
+    Ok(item)
+}
@@ -0,0 +1,111 @@
+use futures::future;
+
+use crate::{
+    keys::KeyBuilder,
+    traits::{Database, QueryOptions, SortKeyCondition},
+    types,
+};
+
+/// Labels the shard segment appended to a partition key, e.g. `model#foo#shard#3`.
+pub const LABEL: &str = "shard";
+
+/// The shard, out of `shards`, that the item with the sort key `sk` is written to.
+///
+/// This uses FNV-1a, so that an item always lands in the same shard,
+/// whichever backend, platform or version of Rust wrote it.
+pub fn shard_of(sk: &str, shards: u32) -> u32 {
+    let hash = sk.bytes().fold(0x811c_9dc5_u32, |hash, byte| {
+        (hash ^ u32::from(byte)).wrapping_mul(0x0100_0193)
+    });
+
+    hash % shards.max(1)
+}
+
+/// The partition key of the item with the sort key `sk`, within the partition `pk`.
+///
+/// Without sharding, this is `pk` itself.
+pub fn key(pk: KeyBuilder, sk: &str, shards: u32) -> String {
+    if shards <= 1 {
+        return pk.build();
+    }
+
+    pk.segment(LABEL, &shard_of(sk, shards).to_string()).build()
+}
+
+/// Every partition key that items written within `pk` may have.
+pub fn partitions(pk: KeyBuilder, shards: u32) -> Vec<String> {
+    if shards <= 1 {
+        return vec![pk.build()];
+    }
+
+    (0..shards)
+        .map(|shard| pk.clone().segment(LABEL, &shard.to_string()).build())
+        .collect()
+}
+
+/// Query each of the partitions for items beginning with `sk`, merging the results.
+///
+/// The queries run concurrently, and every page of each partition is read, so that the
+/// items are returned in sort key order as though they came from a single partition.
+/// There is never a `last_evaluated_key`, since no one key could resume every partition.
+pub async fn query(db: &impl Database, partitions: Vec<String>, sk: String) -> types::QueryResult {
+    query_with(db, partitions, sk, QueryOptions::default()).await
+}
+
+/// Query each of the partitions as `query` does, with `options` such as `consistent_read`.
+///
+/// A `limit` applies to each page, and an `exclusive_start_key` is ignored.
+pub async fn query_with(
+    db: &impl Database,
+    partitions: Vec<String>,
+    sk: String,
+    options: QueryOptions,
+) -> types::QueryResult {
+    let queries = partitions
+        .into_iter()
+        .map(|pk| query_partition(db, pk, sk.clone(), options.clone()));
+
+    let mut items: Vec<types::HashMap> = future::try_join_all(queries)
+        .await?
+        .into_iter()
+        .flatten()
+        .collect();
+    items.sort_by(|a, b| sort_key(a).cmp(&sort_key(b)));
+
+    Ok(types::QueryOutput {
+        count: Some(items.len() as i64),
+        items: Some(items),
+        ..Default::default()
+    })
+}
+
+// Every page of the items in `pk` beginning with `sk`
+async fn query_partition(
+    db: &impl Database,
+    pk: String,
+    sk: String,
+    options: QueryOptions,
+) -> Result<Vec<types::HashMap>, types::RusotoError<types::QueryError>> {
+    let mut items = vec![];
+    let mut exclusive_start_key = None;
+    loop {
+        let options = QueryOptions {
+            exclusive_start_key,
+            ..options.clone()
+        };
+        let condition = SortKeyCondition::BeginsWith(sk.clone());
+        let res = db.query_with(None, pk.clone(), condition, options).await?;
+        items.extend(res.items.unwrap_or_default());
+
+        exclusive_start_key = res.last_evaluated_key;
+        if exclusive_start_key.is_none() {
+            return Ok(items);
+        }
+    }
+}
+
+// Items are ordered by `sk` within a partition, and by `pk` between shards
+fn sort_key(item: &types::HashMap) -> (Option<&str>, Option<&str>) {
+    let attribute = |name: &str| item.get(name).and_then(|value| value.s.as_deref());
+    (attribute("sk"), attribute("pk"))
+}
@@ -0,0 +1,123 @@
+use chrono::{DateTime, SecondsFormat, Utc};
+use uuid::Uuid;
+
+use crate::keys::KeyError;
+
+/// A value that can be encoded in a key, such that keys sort in the same order as the values.
+///
+/// DynamoDB compares sort keys as UTF-8 bytes, so `10` would otherwise sort before `9`.
+/// Encoded values never contain the key delimiter.
+///
+/// ```
+/// use single_table::{keys::KeyBuilder, sortable::SortKey};
+///
+/// assert!(9u64.encode() < 10u64.encode());
+/// assert!((-1i64).encode() < 0i64.encode());
+///
+/// let key = KeyBuilder::new().sortable("event", &42u64).build();
+/// assert_eq!(key, "event#000000000000002a");
+/// ```
+pub trait SortKey: Sized {
+    fn encode(&self) -> String;
+    fn decode(encoded: &str) -> Result<Self, KeyError>;
+}
+
+// Integers are written as fixed width hex, which sorts the same as the numbers themselves
+fn encode_bits(bits: u64) -> String {
+    format!("{:016x}", bits)
+}
+
+fn decode_bits(encoded: &str) -> Result<u64, KeyError> {
+    let valid = encoded.len() == 16
+        && encoded
+            .bytes()
+            .all(|b| b.is_ascii_digit() || (b'a'..=b'f').contains(&b));
+    if !valid {
+        return Err(KeyError::InvalidSortKey(encoded.to_string()));
+    }
+
+    u64::from_str_radix(encoded, 16).map_err(|_| KeyError::InvalidSortKey(encoded.to_string()))
+}
+
+const SIGN_BIT: u64 = 1 << 63;
+
+impl SortKey for u64 {
+    fn encode(&self) -> String {
+        encode_bits(*self)
+    }
+
+    fn decode(encoded: &str) -> Result<Self, KeyError> {
+        decode_bits(encoded)
+    }
+}
+
+/// Negative numbers sort first: the sign bit is flipped, so that they are below the positives.
+impl SortKey for i64 {
+    fn encode(&self) -> String {
+        encode_bits(*self as u64 ^ SIGN_BIT)
+    }
+
+    fn decode(encoded: &str) -> Result<Self, KeyError> {
+        Ok((decode_bits(encoded)? ^ SIGN_BIT) as i64)
+    }
+}
+
+/// Floats sort by their IEEE 754 total order, so `-0.0` sorts before `0.0`,
+/// and NaNs sort below negative infinity, or above positive infinity, depending on their sign.
+impl SortKey for f64 {
+    fn encode(&self) -> String {
+        // Positive floats get the sign bit set, negative floats have every bit inverted,
+        // so that larger magnitudes sort first
+        let bits = self.to_bits();
+        if bits & SIGN_BIT == 0 {
+            encode_bits(bits | SIGN_BIT)
+        } else {
+            encode_bits(!bits)
+        }
+    }
+
+    fn decode(encoded: &str) -> Result<Self, KeyError> {
+        let bits = decode_bits(encoded)?;
+        if bits & SIGN_BIT == 0 {
+            Ok(f64::from_bits(!bits))
+        } else {
+            Ok(f64::from_bits(bits ^ SIGN_BIT))
+        }
+    }
+}
+
+/// Timestamps are readable RFC 3339 in UTC, with a fixed number of fractional digits,
+/// e.g. `2020-07-01T12:00:00.000000000Z`.
+///
+/// Only the years 0 to 9999 keep a fixed width, and sort correctly.
+impl SortKey for DateTime<Utc> {
+    fn encode(&self) -> String {
+        self.to_rfc3339_opts(SecondsFormat::Nanos, true)
+    }
+
+    fn decode(encoded: &str) -> Result<Self, KeyError> {
+        DateTime::parse_from_rfc3339(encoded)
+            .map(|datetime| datetime.with_timezone(&Utc))
+            .map_err(|_| KeyError::InvalidSortKey(encoded.to_string()))
+    }
+}
+
+/// UUIDs are hyphenated lowercase hex, which sorts in the same order as their bytes.
+///
+/// Only time based UUIDs, such as version 6 or 7, sort in the order they were created.
+impl SortKey for Uuid {
+    fn encode(&self) -> String {
+        self.to_hyphenated().to_string()
+    }
+
+    fn decode(encoded: &str) -> Result<Self, KeyError> {
+        let uuid =
+            Uuid::parse_str(encoded).map_err(|_| KeyError::InvalidSortKey(encoded.to_string()))?;
+
+        // Other forms would parse, but not sort alongside the encoded ones
+        if uuid.encode() != encoded {
+            return Err(KeyError::InvalidSortKey(encoded.to_string()));
+        }
+        Ok(uuid)
+    }
+}
@@ -0,0 +1,249 @@
+use rusoto_dynamodbstreams::{
+    AttributeValue as StreamAttributeValue, DescribeStreamInput, DynamoDbStreams,
+    DynamoDbStreamsClient, GetRecordsInput, GetShardIteratorInput, Record,
+};
+use serde::{Deserialize, Serialize};
+
+use std::{
+    collections::{self, BTreeMap},
+    error::Error,
+    fs,
+    path::Path,
+};
+
+use crate::types;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum EventName {
+    Insert,
+    Modify,
+    Remove,
+}
+
+/// A single change to an item, with its images from before and after the change.
+///
+/// These are produced by `MemoryDB::subscribe` and by consuming DynamoDB Streams
+/// with a `StreamConsumer`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ChangeRecord {
+    pub event_name: EventName,
+    pub sequence_number: String,
+    pub keys: types::HashMap,
+    pub old_image: Option<types::HashMap>,
+    pub new_image: Option<types::HashMap>,
+}
+
+impl ChangeRecord {
+    /// Describe the change from `old_image` to `new_image`,
+    /// or `None` if neither image exists.
+    pub fn new(
+        sequence_number: String,
+        old_image: Option<types::HashMap>,
+        new_image: Option<types::HashMap>,
+    ) -> Option<Self> {
+        let event_name = match (&old_image, &new_image) {
+            (None, Some(_)) => EventName::Insert,
+            (Some(_), Some(_)) => EventName::Modify,
+            (Some(_), None) => EventName::Remove,
+            (None, None) => return None,
+        };
+
+        let keys = {
+            let image = new_image.as_ref().or_else(|| old_image.as_ref())?;
+            let mut keys = types::HashMap::new();
+            for attribute_name in &["pk", "sk"] {
+                if let Some(value) = image.get(*attribute_name) {
+                    keys.insert(attribute_name.to_string(), value.clone());
+                }
+            }
+            keys
+        };
+
+        Some(Self {
+            event_name,
+            sequence_number,
+            keys,
+            old_image,
+            new_image,
+        })
+    }
+}
+
+/// The last sequence number processed from each shard of a stream.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Checkpoints(BTreeMap<String, String>);
+
+impl Checkpoints {
+    /// Load checkpoints from a file, starting afresh if it does not exist yet.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, Box<dyn Error>> {
+        match fs::read_to_string(path) {
+            Ok(checkpoints) => Ok(serde_json::from_str(&checkpoints)?),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(Box::new(e)),
+        }
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), Box<dyn Error>> {
+        fs::write(path, serde_json::to_string_pretty(self)? + "\n")?;
+        Ok(())
+    }
+
+    pub fn get(&self, shard_id: &str) -> Option<&str> {
+        self.0.get(shard_id).map(String::as_str)
+    }
+
+    pub fn set<S: Into<String>>(&mut self, shard_id: S, sequence_number: S) {
+        self.0.insert(shard_id.into(), sequence_number.into());
+    }
+}
+
+/// Reads `ChangeRecord`s from the DynamoDB Stream of a table.
+///
+/// The table must have been created with a stream of `NEW_AND_OLD_IMAGES`,
+/// as `DDB::create_table` does.
+pub struct StreamConsumer {
+    client: DynamoDbStreamsClient,
+    stream_arn: String,
+    checkpoints: Checkpoints,
+}
+
+impl StreamConsumer {
+    pub fn new<S: Into<String>>(
+        client: DynamoDbStreamsClient,
+        stream_arn: S,
+        checkpoints: Checkpoints,
+    ) -> Self {
+        Self {
+            client,
+            stream_arn: stream_arn.into(),
+            checkpoints,
+        }
+    }
+
+    pub fn checkpoints(&self) -> &Checkpoints {
+        &self.checkpoints
+    }
+
+    /// Fetch the next batch of records from every shard of the stream.
+    ///
+    /// Shards are read from their checkpoint, or from the oldest available record,
+    /// and the checkpoints are advanced past the records returned.
+    pub async fn poll(&mut self) -> Result<Vec<ChangeRecord>, Box<dyn Error>> {
+        let mut records = vec![];
+
+        for shard_id in self.shard_ids().await? {
+            let shard_iterator = self.shard_iterator(&shard_id).await?;
+            let shard_iterator = match shard_iterator {
+                Some(shard_iterator) => shard_iterator,
+                None => continue,
+            };
+
+            let output = self
+                .client
+                .get_records(GetRecordsInput {
+                    shard_iterator,
+                    ..Default::default()
+                })
+                .await?;
+
+            for record in output.records.unwrap_or_default() {
+                if let Some(record) = change_record(record) {
+                    self.checkpoints
+                        .set(shard_id.clone(), record.sequence_number.clone());
+                    records.push(record);
+                }
+            }
+        }
+
+        Ok(records)
+    }
+
+    // Parent shards are listed before their children
+    async fn shard_ids(&self) -> Result<Vec<String>, Box<dyn Error>> {
+        let mut shard_ids = vec![];
+        let mut exclusive_start_shard_id = None;
+
+        loop {
+            let output = self
+                .client
+                .describe_stream(DescribeStreamInput {
+                    stream_arn: self.stream_arn.clone(),
+                    exclusive_start_shard_id,
+                    ..Default::default()
+                })
+                .await?;
+
+            let description = output.stream_description.unwrap_or_default();
+            for shard in description.shards.unwrap_or_default() {
+                shard_ids.extend(shard.shard_id);
+            }
+
+            exclusive_start_shard_id = description.last_evaluated_shard_id;
+            if exclusive_start_shard_id.is_none() {
+                return Ok(shard_ids);
+            }
+        }
+    }
+
+    async fn shard_iterator(&self, shard_id: &str) -> Result<Option<String>, Box<dyn Error>> {
+        let (shard_iterator_type, sequence_number) = match self.checkpoints.get(shard_id) {
+            Some(sequence_number) => ("AFTER_SEQUENCE_NUMBER", Some(sequence_number.to_string())),
+            None => ("TRIM_HORIZON", None),
+        };
+
+        let output = self
+            .client
+            .get_shard_iterator(GetShardIteratorInput {
+                stream_arn: self.stream_arn.clone(),
+                shard_id: shard_id.to_string(),
+                shard_iterator_type: shard_iterator_type.to_string(),
+                sequence_number,
+            })
+            .await?;
+
+        Ok(output.shard_iterator)
+    }
+}
+
+fn change_record(record: Record) -> Option<ChangeRecord> {
+    let event_name = match record.event_name.as_deref()? {
+        "INSERT" => EventName::Insert,
+        "MODIFY" => EventName::Modify,
+        "REMOVE" => EventName::Remove,
+        _ => return None,
+    };
+    let stream_record = record.dynamodb?;
+
+    Some(ChangeRecord {
+        event_name,
+        sequence_number: stream_record.sequence_number.unwrap_or_default(),
+        keys: stream_record.keys.map(image).unwrap_or_default(),
+        old_image: stream_record.old_image.map(image),
+        new_image: stream_record.new_image.map(image),
+    })
+}
+
+// DynamoDB Streams has its own copy of the AttributeValue type
+fn image(image: collections::HashMap<String, StreamAttributeValue>) -> types::HashMap {
+    image
+        .into_iter()
+        .map(|(name, value)| (name, attribute_value(value)))
+        .collect()
+}
+
+fn attribute_value(value: StreamAttributeValue) -> types::AttributeValue {
+    types::AttributeValue {
+        b: value.b,
+        bool: value.bool,
+        bs: value.bs,
+        l: value
+            .l
+            .map(|values| values.into_iter().map(attribute_value).collect()),
+        m: value.m.map(image),
+        n: value.n,
+        ns: value.ns,
+        null: value.null,
+        s: value.s,
+        ss: value.ss,
+    }
+}
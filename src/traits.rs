@@ -1,5 +1,82 @@
-use crate::types;
+use crate::{keys::KeyBuilder, types};
 use async_trait::async_trait;
+use serde::Serialize;
+use std::collections;
+
+/// The most operations DynamoDB accepts in a single transaction.
+pub const MAX_TRANSACTION_ITEMS: usize = 100;
+
+/// The reason each operation of a cancelled transaction failed, in request order,
+/// as listed in the message of a `TransactionCanceled` error.
+///
+/// Operations that did not cause the cancellation are reported as `"None"`.
+pub fn cancellation_reasons(message: &str) -> Vec<&str> {
+    let reasons = message
+        .rfind('[')
+        .and_then(|start| Some((start, message[start..].find(']')? + start)));
+
+    match reasons {
+        Some((start, end)) => message[start + 1..end].split(',').map(str::trim).collect(),
+        None => vec![],
+    }
+}
+
+/// How a query matches the sort key of the items in a partition.
+#[derive(Clone, Debug, PartialEq)]
+pub enum SortKeyCondition {
+    /// Every item in the partition.
+    All,
+    BeginsWith(String),
+    /// Sort keys from the first to the second, inclusive.
+    Between(String, String),
+}
+
+impl SortKeyCondition {
+    pub fn matches(&self, sk: &str) -> bool {
+        match self {
+            Self::All => true,
+            Self::BeginsWith(prefix) => sk.starts_with(prefix.as_str()),
+            Self::Between(low, high) => low.as_str() <= sk && sk <= high.as_str(),
+        }
+    }
+}
+
+/// Options for `Database::query_with`.
+#[derive(Clone, Debug, Default)]
+pub struct QueryOptions {
+    /// Return items in descending sort key order, rather than ascending.
+    pub descending: bool,
+
+    /// The most items to return in a page.
+    pub limit: Option<i64>,
+
+    /// Continue after the `last_evaluated_key` of a previous page.
+    pub exclusive_start_key: Option<types::HashMap>,
+
+    /// Read the latest items, as `ReadOptions::consistent_read` does.
+    ///
+    /// Queries of a global secondary index are always eventually consistent,
+    /// and fail if this is set.
+    pub consistent_read: bool,
+}
+
+/// Options for `Database::get_item_with`.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ReadOptions {
+    /// Read the item as of the latest successful write, rather than an eventually
+    /// consistent copy of it which may not reflect a write made a moment ago.
+    ///
+    /// Costs twice as much read capacity.
+    pub consistent_read: bool,
+}
+
+/// Options for `Database::transact_write_items_with`.
+#[derive(Clone, Debug, Default)]
+pub struct TransactOptions {
+    /// Makes the request idempotent: DynamoDB applies a request with the same token only once,
+    /// within ten minutes of the first.
+    pub client_request_token: Option<String>,
+}
 
 #[async_trait]
 pub trait Database: TransactionalOperations {
@@ -14,7 +91,23 @@ pub trait Database: TransactionalOperations {
         limit: Option<i64>,
     ) -> types::ScanResult;
 
+    /// Scan from after `exclusive_start_key`, the `last_evaluated_key` of a previous page.
+    async fn scan_from<S: Into<String> + Send>(
+        &self,
+        index: Option<S>,
+        limit: Option<i64>,
+        exclusive_start_key: Option<types::HashMap>,
+    ) -> types::ScanResult;
+
     async fn get_item<S: Into<String> + Send>(&self, pk: S, sk: S) -> types::GetItemResult;
+
+    /// Get an item, as `get_item` does, with `options`.
+    async fn get_item_with<S: Into<String> + Send>(
+        &self,
+        pk: S,
+        sk: S,
+        options: ReadOptions,
+    ) -> types::GetItemResult;
     async fn put_item<H: Into<types::HashMap> + Key + Send>(
         &self,
         hashmap: H,
@@ -26,10 +119,54 @@ pub trait Database: TransactionalOperations {
         sk: S,
     ) -> types::QueryResult;
 
+    /// Query the partition `pk` for items whose sort key matches `condition`.
+    ///
+    /// `query` is the same as a `BeginsWith` condition, with the default options.
+    async fn query_with<S: Into<String> + Send>(
+        &self,
+        index: Option<S>,
+        pk: S,
+        condition: SortKeyCondition,
+        options: QueryOptions,
+    ) -> types::QueryResult;
+
+    /// Atomically add `by` to the Number `attribute` of an item, returning its new value.
+    ///
+    /// Missing attributes start from zero, and missing items are created.
+    async fn increment<S: Into<String> + Send>(
+        &self,
+        pk: S,
+        sk: S,
+        attribute: S,
+        by: i64,
+    ) -> types::UpdateItemResult;
+
     async fn transact_write_items(
         &self,
         transact_items: Vec<types::TransactWriteItem>,
     ) -> types::TransactWriteItemsResult;
+
+    /// Read up to `MAX_TRANSACTION_ITEMS` items by key, as a consistent snapshot.
+    ///
+    /// There is one response for each key, in the same order, without an item if it is missing.
+    async fn transact_get_items(
+        &self,
+        keys: Vec<(String, String)>,
+    ) -> types::TransactGetItemsResult;
+
+    /// Write items in a transaction, as `transact_write_items` does, with `options`.
+    async fn transact_write_items_with(
+        &self,
+        transact_items: Vec<types::TransactWriteItem>,
+        options: TransactOptions,
+    ) -> types::TransactWriteItemsResult;
+
+    async fn update_time_to_live<S: Into<String> + Send>(
+        &self,
+        attribute_name: S,
+        enabled: bool,
+    ) -> types::UpdateTimeToLiveResult;
+    async fn describe_time_to_live(&self) -> types::DescribeTimeToLiveResult;
 }
 
 pub trait TransactionalOperations {
@@ -45,6 +182,46 @@ pub trait TransactionalOperations {
         M: Into<String> + Send;
 
     fn put(&self, hashmap: types::HashMap) -> types::TransactWriteItem;
+
+    /// Put an item, only if there is no item with the same key already.
+    fn put_new(&self, hashmap: types::HashMap) -> types::TransactWriteItem;
+
+    /// Set the attributes in `hashmap` on the item with its key, creating the item if missing.
+    ///
+    /// Unlike `put`, attributes left out of `hashmap` are kept, such as counters
+    /// maintained with `add`, except those named in `remove`.
+    fn update_attributes(
+        &self,
+        hashmap: types::HashMap,
+        remove: &[&str],
+    ) -> types::TransactWriteItem;
+
+    fn delete<PK, SK>(&self, pk: PK, sk: SK) -> types::TransactWriteItem
+    where
+        PK: Into<String> + Send,
+        SK: Into<String> + Send;
+
+    /// Add `by` to the Number `attribute` of an item, creating either if missing.
+    fn add<PK, SK, A>(&self, pk: PK, sk: SK, attribute: A, by: i64) -> types::TransactWriteItem
+    where
+        PK: Into<String> + Send,
+        SK: Into<String> + Send,
+        A: Into<String> + Send;
+
+    /// Add `by` to the Number `attribute` of an existing item of type `model`.
+    fn add_to_existing<PK, SK, M, A>(
+        &self,
+        pk: PK,
+        sk: SK,
+        model: M,
+        attribute: A,
+        by: i64,
+    ) -> types::TransactWriteItem
+    where
+        PK: Into<String> + Send,
+        SK: Into<String> + Send,
+        M: Into<String> + Send,
+        A: Into<String> + Send;
 }
 
 impl<T: Database> TransactionalOperations for T {
@@ -99,6 +276,204 @@ impl<T: Database> TransactionalOperations for T {
             ..Default::default()
         }
     }
+
+    fn put_new(&self, hashmap: types::HashMap) -> types::TransactWriteItem {
+        let mut names = Names::new();
+        names.insert("#pk".to_string(), "pk".to_string());
+
+        types::TransactWriteItem {
+            put: Some(types::Put {
+                table_name: self.table_name(),
+                item: hashmap,
+                condition_expression: Some("attribute_not_exists(#pk)".to_string()),
+                expression_attribute_names: Some(names),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+
+    fn update_attributes(
+        &self,
+        hashmap: types::HashMap,
+        remove: &[&str],
+    ) -> types::TransactWriteItem {
+        let (pk, sk) = hashmap.key();
+        let remove: Vec<&str> = remove
+            .iter()
+            .filter(|name| !hashmap.contains_key(**name))
+            .copied()
+            .collect();
+
+        // Sorted, so that the same item always makes the same expression
+        let mut attributes: Vec<(String, types::AttributeValue)> = hashmap
+            .into_iter()
+            .filter(|(name, _)| name != "pk" && name != "sk")
+            .collect();
+        attributes.sort_by(|a, b| a.0.cmp(&b.0));
+
+        let mut names = Names::new();
+        let mut values = types::HashMap::new();
+        let mut set = vec![];
+        for (i, (name, value)) in attributes.into_iter().enumerate() {
+            names.insert(format!("#set{}", i), name);
+            values.insert(format!(":set{}", i), value);
+            set.push(format!("#set{} = :set{}", i, i));
+        }
+
+        let mut update_expression = format!("SET {}", set.join(", "));
+        if !remove.is_empty() {
+            let mut removed = vec![];
+            for (i, name) in remove.into_iter().enumerate() {
+                names.insert(format!("#remove{}", i), name.to_string());
+                removed.push(format!("#remove{}", i));
+            }
+            update_expression.push_str(&format!(" REMOVE {}", removed.join(", ")));
+        }
+
+        types::TransactWriteItem {
+            update: Some(types::Update {
+                table_name: self.table_name(),
+                key: make_key(pk, sk),
+                update_expression,
+                expression_attribute_names: Some(names),
+                expression_attribute_values: Some(values),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+
+    fn delete<PK, SK>(&self, pk: PK, sk: SK) -> types::TransactWriteItem
+    where
+        PK: Into<String> + Send,
+        SK: Into<String> + Send,
+    {
+        types::TransactWriteItem {
+            delete: Some(types::Delete {
+                table_name: self.table_name(),
+                key: make_key(pk, sk),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+
+    fn add<PK, SK, A>(&self, pk: PK, sk: SK, attribute: A, by: i64) -> types::TransactWriteItem
+    where
+        PK: Into<String> + Send,
+        SK: Into<String> + Send,
+        A: Into<String> + Send,
+    {
+        let (update_expression, names, values) = add_expression(attribute, by);
+
+        types::TransactWriteItem {
+            update: Some(types::Update {
+                table_name: self.table_name(),
+                key: make_key(pk, sk),
+                update_expression,
+                expression_attribute_names: Some(names),
+                expression_attribute_values: Some(values),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+
+    fn add_to_existing<PK, SK, M, A>(
+        &self,
+        pk: PK,
+        sk: SK,
+        model: M,
+        attribute: A,
+        by: i64,
+    ) -> types::TransactWriteItem
+    where
+        PK: Into<String> + Send,
+        SK: Into<String> + Send,
+        M: Into<String> + Send,
+        A: Into<String> + Send,
+    {
+        let (update_expression, names, mut values) = add_expression(attribute, by);
+        values.insert(
+            ":model".to_string(),
+            types::AttributeValue {
+                s: Some(model.into()),
+                ..Default::default()
+            },
+        );
+
+        types::TransactWriteItem {
+            update: Some(types::Update {
+                table_name: self.table_name(),
+                key: make_key(pk, sk),
+                update_expression,
+                condition_expression: Some("model = :model".to_string()),
+                expression_attribute_names: Some(names),
+                expression_attribute_values: Some(values),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+}
+
+type Names = collections::HashMap<String, String>;
+
+/// The condition of a put, update or delete, with its attribute names and values,
+/// so that more conditions can be added to it.
+pub(crate) fn condition_mut(
+    transact_item: &mut types::TransactWriteItem,
+) -> Option<(
+    &mut Option<String>,
+    &mut Option<Names>,
+    &mut Option<types::HashMap>,
+)> {
+    if let Some(put) = transact_item.put.as_mut() {
+        return Some((
+            &mut put.condition_expression,
+            &mut put.expression_attribute_names,
+            &mut put.expression_attribute_values,
+        ));
+    }
+
+    if let Some(update) = transact_item.update.as_mut() {
+        return Some((
+            &mut update.condition_expression,
+            &mut update.expression_attribute_names,
+            &mut update.expression_attribute_values,
+        ));
+    }
+
+    if let Some(delete) = transact_item.delete.as_mut() {
+        return Some((
+            &mut delete.condition_expression,
+            &mut delete.expression_attribute_names,
+            &mut delete.expression_attribute_values,
+        ));
+    }
+
+    None
+}
+
+/// The update expression, names and values to add `by` to a Number `attribute`.
+pub(crate) fn add_expression<A>(attribute: A, by: i64) -> (String, Names, types::HashMap)
+where
+    A: Into<String>,
+{
+    let mut names = Names::new();
+    names.insert("#attribute".to_string(), attribute.into());
+
+    let mut values = types::HashMap::new();
+    values.insert(
+        ":by".to_string(),
+        types::AttributeValue {
+            n: Some(by.to_string()),
+            ..Default::default()
+        },
+    );
+
+    ("ADD #attribute :by".to_string(), names, values)
 }
 
 pub trait Key {
@@ -106,6 +481,61 @@ pub trait Key {
     fn model_key(&self) -> (String, String);
 }
 
+/// A type of item stored in the table, told apart from others by its `model` attribute.
+pub trait Entity: Sized {
+    /// The value of the `model` attribute for items of this type.
+    const MODEL: &'static str;
+
+    /// The number of partitions that items of this type are spread across, within their parent.
+    ///
+    /// Writes go to the shard chosen from an item's sort key, and reads query every shard,
+    /// see `shard`. One shard, the default, keeps items in the partition of their parent.
+    const SHARDS: u32 = 1;
+
+    fn from_hashmap(hashmap: &types::HashMap) -> serde_dynamodb::error::Result<Self>;
+}
+
+/// An entity that a `Transaction` can write.
+///
+/// An entity that keeps other items in step with its own when it is saved, such as the
+/// guards of its unique values, writes them here too, so that a transaction saves it
+/// the same way it saves itself.
+pub trait Transactable: Entity + Serialize {
+    /// The write that replaces the entity's item, see `Transaction::put`.
+    fn put(
+        &self,
+        db: &impl TransactionalOperations,
+    ) -> serde_dynamodb::error::Result<types::TransactWriteItem> {
+        Ok(db.put(serde_dynamodb::to_hashmap(self)?))
+    }
+
+    /// The update that sets the entity's attributes on its item, see `Transaction::update`.
+    fn update(
+        &self,
+        db: &impl TransactionalOperations,
+    ) -> serde_dynamodb::error::Result<types::TransactWriteItem> {
+        Ok(db.update_attributes(serde_dynamodb::to_hashmap(self)?, &[]))
+    }
+
+    /// The items to write for the `write` of the entity's own item, `write` included.
+    fn transact_items(
+        &self,
+        _db: &impl TransactionalOperations,
+        write: types::TransactWriteItem,
+    ) -> serde_dynamodb::error::Result<Vec<types::TransactWriteItem>> {
+        Ok(vec![write])
+    }
+}
+
+/// An entity stored within the partitions of a parent Model, such as a SubModel.
+pub trait Child: Entity {
+    /// The `sk` of the child called `name`.
+    fn key_of(name: &str) -> KeyBuilder;
+
+    /// A `begins_with` prefix of the `sk` of every child of this type in a partition.
+    fn prefix() -> String;
+}
+
 impl Key for types::HashMap {
     fn key(&self) -> (String, String) {
         (
@@ -146,6 +576,21 @@ impl Key for types::HashMap {
     }
 }
 
+/// The key of the item targeted by an operation in a transaction.
+pub fn transact_item_key(transact_item: &types::TransactWriteItem) -> (String, String) {
+    if let Some(put) = &transact_item.put {
+        put.item.key()
+    } else if let Some(delete) = &transact_item.delete {
+        delete.key.key()
+    } else if let Some(update) = &transact_item.update {
+        update.key.key()
+    } else if let Some(check) = &transact_item.condition_check {
+        check.key.key()
+    } else {
+        Default::default()
+    }
+}
+
 pub fn make_key<PK, SK>(pk: PK, sk: SK) -> types::HashMap
 where
     PK: Into<String>,
@@ -0,0 +1,311 @@
+use std::{collections, fmt};
+
+use crate::{
+    traits::{self, Database, Entity, Key, TransactOptions, Transactable},
+    types,
+};
+
+/// The kind of an operation within a transaction.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OperationKind {
+    Put,
+    PutNew,
+    Update,
+    Delete,
+    ConditionCheck,
+}
+
+/// An operation within a transaction, and the item it targets.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Operation {
+    pub kind: OperationKind,
+    pub pk: String,
+    pub sk: String,
+}
+
+impl fmt::Display for Operation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?} of ({}, {})", self.kind, self.pk, self.sk)
+    }
+}
+
+/// An operation that caused a transaction to be cancelled.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Cancellation {
+    /// The position of the operation in the transaction.
+    pub index: usize,
+    pub operation: Operation,
+    /// Such as `ConditionalCheckFailed`.
+    pub reason: String,
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum TransactionError {
+    #[error("transaction has no operations")]
+    Empty,
+    #[error("transaction has {0} operations, over {max}", max = traits::MAX_TRANSACTION_ITEMS)]
+    TooManyOperations(usize),
+    #[error("transaction has more than one operation on ({0}, {1})")]
+    DuplicateItem(String, String),
+    #[error("could not serialize item: {0}")]
+    Serialization(#[from] serde_dynamodb::error::Error),
+    #[error("transaction cancelled: {}", describe(.0))]
+    Cancelled(Vec<Cancellation>),
+    #[error(transparent)]
+    Rusoto(#[from] types::RusotoError<types::TransactWriteItemsError>),
+}
+
+fn describe(cancellations: &[Cancellation]) -> String {
+    let reasons: Vec<String> = cancellations
+        .iter()
+        .map(|cancellation| format!("{} by {}", cancellation.reason, cancellation.operation))
+        .collect();
+    reasons.join(", ")
+}
+
+/// A builder for a transaction of typed operations, run with `TransactWriteItems`.
+///
+/// ```ignore
+/// Transaction::new(&db)
+///     .check_exists::<Model>(&parent, &parent)
+///     .put_new(&submodel)
+///     .client_request_token(token)
+///     .run()
+///     .await?;
+/// ```
+///
+/// Entities are written the way they save themselves, see `Transactable`, so writing a
+/// Model also claims and releases the guards of its email.
+///
+/// Limits that DynamoDB would reject the request for are checked before it is sent.
+/// A cancelled transaction reports which operations caused it, see `TransactionError::Cancelled`.
+pub struct Transaction<'a, D: Database> {
+    db: &'a D,
+    transact_items: Vec<types::TransactWriteItem>,
+    operations: Vec<Operation>,
+    client_request_token: Option<String>,
+
+    // The first item that could not be serialized, reported by `run`
+    error: Option<serde_dynamodb::error::Error>,
+}
+
+impl<'a, D: Database> Transaction<'a, D> {
+    pub fn new(db: &'a D) -> Self {
+        Self {
+            db,
+            transact_items: vec![],
+            operations: vec![],
+            client_request_token: None,
+            error: None,
+        }
+    }
+
+    /// Make the transaction idempotent, retrying it with the same token applies it only once.
+    pub fn client_request_token<S: Into<String>>(mut self, token: S) -> Self {
+        self.client_request_token = Some(token.into());
+        self
+    }
+
+    /// Put an entity, replacing any item with the same key.
+    ///
+    /// Attributes the entity keeps on its item, such as a Model's SubModel count, are kept.
+    pub fn put<E: Transactable>(self, entity: &E) -> Self {
+        let write = entity.put(self.db);
+        self.write(entity, OperationKind::Put, write)
+    }
+
+    /// Put an entity, only if there is no item with the same key already.
+    pub fn put_new<E: Transactable>(self, entity: &E) -> Self {
+        let write = serde_dynamodb::to_hashmap(entity).map(|hashmap| self.db.put_new(hashmap));
+        self.write(entity, OperationKind::PutNew, write)
+    }
+
+    /// Update an existing entity of type `E`, keeping the attributes its item has
+    /// that the entity leaves out, such as counters maintained with `add`.
+    pub fn update<E: Transactable>(self, entity: &E) -> Self {
+        let write = entity.update(self.db).map(if_exists::<E>);
+        self.write(entity, OperationKind::Update, write)
+    }
+
+    /// Delete an entity.
+    pub fn delete<E: Entity + Key>(self, entity: &E) -> Self {
+        let (pk, sk) = entity.key();
+        let transact_item = self.db.delete(pk, sk);
+        self.push(OperationKind::Delete, transact_item)
+    }
+
+    /// Delete the item with a key, whatever its type.
+    pub fn delete_key<PK, SK>(self, pk: PK, sk: SK) -> Self
+    where
+        PK: Into<String> + Send,
+        SK: Into<String> + Send,
+    {
+        let transact_item = self.db.delete(pk, sk);
+        self.push(OperationKind::Delete, transact_item)
+    }
+
+    /// Check that an item of type `E` exists, without changing it.
+    pub fn check_exists<E: Entity>(self, pk: &str, sk: &str) -> Self {
+        let transact_item = self.db.condition_check_exists(pk, sk, E::MODEL);
+        self.push(OperationKind::ConditionCheck, transact_item)
+    }
+
+    /// Add `by` to the Number `attribute` of an existing item of type `E`.
+    pub fn add<E: Entity>(self, pk: &str, sk: &str, attribute: &str, by: i64) -> Self {
+        let transact_item = self.db.add_to_existing(pk, sk, E::MODEL, attribute, by);
+        self.push(OperationKind::Update, transact_item)
+    }
+
+    /// Add an operation built with `TransactionalOperations`, or by hand.
+    pub fn operation(self, transact_item: types::TransactWriteItem) -> Self {
+        let kind = if transact_item.condition_check.is_some() {
+            OperationKind::ConditionCheck
+        } else if transact_item.update.is_some() {
+            OperationKind::Update
+        } else if transact_item.delete.is_some() {
+            OperationKind::Delete
+        } else {
+            match transact_item.put.as_ref() {
+                Some(put) if put.condition_expression.is_some() => OperationKind::PutNew,
+                _ => OperationKind::Put,
+            }
+        };
+        self.push(kind, transact_item)
+    }
+
+    /// The operations added so far, in order.
+    pub fn operations(&self) -> &[Operation] {
+        &self.operations
+    }
+
+    pub fn len(&self) -> usize {
+        self.operations.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.operations.is_empty()
+    }
+
+    /// Check the transaction against DynamoDB's limits, without running it.
+    pub fn validate(&self) -> Result<(), TransactionError> {
+        if self.operations.is_empty() {
+            return Err(TransactionError::Empty);
+        }
+        if self.operations.len() > traits::MAX_TRANSACTION_ITEMS {
+            return Err(TransactionError::TooManyOperations(self.operations.len()));
+        }
+
+        let mut keys = collections::HashSet::new();
+        for operation in &self.operations {
+            if !keys.insert((&operation.pk, &operation.sk)) {
+                return Err(TransactionError::DuplicateItem(
+                    operation.pk.clone(),
+                    operation.sk.clone(),
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Run the transaction, so that either every operation applies or none do.
+    pub async fn run(mut self) -> Result<types::TransactWriteItemsOutput, TransactionError> {
+        if let Some(e) = self.error.take() {
+            return Err(e.into());
+        }
+        self.validate()?;
+
+        let Self {
+            db,
+            transact_items,
+            operations,
+            client_request_token,
+            ..
+        } = self;
+        let options = TransactOptions {
+            client_request_token,
+        };
+
+        match db.transact_write_items_with(transact_items, options).await {
+            Ok(output) => Ok(output),
+            Err(types::RusotoError::Service(
+                types::TransactWriteItemsError::TransactionCanceled(message),
+            )) => {
+                let cancellations = cancellations(&operations, &message);
+                if cancellations.is_empty() {
+                    return Err(TransactionError::Rusoto(types::RusotoError::Service(
+                        types::TransactWriteItemsError::TransactionCanceled(message),
+                    )));
+                }
+                Err(TransactionError::Cancelled(cancellations))
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    // Add the `write` of an entity's item, along with the items the entity keeps in step
+    fn write<E: Transactable>(
+        mut self,
+        entity: &E,
+        kind: OperationKind,
+        write: serde_dynamodb::error::Result<types::TransactWriteItem>,
+    ) -> Self {
+        let transact_items = write.and_then(|write| {
+            let key = traits::transact_item_key(&write);
+            Ok((key, entity.transact_items(self.db, write)?))
+        });
+        let (key, transact_items) = match transact_items {
+            Ok(transact_items) => transact_items,
+            Err(e) => {
+                self.error.get_or_insert(e);
+                return self;
+            }
+        };
+
+        for transact_item in transact_items {
+            if traits::transact_item_key(&transact_item) == key {
+                self = self.push(kind, transact_item);
+            } else {
+                self = self.operation(transact_item);
+            }
+        }
+        self
+    }
+
+    fn push(mut self, kind: OperationKind, transact_item: types::TransactWriteItem) -> Self {
+        let (pk, sk) = traits::transact_item_key(&transact_item);
+        self.operations.push(Operation { kind, pk, sk });
+        self.transact_items.push(transact_item);
+        self
+    }
+}
+
+// Make an update apply only to an existing item of type `E`, as well as any condition it has
+fn if_exists<E: Entity>(mut update: types::TransactWriteItem) -> types::TransactWriteItem {
+    if let Some((condition, _, values)) = traits::condition_mut(&mut update) {
+        let exists = "model = :model".to_string();
+        *condition = Some(match condition.take() {
+            Some(existing) => format!("{} AND {}", existing, exists),
+            None => exists,
+        });
+        values
+            .get_or_insert_with(Default::default)
+            .insert(":model".to_string(), types::string(E::MODEL));
+    }
+    update
+}
+
+// Match the reasons in a `TransactionCanceled` message to the operations, in request order
+fn cancellations(operations: &[Operation], message: &str) -> Vec<Cancellation> {
+    traits::cancellation_reasons(message)
+        .into_iter()
+        .zip(operations)
+        .enumerate()
+        .filter(|(_, (reason, _))| *reason != "None")
+        .map(|(index, (reason, operation))| Cancellation {
+            index,
+            operation: operation.clone(),
+            reason: reason.to_string(),
+        })
+        .collect()
+}
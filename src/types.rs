@@ -12,10 +12,18 @@ pub use rusoto_dynamodb::{
     ScanError, ScanInput, ScanOutput,
     GetItemError, GetItemInput, GetItemOutput,
     PutItemError, PutItemInput, PutItemOutput,
+    UpdateItemError, UpdateItemInput, UpdateItemOutput,
     QueryError, QueryInput, QueryOutput,
+    UpdateTimeToLiveError, UpdateTimeToLiveInput, UpdateTimeToLiveOutput,
+    DescribeTimeToLiveError, DescribeTimeToLiveInput, DescribeTimeToLiveOutput,
+    TimeToLiveSpecification, TimeToLiveDescription,
 
     TransactWriteItemsError, TransactWriteItemsInput, TransactWriteItemsOutput, TransactWriteItem,
-    ConditionCheck, Put,
+    ConditionCheck, Delete, Put, Update,
+    TransactGetItemsError, TransactGetItemsInput, TransactGetItemsOutput, TransactGetItem,
+    Get, ItemResponse,
+
+    Capacity, ConsumedCapacity,
 };
 
 #[rustfmt::skip]
@@ -31,10 +39,25 @@ pub type DescribeTableResult = Result<DescribeTableOutput, RusotoError<DescribeT
 pub type ScanResult = Result<ScanOutput, RusotoError<ScanError>>;
 pub type GetItemResult = Result<GetItemOutput, RusotoError<GetItemError>>;
 pub type PutItemResult = Result<PutItemOutput, RusotoError<PutItemError>>;
+pub type UpdateItemResult = Result<UpdateItemOutput, RusotoError<UpdateItemError>>;
 pub type QueryResult = Result<QueryOutput, RusotoError<QueryError>>;
+pub type UpdateTimeToLiveResult =
+    Result<UpdateTimeToLiveOutput, RusotoError<UpdateTimeToLiveError>>;
+pub type DescribeTimeToLiveResult =
+    Result<DescribeTimeToLiveOutput, RusotoError<DescribeTimeToLiveError>>;
 pub type TransactWriteItemsResult =
     Result<TransactWriteItemsOutput, RusotoError<TransactWriteItemsError>>;
+pub type TransactGetItemsResult =
+    Result<TransactGetItemsOutput, RusotoError<TransactGetItemsError>>;
 
 // STS
 pub type GetCallerIdentityResult =
     Result<GetCallerIdentityResponse, RusotoError<GetCallerIdentityError>>;
+
+/// A string `AttributeValue`.
+pub fn string<S: Into<String>>(s: S) -> AttributeValue {
+    AttributeValue {
+        s: Some(s.into()),
+        ..Default::default()
+    }
+}
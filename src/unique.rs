@@ -0,0 +1,269 @@
+use serde::{Deserialize, Serialize};
+
+use std::collections;
+
+use crate::{
+    keys::KeyBuilder,
+    traits::{self, Entity, TransactionalOperations},
+    types, validation, ValidationError,
+};
+
+/// A unique value is already claimed by another item.
+#[derive(thiserror::Error, Clone, Debug, PartialEq)]
+#[error("{attribute} is already taken: {value}")]
+pub struct UniqueConflict {
+    pub attribute: String,
+    pub value: String,
+}
+
+/// Claims a unique value for the item that owns it.
+///
+/// DynamoDB has no unique secondary indexes, so a guard item keyed by
+/// the attribute and its value is written in the same transaction as its owner,
+/// with `attribute_not_exists`. A second owner of the same value fails that condition.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Guard {
+    attribute: String,
+    value: String,
+
+    // The key of the item holding the value
+    owner_pk: String,
+    owner_sk: String,
+
+    // These are used as dynamodb key attributes
+    pk: String,
+    sk: String,
+    model: String,
+}
+
+impl Guard {
+    pub fn new(attribute: &str, value: &str, owner_pk: &str, owner_sk: &str) -> Self {
+        let key = Self::key_of(attribute, value);
+
+        Self {
+            attribute: attribute.to_string(),
+            value: value.to_string(),
+            owner_pk: owner_pk.to_string(),
+            owner_sk: owner_sk.to_string(),
+
+            pk: key.clone(),
+            sk: key,
+            model: Self::MODEL.to_string(),
+        }
+    }
+
+    /// The key of the guard for `value`, both its `pk` and `sk`, e.g. `unique#email#x`.
+    ///
+    /// `attribute` is used as a label, so it must not contain the delimiter.
+    pub fn key_of(attribute: &str, value: &str) -> String {
+        let label = KeyBuilder::new().label_prefix(Self::MODEL);
+        let value = KeyBuilder::new().segment(attribute, value).build();
+        format!("{}{}", label, value)
+    }
+
+    pub fn from_hashmap(hashmap: &types::HashMap) -> serde_dynamodb::error::Result<Self> {
+        serde_dynamodb::from_hashmap(hashmap.to_owned())
+    }
+
+    pub fn to_hashmap(&self) -> serde_dynamodb::error::Result<types::HashMap> {
+        serde_dynamodb::to_hashmap(&self)
+    }
+
+    pub fn attribute(&self) -> &str {
+        &self.attribute
+    }
+
+    pub fn value(&self) -> &str {
+        &self.value
+    }
+
+    /// The key of the item holding the value.
+    pub fn owner(&self) -> (String, String) {
+        (self.owner_pk.clone(), self.owner_sk.clone())
+    }
+}
+
+impl Entity for Guard {
+    const MODEL: &'static str = "unique";
+
+    fn from_hashmap(hashmap: &types::HashMap) -> serde_dynamodb::error::Result<Self> {
+        Self::from_hashmap(hashmap)
+    }
+}
+
+impl traits::Key for Guard {
+    fn key(&self) -> (String, String) {
+        (self.pk.clone(), self.sk.clone())
+    }
+
+    fn model_key(&self) -> (String, String) {
+        (self.model.clone(), self.sk.clone())
+    }
+}
+
+/// A unique attribute of an item, with the value stored in the table and the value being saved.
+#[derive(Clone, Debug)]
+pub struct Constraint<'a> {
+    pub attribute: &'static str,
+    pub stored: Option<&'a str>,
+    pub value: Option<&'a str>,
+}
+
+impl<'a> Constraint<'a> {
+    fn changed(&self) -> bool {
+        self.stored != self.value
+    }
+}
+
+/// Check a unique value, whose guard key must fit within DynamoDB's key size limits.
+pub fn validate_value(attribute: &'static str, value: &str) -> Result<(), ValidationError> {
+    validation::validate_name(attribute, value)?;
+
+    let key = Guard::key_of(attribute, value);
+    validation::validate_key(&key, &key)
+}
+
+/// The transaction that saves an item, claiming and releasing the guards of its unique values.
+pub struct Save {
+    pub transact_items: Vec<types::TransactWriteItem>,
+
+    // The position of each new guard within `transact_items`
+    claims: Vec<(usize, UniqueConflict)>,
+}
+
+impl Save {
+    /// Build the transaction, returning `None` if no unique value has changed.
+    ///
+    /// `write` puts or updates the owner, and is made conditional on the stored values being
+    /// unchanged, see `if_unchanged`. Without any changes, `write` should still be made
+    /// conditional on them before it is written on its own.
+    pub fn new(
+        db: &impl TransactionalOperations,
+        (pk, sk): (String, String),
+        constraints: &[Constraint],
+        write: types::TransactWriteItem,
+    ) -> Result<Option<Self>, serde_dynamodb::error::Error> {
+        if !constraints.iter().any(Constraint::changed) {
+            return Ok(None);
+        }
+
+        let mut transact_items = vec![];
+        let mut claims = vec![];
+
+        for constraint in constraints.iter().filter(|constraint| constraint.changed()) {
+            if let Some(value) = constraint.value {
+                let guard = Guard::new(constraint.attribute, value, &pk, &sk);
+                claims.push((
+                    transact_items.len(),
+                    UniqueConflict {
+                        attribute: constraint.attribute.to_string(),
+                        value: value.to_string(),
+                    },
+                ));
+                transact_items.push(db.put_new(guard.to_hashmap()?));
+            }
+            if let Some(stored) = constraint.stored {
+                let key = Guard::key_of(constraint.attribute, stored);
+                transact_items.push(db.delete(key.clone(), key));
+            }
+        }
+        transact_items.push(if_unchanged(constraints, write));
+
+        Ok(Some(Self {
+            transact_items,
+            claims,
+        }))
+    }
+
+    /// Run the transaction, reporting a guard that already exists as a `UniqueConflict`.
+    pub async fn run(
+        self,
+        db: &impl traits::Database,
+    ) -> Result<types::TransactWriteItemsOutput, Box<dyn std::error::Error>> {
+        let Self {
+            transact_items,
+            claims,
+        } = self;
+
+        match db.transact_write_items(transact_items).await {
+            Ok(output) => Ok(output),
+            Err(types::RusotoError::Service(
+                types::TransactWriteItemsError::TransactionCanceled(message),
+            )) => {
+                let reasons = traits::cancellation_reasons(&message);
+                for (i, conflict) in claims {
+                    if reasons.get(i) == Some(&"ConditionalCheckFailed") {
+                        return Err(Box::new(conflict));
+                    }
+                }
+                Err(Box::new(types::RusotoError::Service(
+                    types::TransactWriteItemsError::TransactionCanceled(message),
+                )))
+            }
+            Err(e) => Err(Box::new(e)),
+        }
+    }
+}
+
+/// Make the write of an owner conditional on its stored unique values being unchanged.
+///
+/// Otherwise a guard could be released on behalf of a value the owner no longer holds,
+/// or orphaned by writing over an owner without knowing the values it holds.
+/// Conditions already on `write` are kept.
+pub fn if_unchanged(
+    constraints: &[Constraint],
+    mut write: types::TransactWriteItem,
+) -> types::TransactWriteItem {
+    let mut conditions = vec![];
+    let mut names = collections::HashMap::new();
+    let mut values = types::HashMap::new();
+
+    for (i, constraint) in constraints.iter().enumerate() {
+        let name = format!("#unique{}", i);
+        names.insert(name.clone(), constraint.attribute.to_string());
+
+        match constraint.stored {
+            Some(stored) => {
+                let placeholder = format!(":unique{}", i);
+                conditions.push(format!("{} = {}", name, placeholder));
+                values.insert(
+                    placeholder,
+                    types::AttributeValue {
+                        s: Some(stored.to_string()),
+                        ..Default::default()
+                    },
+                );
+            }
+            None => conditions.push(format!("attribute_not_exists({})", name)),
+        }
+    }
+
+    if let Some((condition, write_names, write_values)) = traits::condition_mut(&mut write) {
+        if let Some(condition) = condition.take() {
+            conditions.insert(0, condition);
+        }
+        names.extend(write_names.take().unwrap_or_default());
+        values.extend(write_values.take().unwrap_or_default());
+
+        *condition = Some(conditions.join(" AND "));
+        *write_names = Some(names);
+        if !values.is_empty() {
+            *write_values = Some(values);
+        }
+    }
+    write
+}
+
+/// Delete the guards of the unique values held by an item that is being deleted.
+pub fn release(
+    db: &impl TransactionalOperations,
+    constraints: &[Constraint],
+) -> Vec<types::TransactWriteItem> {
+    constraints
+        .iter()
+        .filter_map(|constraint| {
+            let key = Guard::key_of(constraint.attribute, constraint.stored?);
+            Some(db.delete(key.clone(), key))
+        })
+        .collect()
+}
@@ -0,0 +1,132 @@
+use crate::types::{AttributeValue, HashMap};
+
+pub const MAX_PARTITION_KEY_BYTES: usize = 2048;
+pub const MAX_SORT_KEY_BYTES: usize = 1024;
+pub const MAX_ITEM_BYTES: usize = 400 * 1024;
+pub const MAX_NESTING_DEPTH: usize = 32;
+
+/// Reasons an item would be rejected by DynamoDB with a `ValidationException`.
+///
+/// The messages are those returned by DynamoDB, so that `MemoryDB` errors read the same.
+#[derive(thiserror::Error, Debug, PartialEq)]
+pub enum ValidationError {
+    #[error("{0} name cannot be empty")]
+    EmptyName(&'static str),
+    #[error("One or more parameter values were invalid: Missing the key {0} in the item")]
+    MissingKey(String),
+    #[error("One or more parameter values are not valid. The AttributeValue for a key attribute cannot contain an empty string value. Key: {0}")]
+    EmptyKey(String),
+    #[error("One or more parameter values were invalid: Size of hashkey has exceeded the maximum size limit of2048 bytes")]
+    PartitionKeyTooLong,
+    #[error("One or more parameter values were invalid: Aggregated size of all range keys has exceeded the size limit of 1024 bytes")]
+    SortKeyTooLong,
+    #[error("Item size has exceeded the maximum allowed size")]
+    ItemTooLarge,
+    #[error("Nesting Levels have exceeded supported limits")]
+    NestingTooDeep,
+}
+
+/// Check a name that will be used within an entity's keys.
+pub fn validate_name(entity: &'static str, name: &str) -> Result<(), ValidationError> {
+    if name.is_empty() {
+        return Err(ValidationError::EmptyName(entity));
+    }
+
+    Ok(())
+}
+
+/// Check the key attributes, as DynamoDB does for every read and write.
+pub fn validate_key(pk: &str, sk: &str) -> Result<(), ValidationError> {
+    if pk.is_empty() {
+        return Err(ValidationError::EmptyKey("pk".to_string()));
+    }
+    if sk.is_empty() {
+        return Err(ValidationError::EmptyKey("sk".to_string()));
+    }
+    if pk.len() > MAX_PARTITION_KEY_BYTES {
+        return Err(ValidationError::PartitionKeyTooLong);
+    }
+    if sk.len() > MAX_SORT_KEY_BYTES {
+        return Err(ValidationError::SortKeyTooLong);
+    }
+
+    Ok(())
+}
+
+/// Check an item before it is written.
+pub fn validate_item(item: &HashMap) -> Result<(), ValidationError> {
+    let pk = key_attribute(item, "pk")?;
+    let sk = key_attribute(item, "sk")?;
+    validate_key(pk, sk)?;
+
+    if item.values().map(depth).max().unwrap_or_default() > MAX_NESTING_DEPTH {
+        return Err(ValidationError::NestingTooDeep);
+    }
+    if item_size(item) > MAX_ITEM_BYTES {
+        return Err(ValidationError::ItemTooLarge);
+    }
+
+    Ok(())
+}
+
+fn key_attribute<'a>(item: &'a HashMap, name: &str) -> Result<&'a str, ValidationError> {
+    item.get(name)
+        .and_then(|value| value.s.as_deref())
+        .ok_or_else(|| ValidationError::MissingKey(name.to_string()))
+}
+
+/// The size of an item, as DynamoDB counts it towards the 400KB limit.
+///
+/// This is the length of each attribute name, plus the size of its value.
+pub fn item_size(item: &HashMap) -> usize {
+    item.iter()
+        .map(|(name, value)| name.len() + value_size(value))
+        .sum()
+}
+
+// Documents have 3 bytes of overhead, plus 1 byte per element
+fn value_size(value: &AttributeValue) -> usize {
+    if let Some(s) = &value.s {
+        s.len()
+    } else if let Some(n) = &value.n {
+        number_size(n)
+    } else if let Some(b) = &value.b {
+        b.len()
+    } else if value.bool.is_some() || value.null.is_some() {
+        1
+    } else if let Some(ss) = &value.ss {
+        ss.iter().map(String::len).sum()
+    } else if let Some(ns) = &value.ns {
+        ns.iter().map(|n| number_size(n)).sum()
+    } else if let Some(bs) = &value.bs {
+        bs.iter().map(|b| b.len()).sum()
+    } else if let Some(l) = &value.l {
+        3 + l.iter().map(|value| 1 + value_size(value)).sum::<usize>()
+    } else if let Some(m) = &value.m {
+        3 + m
+            .iter()
+            .map(|(name, value)| name.len() + 1 + value_size(value))
+            .sum::<usize>()
+    } else {
+        0
+    }
+}
+
+// One byte per two significant digits, plus one.
+// Numbers are stored as a mantissa and exponent, so leading and trailing zeros are free.
+fn number_size(n: &str) -> usize {
+    let digits: String = n.chars().filter(char::is_ascii_digit).collect();
+    let digits = digits.trim_start_matches('0').trim_end_matches('0');
+
+    (digits.len() + 1) / 2 + 1
+}
+
+fn depth(value: &AttributeValue) -> usize {
+    if let Some(l) = &value.l {
+        1 + l.iter().map(depth).max().unwrap_or_default()
+    } else if let Some(m) = &value.m {
+        1 + m.values().map(depth).max().unwrap_or_default()
+    } else {
+        0
+    }
+}
@@ -0,0 +1,75 @@
+use rstest::rstest;
+use rstest_reuse::*;
+
+use single_table::*;
+use traits::Database;
+
+use super::*;
+
+#[apply(state)]
+fn test_delete_cascade<DB>(state: State<DB>) -> TestResult
+where
+    DB: Database + Send + Sync,
+{
+    insert_models(&state.db)?;
+
+    let deleted = smol::run(Model::delete_cascade(&state.db, "foo"))?;
+    assert_eq!(deleted, 3);
+
+    let items = smol::run(state.db.query(None, "model#foo", "model#foo"))?;
+    assert_eq!(items.count, Some(0));
+
+    Ok(())
+}
+
+#[apply(state)]
+fn test_delete_with_children<DB>(state: State<DB>) -> TestResult
+where
+    DB: Database + Send + Sync,
+{
+    insert_models(&state.db)?;
+
+    // Deleting the parent alone would orphan its SubModels
+    assert!(smol::run(Model::delete(&state.db, "foo")).is_err());
+    assert!(smol::run(Model::get(&state.db, "foo")).is_ok());
+
+    Ok(())
+}
+
+#[apply(state)]
+fn test_delete_without_children<DB>(state: State<DB>) -> TestResult
+where
+    DB: Database + Send + Sync,
+{
+    let mut foo = Model::new("foo", 1)?;
+    smol::run(foo.save(&state.db))?;
+
+    smol::run(Model::delete(&state.db, "foo"))?;
+    assert!(smol::run(Model::get(&state.db, "foo")).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_delete_cascade_chunks() -> TestResult {
+    let db = memorydb();
+    let mut foo = Model::new("foo", 1)?;
+
+    // More SubModels than fit in a single transaction
+    smol::run(async {
+        foo.save(&db).await?;
+        for i in 0..250 {
+            SubModel::new(format!("{:03}", i), foo.clone())?
+                .save(&db)
+                .await?;
+        }
+
+        let deleted = Model::delete_cascade(&db, "foo").await?;
+        assert_eq!(deleted, 251);
+
+        let items = db.scan(None::<String>, None).await?;
+        assert_eq!(items.count, Some(0));
+
+        Ok(())
+    })
+}
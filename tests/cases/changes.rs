@@ -0,0 +1,156 @@
+use chrono::{Duration, TimeZone, Utc};
+use std::sync::Arc;
+
+use clock::ManualClock;
+use single_table::*;
+use stream::{Checkpoints, EventName, StreamConsumer};
+use traits::{Database, Key};
+
+use super::*;
+
+// Written by hand, in the format of the responses from DynamoDB Streams
+const STREAM_FIXTURE: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/stream.json");
+const STREAM_ARN: &str =
+    "arn:aws:dynamodb:ddblocal:000000000000:table/single-table-replay/stream/2020-07-01T12:00:00.000";
+
+#[test]
+fn test_memorydb_change_stream() -> TestResult {
+    let db = mem::memorydb();
+    let mut changes = db.subscribe();
+
+    let mut foo = Model::new("foo", 1)?;
+    let mut bar = SubModel::new("bar", foo.clone())?;
+
+    smol::run(async {
+        foo.save(&db).await?;
+        Model::new("foo", 2)?.save(&db).await?;
+        bar.save(&db).await?;
+
+        // A failed transaction does not change anything
+        assert!(SubModel::new("baz", Model::new("missing", 0)?)?
+            .save(&db)
+            .await
+            .is_err());
+
+        Ok::<_, Box<dyn Error>>(())
+    })?;
+
+    let insert = changes.try_next()?.ok_or("insert")?;
+    assert_eq!(insert.event_name, EventName::Insert);
+    assert_eq!(insert.old_image, None);
+    assert_eq!(
+        Model::from_hashmap(&insert.new_image.ok_or("new")?)?.value(),
+        1
+    );
+
+    let modify = changes.try_next()?.ok_or("modify")?;
+    assert_eq!(modify.event_name, EventName::Modify);
+    assert_eq!(
+        Model::from_hashmap(&modify.old_image.ok_or("old")?)?.value(),
+        1
+    );
+    assert_eq!(
+        Model::from_hashmap(&modify.new_image.ok_or("new")?)?.value(),
+        2
+    );
+    assert!(modify.sequence_number > insert.sequence_number);
+
+    let insert = changes.try_next()?.ok_or("insert")?;
+    assert_eq!(insert.event_name, EventName::Insert);
+    assert_eq!(insert.keys.key(), (bar.pk(), bar.sk()));
+
+    assert!(changes.try_next().is_err(), "no more changes");
+    Ok(())
+}
+
+#[test]
+fn test_memorydb_change_stream_expiry() -> TestResult {
+    let start = Utc.ymd(2020, 7, 1).and_hms(12, 0, 0);
+    let clock = Arc::new(ManualClock::new(start));
+    let db = mem::memorydb().with_clock(clock.clone());
+
+    let mut session = Model::new("session", 1)?.expire_at(start);
+
+    smol::run(async {
+        db.update_time_to_live(TTL_ATTRIBUTE, true).await?;
+        session.save(&db).await?;
+
+        let mut changes = db.subscribe();
+        clock.advance(Duration::seconds(1));
+        assert_eq!(db.sweep_expired().await, 1);
+
+        let remove = changes.try_next()?.ok_or("remove")?;
+        assert_eq!(remove.event_name, EventName::Remove);
+        assert_eq!(remove.new_image, None);
+        assert_eq!(
+            Model::from_hashmap(&remove.old_image.ok_or("old")?)?.name(),
+            "session"
+        );
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_stream_consumer_replay() -> TestResult {
+    let client = record::replaying_stream(STREAM_FIXTURE)?;
+    let mut consumer = StreamConsumer::new(client, STREAM_ARN, Checkpoints::default());
+
+    // Every shard is read from the start, parents before their children
+    let records = smol::run(consumer.poll())?;
+    let events: Vec<_> = records.iter().map(|record| record.event_name).collect();
+    assert_eq!(
+        events,
+        vec![EventName::Insert, EventName::Modify, EventName::Remove]
+    );
+
+    let modify = &records[1];
+    assert_eq!(modify.sequence_number, "101");
+    assert_eq!(
+        modify.keys.key(),
+        ("model#foo".to_string(), "model#foo".to_string())
+    );
+    let a_number = |image: &Option<types::HashMap>| {
+        image
+            .as_ref()
+            .and_then(|image| image.get("a_number"))
+            .and_then(|value| value.n.clone())
+    };
+    assert_eq!(a_number(&modify.old_image), Some("1".to_string()));
+    assert_eq!(a_number(&modify.new_image), Some("2".to_string()));
+    assert_eq!(records[2].new_image, None);
+
+    assert_eq!(consumer.checkpoints().get("shardId-1"), Some("101"));
+    assert_eq!(consumer.checkpoints().get("shardId-2"), Some("200"));
+
+    // The next poll resumes after the checkpoints, which stay where they were
+    assert!(smol::run(consumer.poll())?.is_empty());
+    assert_eq!(consumer.checkpoints().get("shardId-1"), Some("101"));
+    assert_eq!(consumer.checkpoints().get("shardId-2"), Some("200"));
+
+    Ok(())
+}
+
+#[test]
+fn test_checkpoints_round_trip() -> TestResult {
+    let path = std::env::temp_dir().join(format!("checkpoints-{}.json", uuid::Uuid::new_v4()));
+
+    // A missing file starts afresh
+    let mut checkpoints = Checkpoints::load(&path)?;
+    assert_eq!(checkpoints.get("shardId-1"), None);
+
+    checkpoints.set("shardId-1", "101");
+    checkpoints.set("shardId-2", "200");
+    checkpoints.set("shardId-1", "102");
+    checkpoints.save(&path)?;
+
+    let loaded = Checkpoints::load(&path);
+    std::fs::remove_file(&path)?;
+
+    let loaded = loaded?;
+    assert_eq!(loaded.get("shardId-1"), Some("102"));
+    assert_eq!(loaded.get("shardId-2"), Some("200"));
+    assert_eq!(loaded.get("shardId-3"), None);
+
+    Ok(())
+}
@@ -0,0 +1,158 @@
+use chrono::{Duration, TimeZone, Utc};
+use rstest::rstest;
+use rstest_reuse::*;
+use std::sync::Arc;
+
+use clock::ManualClock;
+use single_table::*;
+use traits::{Database, QueryOptions, ReadOptions, SortKeyCondition};
+
+use super::*;
+
+const CONSISTENT: ReadOptions = ReadOptions {
+    consistent_read: true,
+};
+
+fn consistent_query() -> QueryOptions {
+    QueryOptions {
+        consistent_read: true,
+        ..Default::default()
+    }
+}
+
+#[apply(state)]
+fn test_consistent_reads<DB>(state: State<DB>) -> TestResult
+where
+    DB: Database + Send + Sync,
+{
+    smol::run(async {
+        let foo = Model::new("foo", 1)?;
+        foo.clone().save(&state.db).await?;
+
+        let res = state
+            .db
+            .get_item_with(foo.pk(), foo.sk(), CONSISTENT)
+            .await?;
+        assert!(res.item.is_some());
+
+        let res = state
+            .db
+            .query_with(None, foo.pk(), SortKeyCondition::All, consistent_query())
+            .await?;
+        assert_eq!(res.items.unwrap_or_default().len(), 1);
+
+        // Global secondary indexes are only ever eventually consistent
+        let res = state
+            .db
+            .query_with(
+                Some("model".to_string()),
+                Model::MODEL.to_string(),
+                SortKeyCondition::All,
+                consistent_query(),
+            )
+            .await;
+        assert!(res.is_err());
+
+        Ok(())
+    })
+}
+
+// The number of Models in the `model` index
+async fn indexed_models(db: &impl Database) -> Result<usize, Box<dyn Error>> {
+    let res = db
+        .query_with(
+            Some("model".to_string()),
+            Model::MODEL.to_string(),
+            SortKeyCondition::All,
+            QueryOptions::default(),
+        )
+        .await?;
+    Ok(res.items.unwrap_or_default().len())
+}
+
+#[test]
+fn test_memorydb_replication_lag() -> TestResult {
+    let start = Utc.ymd(2020, 7, 1).and_hms(12, 0, 0);
+    let clock = Arc::new(ManualClock::new(start));
+    let db = mem::memorydb()
+        .with_clock(clock.clone())
+        .with_replication_lag(Duration::seconds(1));
+
+    smol::run(async {
+        let foo = Model::new("foo", 1)?;
+        foo.clone().save(&db).await?;
+
+        // Only a consistent read sees the write straight away
+        assert!(db.get_item(foo.pk(), foo.sk()).await?.item.is_none());
+        assert!(Model::get(&db, "foo").await.is_err());
+        assert!(db
+            .get_item_with(foo.pk(), foo.sk(), CONSISTENT)
+            .await?
+            .item
+            .is_some());
+        assert_eq!(indexed_models(&db).await?, 0);
+        assert_eq!(db.scan(None::<String>, None).await?.count, Some(0));
+
+        clock.advance(Duration::seconds(1));
+        assert_eq!(Model::get(&db, "foo").await?.value(), 1);
+        assert_eq!(indexed_models(&db).await?, 1);
+
+        // Until the lag has passed, a replaced item is read as it was
+        Model::new("foo", 2)?.save(&db).await?;
+        clock.advance(Duration::milliseconds(500));
+        assert_eq!(Model::get(&db, "foo").await?.value(), 1);
+
+        clock.advance(Duration::milliseconds(500));
+        assert_eq!(Model::get(&db, "foo").await?.value(), 2);
+
+        Model::delete(&db, "foo").await?;
+        assert!(db.get_item(foo.pk(), foo.sk()).await?.item.is_some());
+        clock.advance(Duration::seconds(1));
+        assert!(db.get_item(foo.pk(), foo.sk()).await?.item.is_none());
+        assert_eq!(indexed_models(&db).await?, 0);
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_deletes_read_consistently() -> TestResult {
+    let start = Utc.ymd(2020, 7, 1).and_hms(12, 0, 0);
+    let clock = Arc::new(ManualClock::new(start));
+    let db = mem::memorydb()
+        .with_clock(clock.clone())
+        .with_replication_lag(Duration::seconds(1));
+
+    smol::run(async {
+        // Deleting a Model saved a moment ago sees it, and its SubModels
+        let mut foo = Model::new("foo", 1)?;
+        foo.save(&db).await?;
+        SubModel::new("bar", foo.clone())?.save(&db).await?;
+        assert!(Model::delete(&db, "foo").await.is_err());
+        assert_eq!(Model::delete_cascade(&db, "foo").await?, 2);
+
+        // So does soft deleting it, and its history
+        let mut foo = Model::new("foo", 1)?;
+        foo.save_with_history(&db, &history::Audit::new(None))
+            .await?;
+        assert!(Model::delete(&db, "foo").await.is_err());
+        let tombstone = Model::soft_delete(&db, "foo", &history::Audit::new(None)).await?;
+        assert_eq!(tombstone, 2);
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_replica_starts_with_table() -> TestResult {
+    let db = mem::memorydb();
+    smol::run(Model::new("foo", 1)?.save(&db))?;
+
+    // Items written before the lag was simulated have been replicated
+    let db = db.with_replication_lag(Duration::seconds(1));
+    smol::run(async {
+        assert_eq!(Model::get(&db, "foo").await?.value(), 1);
+        assert_eq!(indexed_models(&db).await?, 1);
+        Ok(())
+    })
+}
@@ -0,0 +1,146 @@
+use std::sync::Arc;
+
+use capacity::{Meter, RequestKind};
+use single_table::*;
+use traits::{Database, Key, QueryOptions, ReadOptions, SortKeyCondition};
+use types::AttributeValue;
+
+use super::*;
+
+#[test]
+fn test_capacity_unit_rounding() {
+    assert_eq!(capacity::read_units(0, true), 1.0);
+    assert_eq!(capacity::read_units(4096, true), 1.0);
+    assert_eq!(capacity::read_units(4097, true), 2.0);
+    assert_eq!(capacity::read_units(4097, false), 1.0);
+    assert_eq!(capacity::read_units(100, false), 0.5);
+
+    assert_eq!(capacity::write_units(0), 1.0);
+    assert_eq!(capacity::write_units(1024), 1.0);
+    assert_eq!(capacity::write_units(1025), 2.0);
+}
+
+#[test]
+fn test_memorydb_consumed_capacity() -> TestResult {
+    let meter = Arc::new(Meter::new());
+    let db = mem::memorydb().with_capacity_meter(meter.clone());
+
+    smol::run(async {
+        // A small Model is written to the table and both indexes
+        let foo = Model::new("foo", 1)?;
+        let res = db.put_item(foo.to_hashmap()?).await?;
+        let consumed = res.consumed_capacity.expect("consumed capacity");
+        assert_eq!(consumed.capacity_units, Some(3.0));
+        assert_eq!(consumed.table.and_then(|t| t.capacity_units), Some(1.0));
+        let indexes = consumed.global_secondary_indexes.unwrap_or_default();
+        assert_eq!(indexes["model"].capacity_units, Some(1.0));
+        assert_eq!(indexes["inverted"].capacity_units, Some(1.0));
+
+        // Eventually consistent reads cost half as much, even of a missing item
+        db.get_item(foo.pk(), foo.sk()).await?;
+        let consistent = ReadOptions {
+            consistent_read: true,
+        };
+        db.get_item_with("model#bar".to_string(), "model#bar".to_string(), consistent)
+            .await?;
+
+        // Queries of an index are charged to the index
+        let res = db
+            .query_with(
+                Some("model".to_string()),
+                Model::MODEL.to_string(),
+                SortKeyCondition::All,
+                QueryOptions::default(),
+            )
+            .await?;
+        let consumed = res.consumed_capacity.expect("consumed capacity");
+        assert_eq!(consumed.table.and_then(|t| t.capacity_units), Some(0.0));
+
+        // Transactions cost twice as much: the check of foo, and the put of bar
+        SubModel::new("bar", foo.clone())?.save(&db).await?;
+
+        let summary = meter.summary();
+        assert_eq!(summary.requests[&RequestKind::GetItem].count, 2);
+        assert_eq!(summary.requests[&RequestKind::GetItem].capacity_units, 1.5);
+        assert_eq!(
+            summary.requests[&RequestKind::TransactWriteItems].capacity_units,
+            8.0
+        );
+        assert_eq!(summary.table.read_capacity_units, 1.5);
+        assert_eq!(summary.table.write_capacity_units, 5.0);
+        assert_eq!(summary.indexes["model"].read_capacity_units, 0.5);
+        assert_eq!(summary.indexes["model"].write_capacity_units, 3.0);
+        assert_eq!(summary.read_capacity_units(), 2.0);
+        assert_eq!(summary.write_capacity_units(), 11.0);
+        assert!(summary.to_string().contains("GetItem: 2 requests"));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_memorydb_capacity_by_item_size() -> TestResult {
+    let meter = Arc::new(Meter::new());
+    let db = mem::memorydb().with_capacity_meter(meter.clone());
+
+    // An item of 4500 bytes, over four 1KB write units, and one 4KB read unit
+    let mut item = Model::new("foo", 1)?.to_hashmap()?;
+    let size = validation::item_size(&item) + "blob".len();
+    let blob = AttributeValue {
+        s: Some("x".repeat(4500 - size)),
+        ..Default::default()
+    };
+    item.insert("blob".to_string(), blob);
+    assert_eq!(validation::item_size(&item), 4500);
+    let (pk, sk) = item.key();
+
+    smol::run(async {
+        let res = db.put_item(item).await?;
+        let consumed = res.consumed_capacity.expect("consumed capacity");
+        assert_eq!(consumed.table.and_then(|t| t.capacity_units), Some(5.0));
+
+        let res = db.get_item(pk.clone(), sk.clone()).await?;
+        let consumed = res.consumed_capacity.expect("consumed capacity");
+        assert_eq!(consumed.capacity_units, Some(1.0));
+
+        let consistent = ReadOptions {
+            consistent_read: true,
+        };
+        let res = db.get_item_with(pk, sk, consistent).await?;
+        let consumed = res.consumed_capacity.expect("consumed capacity");
+        assert_eq!(consumed.capacity_units, Some(2.0));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_memorydb_cancelled_capacity() -> TestResult {
+    let meter = Arc::new(Meter::new());
+    let db = mem::memorydb().with_capacity_meter(meter.clone());
+
+    // A cancelled transaction is charged for, though nothing is written
+    let check = db.condition_check_exists("model#bar", "model#bar", Model::MODEL);
+    assert!(smol::run(db.transact_write_items(vec![check])).is_err());
+
+    let summary = meter.summary();
+    let requests = summary.requests[&RequestKind::TransactWriteItems];
+    assert_eq!(requests.count, 1);
+    assert_eq!(requests.capacity_units, 2.0);
+    assert_eq!(summary.table.write_capacity_units, 2.0);
+
+    Ok(())
+}
+
+#[test]
+fn test_memorydb_unmetered() -> TestResult {
+    let db = mem::memorydb();
+
+    smol::run(async {
+        let foo = Model::new("foo", 1)?;
+        let res = db.put_item(foo.to_hashmap()?).await?;
+        assert!(res.consumed_capacity.is_none());
+
+        Ok(())
+    })
+}
@@ -0,0 +1,142 @@
+use rstest::rstest;
+use rstest_reuse::*;
+
+use single_table::*;
+use traits::{Database, TransactionalOperations};
+
+use super::*;
+
+fn counted(name: &str) -> impl Fn(&types::UpdateItemOutput) -> Option<String> + '_ {
+    move |output| output.attributes.as_ref()?.get(name)?.n.clone()
+}
+
+#[apply(state)]
+fn test_increment<DB>(state: State<DB>) -> TestResult
+where
+    DB: Database + Send + Sync,
+{
+    let count = counted("hits");
+
+    // The item and the attribute are created on the first increment
+    let output = smol::run(state.db.increment("counter#foo", "counter#foo", "hits", 2))?;
+    assert_eq!(count(&output), Some("2".to_string()));
+
+    let output = smol::run(state.db.increment("counter#foo", "counter#foo", "hits", -3))?;
+    assert_eq!(count(&output), Some("-1".to_string()));
+
+    Ok(())
+}
+
+#[apply(state)]
+fn test_save_with_count<DB>(state: State<DB>) -> TestResult
+where
+    DB: Database + Send + Sync,
+{
+    let options = SaveOptions {
+        update_parent_count: true,
+        ..Default::default()
+    };
+
+    smol::run(async {
+        let mut foo = Model::new("foo", 1)?;
+        foo.save(&state.db).await?;
+        assert_eq!(Model::get(&state.db, "foo").await?.submodel_count(), 0);
+
+        SubModel::new("bar", foo.clone())?
+            .save_with(&state.db, options.clone())
+            .await?;
+        SubModel::new("baz", foo.clone())?
+            .save_with(&state.db, options.clone())
+            .await?;
+
+        // Saving an existing SubModel again must not count it twice
+        let resave = SubModel::new("bar", foo.clone())?
+            .save_with(&state.db, options)
+            .await;
+        assert!(resave.is_err());
+
+        // Without the option, the count is left alone
+        SubModel::new("qux", foo.clone())?.save(&state.db).await?;
+
+        assert_eq!(Model::get(&state.db, "foo").await?.submodel_count(), 2);
+
+        // Saving the Model again, even a copy from before the count, keeps it
+        foo.save(&state.db).await?;
+        let mut saved = Model::get(&state.db, "foo").await?;
+        assert_eq!(saved.submodel_count(), 2);
+        saved.save(&state.db).await?;
+        assert_eq!(Model::get(&state.db, "foo").await?.submodel_count(), 2);
+
+        Ok(())
+    })
+}
+
+#[apply(state)]
+fn test_save_with_count_missing_parent<DB>(state: State<DB>) -> TestResult
+where
+    DB: Database + Send + Sync,
+{
+    let options = SaveOptions {
+        update_parent_count: true,
+        ..Default::default()
+    };
+
+    smol::run(async {
+        let foo = Model::new("foo", 1)?;
+        let saved = SubModel::new("bar", foo)?
+            .save_with(&state.db, options)
+            .await;
+        assert!(saved.is_err());
+
+        // Neither the SubModel nor a parent holding only the count was written
+        assert!(SubModel::get(&state.db, "foo", "bar").await.is_err());
+        assert!(state
+            .db
+            .get_item("model#foo", "model#foo")
+            .await?
+            .item
+            .is_none());
+        Ok(())
+    })
+}
+
+#[apply(state)]
+fn test_transaction_with_add<DB>(state: State<DB>) -> TestResult
+where
+    DB: Database + Send + Sync,
+{
+    let foo = Model::new("foo", 1)?;
+    let hashmap = serde_dynamodb::to_hashmap(&foo)?;
+
+    smol::run(async {
+        state
+            .db
+            .transact_write_items(vec![
+                state.db.put(hashmap.clone()),
+                state.db.add("counter#models", "counter#models", "total", 1),
+            ])
+            .await?;
+
+        let counter = state
+            .db
+            .get_item("counter#models", "counter#models")
+            .await?
+            .item
+            .expect("counter");
+        assert_eq!(counter["total"].n, Some("1".to_string()));
+
+        // A transaction may only include one operation on each item
+        let duplicate = state
+            .db
+            .transact_write_items(vec![
+                state.db.put(hashmap),
+                state
+                    .db
+                    .add(foo.pk(), foo.sk(), SUBMODEL_COUNT_ATTRIBUTE, 1),
+            ])
+            .await;
+        assert!(duplicate.is_err());
+
+        Ok(())
+    })
+}
@@ -6,38 +6,6 @@ use traits::{Database, TransactionalOperations};
 
 use super::*;
 
-struct State<DB>
-where
-    DB: Database + Send + Sync,
-{
-    db: TemporaryDatabase<DB>,
-}
-
-#[cfg(feature = "external_database")]
-impl State<ddb::DDB> {
-    fn new() -> Self {
-        Self { db: dynamodb() }
-    }
-}
-
-impl State<mem::MemoryDB> {
-    fn new() -> Self {
-        Self { db: memorydb() }
-    }
-}
-
-#[template]
-#[rstest(state,
-    #[cfg(feature = "external_database")]
-    case::ddb(State::<ddb::DDB>::new()),
-    case::mem(State::<mem::MemoryDB>::new()),
-)]
-fn state<DB>(state: State<DB>)
-where
-    DB: Database + Send + Sync,
-{
-}
-
 #[apply(state)]
 fn test_get_none<DB>(state: State<DB>) -> TestResult
 where
@@ -56,7 +24,7 @@ fn test_put_get_some<DB>(state: State<DB>) -> TestResult
 where
     DB: Database + Send + Sync,
 {
-    let model = Model::new("foo", 1);
+    let model = Model::new("foo", 1)?;
 
     let hashmap: types::HashMap =
         serde_dynamodb::to_hashmap(&model).unwrap_or_else(|_| types::HashMap::new());
@@ -85,7 +53,7 @@ where
     insert_models(&state.db)?;
 
     let items: rusoto_dynamodb::QueryOutput =
-        smol::run(state.db.query(None, "model#foo", "model#foo#submodel#"))?;
+        smol::run(state.db.query(None, "model#foo", "submodel#"))?;
     assert_eq!(items.count, Some(2));
 
     let mut submodels: Vec<SubModel> = vec![];
@@ -133,11 +101,8 @@ where
 {
     insert_models(&state.db)?;
 
-    let items: rusoto_dynamodb::QueryOutput = smol::run(state.db.query(
-        Some("model"),
-        "submodel",
-        "model#foo#submodel#bar",
-    ))?;
+    let items: rusoto_dynamodb::QueryOutput =
+        smol::run(state.db.query(Some("model"), "submodel", "submodel#bar"))?;
     assert_eq!(items.count, Some(1));
 
     let mut submodels: Vec<SubModel> = vec![];
@@ -206,8 +171,8 @@ where
     let table_name = smol::run(state.db.describe_table())?;
     assert!(table_name.table.is_some());
 
-    let mut foo: Model = Model::new("foo", 1);
-    let bar: SubModel = SubModel::new("bar", foo.clone());
+    let mut foo: Model = Model::new("foo", 1)?;
+    let bar: SubModel = SubModel::new("bar", foo.clone())?;
 
     let _ = smol::run(foo.save(&state.db))?;
     let _ = smol::run(state.db.transact_write_items(vec![
@@ -219,5 +184,11 @@ where
     println!("{:?}", res);
     assert_eq!(res.name(), "bar");
 
+    let too_many = (0..=traits::MAX_TRANSACTION_ITEMS)
+        .map(|i| Ok(state.db.put(Model::new(i.to_string(), 1)?.to_hashmap()?)))
+        .collect::<Result<Vec<_>, Box<dyn Error>>>()?;
+    assert!(smol::run(state.db.transact_write_items(too_many)).is_err());
+    assert!(smol::run(Model::get(&state.db, "0")).is_err());
+
     Ok(())
 }
@@ -0,0 +1,79 @@
+use rstest::rstest;
+use rstest_reuse::*;
+
+use single_table::*;
+use traits::Database;
+use types::AttributeValue;
+
+use super::*;
+
+#[apply(state)]
+fn test_decode_by_model<DB>(state: State<DB>) -> TestResult
+where
+    DB: Database + Send + Sync,
+{
+    insert_models(&state.db)?;
+
+    let collection = smol::run(ItemCollection::query(&state.db, "model#foo"))?;
+    let items = collection.decode(&Registry::default())?;
+    assert_eq!(items.len(), 3);
+
+    match &items[0] {
+        Item::Model(model) => assert_eq!(model.name(), "foo"),
+        item => panic!("expected a Model, got {:?}", item),
+    }
+    match &items[1] {
+        Item::SubModel(submodel) => assert_eq!(submodel.name(), "bar"),
+        item => panic!("expected a SubModel, got {:?}", item),
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_decode_unknown() -> TestResult {
+    let mut hashmap = types::HashMap::new();
+    for (name, value) in &[("pk", "widget#1"), ("sk", "widget#1"), ("model", "widget")] {
+        hashmap.insert(
+            name.to_string(),
+            AttributeValue {
+                s: Some(value.to_string()),
+                ..Default::default()
+            },
+        );
+    }
+
+    match Registry::default().decode(&hashmap)? {
+        Item::Unknown(item) => assert_eq!(item, hashmap),
+        item => panic!("expected an unknown item, got {:?}", item),
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_decode_unregistered() -> TestResult {
+    let foo = Model::new("foo", 1)?;
+    let hashmap = serde_dynamodb::to_hashmap(&foo)?;
+
+    // Only SubModels are known to this registry
+    let registry = Registry::empty().register::<SubModel>();
+    assert!(matches!(registry.decode(&hashmap)?, Item::Unknown(_)));
+
+    Ok(())
+}
+
+#[test]
+fn test_decode_invalid() {
+    let mut hashmap = types::HashMap::new();
+    hashmap.insert(
+        "model".to_string(),
+        AttributeValue {
+            s: Some("model".to_string()),
+            ..Default::default()
+        },
+    );
+
+    // Known types that fail to decode are reported, rather than treated as unknown
+    assert!(Registry::default().decode(&hashmap).is_err());
+}
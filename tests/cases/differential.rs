@@ -0,0 +1,270 @@
+// Differential testing of `MemoryDB` against `DDB`.
+//
+// Random sequences of operations are applied to both backends and the outcomes
+// compared. Without the `external_database` feature, `MemoryDB` is compared
+// against traces recorded from dynamodb-local in `tests/fixtures/differential.json`.
+#[cfg(feature = "external_database")]
+use proptest::{
+    prelude::*,
+    test_runner::{Config, TestError, TestRunner},
+};
+use serde::{Deserialize, Serialize};
+
+use single_table::*;
+use traits::{Database, Key};
+
+use super::*;
+
+#[cfg(feature = "external_database")]
+const NAMES: &[&str] = &["foo", "bar", "baz"];
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+enum Op {
+    PutModel {
+        name: String,
+        a_number: i32,
+    },
+    PutSubModel {
+        parent: String,
+        name: String,
+    },
+    GetModel {
+        name: String,
+    },
+    GetSubModel {
+        parent: String,
+        name: String,
+    },
+    Query {
+        index: bool,
+        pk: String,
+        sk: Option<String>,
+    },
+    Scan {
+        index: bool,
+        limit: Option<i64>,
+    },
+}
+
+// Only keys are compared, attributes such as `created_at` differ between runs
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+enum Outcome {
+    Ok,
+    Failed,
+    Item(Option<(String, String)>),
+    Items(Vec<(String, String)>),
+    Count(Option<i64>),
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Trace {
+    ops: Vec<Op>,
+    outcomes: Vec<Outcome>,
+}
+
+#[cfg(feature = "external_database")]
+fn name() -> impl Strategy<Value = String> {
+    prop::sample::select(NAMES).prop_map(String::from)
+}
+
+#[cfg(feature = "external_database")]
+fn op() -> impl Strategy<Value = Op> {
+    prop_oneof![
+        (name(), any::<i32>()).prop_map(|(name, a_number)| Op::PutModel { name, a_number }),
+        (name(), name()).prop_map(|(parent, name)| Op::PutSubModel { parent, name }),
+        name().prop_map(|name| Op::GetModel { name }),
+        (name(), name()).prop_map(|(parent, name)| Op::GetSubModel { parent, name }),
+        (any::<bool>(), name(), prop::option::of(name())).prop_map(|(index, pk, sk)| Op::Query {
+            index,
+            pk,
+            sk
+        }),
+        (any::<bool>(), prop::option::of(1..4i64))
+            .prop_map(|(index, limit)| Op::Scan { index, limit }),
+    ]
+}
+
+async fn execute(db: &impl Database, op: &Op) -> Outcome {
+    match op {
+        Op::PutModel { name, a_number } => {
+            let mut model = Model::new(name, *a_number).expect("valid name");
+            match model.save(db).await {
+                Ok(_) => Outcome::Ok,
+                Err(_) => Outcome::Failed,
+            }
+        }
+
+        Op::PutSubModel { parent, name } => {
+            // Only the parent's key is needed, the transaction checks that it exists
+            let parent = Model::new(parent, 0).expect("valid name");
+            let mut submodel = SubModel::new(name, parent).expect("valid name");
+            match submodel.save(db).await {
+                Ok(_) => Outcome::Ok,
+                Err(_) => Outcome::Failed,
+            }
+        }
+
+        Op::GetModel { name } => {
+            let pk = Model::key_of(name).build();
+            item_outcome(db.get_item(pk.clone(), pk).await)
+        }
+
+        Op::GetSubModel { parent, name } => {
+            let sk = SubModel::key_of(name).build();
+            let pk = shard::key(Model::key_of(parent), &sk, SubModel::SHARDS);
+            item_outcome(db.get_item(pk, sk).await)
+        }
+
+        // Keys are built the same way as the `query` subcommand
+        Op::Query { index, pk, sk } => {
+            let (index, pk, sk) = match (index, sk) {
+                (true, Some(sk)) => (
+                    Some("model"),
+                    SubModel::MODEL.to_string(),
+                    SubModel::key_of(sk).build(),
+                ),
+                (true, None) => (
+                    Some("model"),
+                    Model::MODEL.to_string(),
+                    Model::key_of(pk).build(),
+                ),
+                (false, Some(sk)) => (
+                    None,
+                    Model::key_of(pk).build(),
+                    SubModel::key_of(sk).build(),
+                ),
+                (false, None) => (None, Model::key_of(pk).build(), String::new()),
+            };
+
+            match db.query(index.map(String::from), pk, sk).await {
+                Ok(output) => Outcome::Items(keys(output.items)),
+                Err(_) => Outcome::Failed,
+            }
+        }
+
+        Op::Scan { index, limit } => {
+            let index = if *index { Some("model") } else { None };
+            match db.scan(index, *limit).await {
+                // DynamoDB scans in hash order, so a limited scan returns arbitrary items
+                Ok(output) if limit.is_some() => Outcome::Count(output.count),
+                Ok(output) => {
+                    let mut keys = keys(output.items);
+                    keys.sort();
+                    Outcome::Items(keys)
+                }
+                Err(_) => Outcome::Failed,
+            }
+        }
+    }
+}
+
+fn item_outcome(result: types::GetItemResult) -> Outcome {
+    match result {
+        Ok(output) => Outcome::Item(output.item.map(|item| item.key())),
+        Err(_) => Outcome::Failed,
+    }
+}
+
+fn keys(items: Option<Vec<types::HashMap>>) -> Vec<(String, String)> {
+    items
+        .unwrap_or_default()
+        .iter()
+        .map(|item| item.key())
+        .collect()
+}
+
+fn run(db: &impl Database, ops: &[Op]) -> Vec<Outcome> {
+    smol::run(async {
+        let mut outcomes = vec![];
+        for op in ops {
+            outcomes.push(execute(db, op).await);
+        }
+        outcomes
+    })
+}
+
+// The shortest prefix of `ops` whose outcomes differ, if any
+fn diverging_prefix<'a>(
+    ops: &'a [Op],
+    expected: &[Outcome],
+    actual: &[Outcome],
+) -> Option<&'a [Op]> {
+    expected
+        .iter()
+        .zip(actual)
+        .position(|(expected, actual)| expected != actual)
+        .map(|i| &ops[..=i])
+}
+
+#[cfg(feature = "external_database")]
+#[test]
+fn test_memorydb_matches_dynamodb() {
+    let mut runner = TestRunner::new(Config {
+        cases: 32,
+        failure_persistence: None,
+        ..Config::default()
+    });
+
+    let result = runner.run(&prop::collection::vec(op(), 1..16), |ops| {
+        let expected = run(&dynamodb(), &ops);
+        let actual = run(&memorydb(), &ops);
+
+        match diverging_prefix(&ops, &expected, &actual) {
+            None => Ok(()),
+            Some(prefix) => Err(TestCaseError::fail(format!(
+                "{} operations, DDB: {:?}, MemoryDB: {:?}",
+                prefix.len(),
+                expected[prefix.len() - 1],
+                actual[prefix.len() - 1],
+            ))),
+        }
+    });
+
+    match result {
+        Ok(()) => {}
+        Err(TestError::Fail(reason, ops)) => panic!(
+            "MemoryDB diverged from DDB after {}\nminimal sequence: {:#?}",
+            reason, ops
+        ),
+        Err(TestError::Abort(reason)) => panic!("differential test aborted: {}", reason),
+    }
+}
+
+fn recorded_traces() -> Result<Vec<Trace>, Box<dyn Error>> {
+    let fixture = include_str!("../fixtures/differential.json");
+    Ok(serde_json::from_str(fixture)?)
+}
+
+fn check_recorded_traces<DB>(new_db: impl Fn() -> TemporaryDatabase<DB>) -> TestResult
+where
+    DB: Database + Send + Sync,
+{
+    for trace in recorded_traces()? {
+        // Each trace starts from an empty table
+        let db = new_db();
+        let outcomes = run(&db, &trace.ops);
+
+        if let Some(prefix) = diverging_prefix(&trace.ops, &trace.outcomes, &outcomes) {
+            panic!(
+                "diverged from the recorded trace\nminimal sequence: {:#?}\nrecorded: {:?}\nactual: {:?}",
+                prefix,
+                trace.outcomes[prefix.len() - 1],
+                outcomes[prefix.len() - 1],
+            );
+        }
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_memorydb_recorded_traces() -> TestResult {
+    check_recorded_traces(memorydb)
+}
+
+// Keeps the recorded traces honest
+#[cfg(feature = "external_database")]
+#[test]
+fn test_dynamodb_recorded_traces() -> TestResult {
+    check_recorded_traces(dynamodb)
+}
@@ -0,0 +1,144 @@
+use rstest::rstest;
+use rstest_reuse::*;
+
+use single_table::*;
+use traits::Database;
+
+use super::*;
+
+fn insert_graph(db: &impl Database) -> TestResult {
+    smol::run(async {
+        for name in &["foo", "bar", "baz"] {
+            Model::new(*name, 1)?.save(db).await?;
+        }
+
+        Edge::link(db, "foo", "bar").await?;
+        Edge::link(db, "foo", "baz").await?;
+        Edge::link(db, "baz", "bar").await?;
+
+        Ok(())
+    })
+}
+
+fn names(edges: &[Edge], name: impl Fn(&Edge) -> &str) -> Vec<&str> {
+    edges.iter().map(name).collect()
+}
+
+#[apply(state)]
+fn test_neighbors<DB>(state: State<DB>) -> TestResult
+where
+    DB: Database + Send + Sync,
+{
+    insert_graph(&state.db)?;
+
+    let outgoing = smol::run(Edge::outgoing(&state.db, "foo"))?;
+    assert_eq!(names(&outgoing, Edge::target), vec!["bar", "baz"]);
+
+    let incoming = smol::run(Edge::incoming(&state.db, "bar"))?;
+    assert_eq!(names(&incoming, Edge::source), vec!["baz", "foo"]);
+
+    assert!(smol::run(Edge::incoming(&state.db, "foo"))?.is_empty());
+
+    // Edges are not mistaken for SubModels
+    let (_, children) = smol::run(Model::get_with_children(&state.db, "foo"))?;
+    assert!(children.is_empty());
+
+    Ok(())
+}
+
+#[apply(state)]
+fn test_link_missing<DB>(state: State<DB>) -> TestResult
+where
+    DB: Database + Send + Sync,
+{
+    insert_graph(&state.db)?;
+
+    assert!(smol::run(Edge::link(&state.db, "foo", "missing")).is_err());
+    assert!(smol::run(Edge::link(&state.db, "missing", "foo")).is_err());
+
+    let outgoing = smol::run(Edge::outgoing(&state.db, "foo"))?;
+    assert_eq!(outgoing.len(), 2);
+
+    Ok(())
+}
+
+#[apply(state)]
+fn test_link_self<DB>(state: State<DB>) -> TestResult
+where
+    DB: Database + Send + Sync,
+{
+    insert_graph(&state.db)?;
+
+    smol::run(Edge::link(&state.db, "bar", "bar"))?;
+
+    let incoming = smol::run(Edge::incoming(&state.db, "bar"))?;
+    assert_eq!(names(&incoming, Edge::source), vec!["bar", "baz", "foo"]);
+
+    Ok(())
+}
+
+#[apply(state)]
+fn test_unlink<DB>(state: State<DB>) -> TestResult
+where
+    DB: Database + Send + Sync,
+{
+    insert_graph(&state.db)?;
+
+    smol::run(Edge::unlink(&state.db, "foo", "bar"))?;
+
+    let outgoing = smol::run(Edge::outgoing(&state.db, "foo"))?;
+    assert_eq!(names(&outgoing, Edge::target), vec!["baz"]);
+
+    let incoming = smol::run(Edge::incoming(&state.db, "bar"))?;
+    assert_eq!(names(&incoming, Edge::source), vec!["baz"]);
+
+    Ok(())
+}
+
+#[apply(state)]
+fn test_delete_cascade_linked<DB>(state: State<DB>) -> TestResult
+where
+    DB: Database + Send + Sync,
+{
+    insert_graph(&state.db)?;
+
+    smol::run(async {
+        let baz = Model::get(&state.db, "baz").await?;
+        SubModel::new("qux", baz)?.save(&state.db).await?;
+        Edge::link(&state.db, "baz", "baz").await?;
+
+        Ok::<_, Box<dyn Error>>(())
+    })?;
+
+    // The Model, its SubModel, and the Edges foo -> baz, baz -> bar and baz -> baz
+    let deleted = smol::run(Model::delete_cascade(&state.db, "baz"))?;
+    assert_eq!(deleted, 5);
+
+    let outgoing = smol::run(Edge::outgoing(&state.db, "foo"))?;
+    assert_eq!(names(&outgoing, Edge::target), vec!["bar"]);
+
+    let incoming = smol::run(Edge::incoming(&state.db, "bar"))?;
+    assert_eq!(names(&incoming, Edge::source), vec!["foo"]);
+
+    assert!(smol::run(Edge::incoming(&state.db, "baz"))?.is_empty());
+    assert!(smol::run(Edge::outgoing(&state.db, "baz"))?.is_empty());
+
+    Ok(())
+}
+
+#[apply(state)]
+fn test_delete_linked<DB>(state: State<DB>) -> TestResult
+where
+    DB: Database + Send + Sync,
+{
+    insert_graph(&state.db)?;
+
+    smol::run(Model::delete(&state.db, "bar"))?;
+
+    assert!(smol::run(Edge::incoming(&state.db, "bar"))?.is_empty());
+    let outgoing = smol::run(Edge::outgoing(&state.db, "foo"))?;
+    assert_eq!(names(&outgoing, Edge::target), vec!["baz"]);
+    assert!(smol::run(Edge::outgoing(&state.db, "baz"))?.is_empty());
+
+    Ok(())
+}
@@ -0,0 +1,225 @@
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use rstest::rstest;
+use rstest_reuse::*;
+
+use clock::ManualClock;
+use events::Event;
+use single_table::*;
+use traits::{Database, QueryOptions, SortKeyCondition};
+
+use super::*;
+
+fn start() -> DateTime<Utc> {
+    Utc.ymd(2020, 7, 1).and_hms(12, 0, 0)
+}
+
+// Events "0" to "11", a minute apart, so that "10" and "11" would sort before "9" as text
+async fn insert_log(db: &impl Database) -> TestResult {
+    Model::new("foo", 1)?.save(db).await?;
+
+    let clock = ManualClock::new(start());
+    for i in 0..12 {
+        Event::new_with_clock("foo".to_string(), i.to_string(), &clock)?
+            .save(db)
+            .await?;
+        clock.advance(Duration::minutes(1));
+    }
+    Ok(())
+}
+
+fn messages(events: &[Event]) -> Vec<&str> {
+    events.iter().map(Event::message).collect()
+}
+
+#[apply(state)]
+fn test_event_log_latest<DB>(state: State<DB>) -> TestResult
+where
+    DB: Database + Send + Sync,
+{
+    smol::run(async {
+        insert_log(&state.db).await?;
+
+        let latest = Event::latest(&state.db, "foo", 3).await?;
+        assert_eq!(messages(&latest), vec!["11", "10", "9"]);
+        assert_eq!(latest[0].at(), start() + Duration::minutes(11));
+        assert_eq!(latest[0].parent(), "foo");
+
+        // Events are kept out of the Model's partition
+        let (_, children) = Model::get_with_children(&state.db, "foo").await?;
+        assert!(children.is_empty());
+        let collection = ItemCollection::query(&state.db, "model#foo").await?;
+        assert_eq!(collection.len(), 1);
+
+        assert!(Event::latest(&state.db, "bar", 3).await?.is_empty());
+
+        Ok(())
+    })
+}
+
+#[apply(state)]
+fn test_event_log_between<DB>(state: State<DB>) -> TestResult
+where
+    DB: Database + Send + Sync,
+{
+    smol::run(async {
+        insert_log(&state.db).await?;
+
+        // From the start of the window, up to but not including its end
+        let since = start() + Duration::minutes(8);
+        let until = start() + Duration::minutes(11);
+        let window = Event::between(&state.db, "foo", since, until).await?;
+        assert_eq!(messages(&window), vec!["10", "9", "8"]);
+
+        let everything =
+            Event::between(&state.db, "foo", start(), until + Duration::days(1)).await?;
+        assert_eq!(everything.len(), 12);
+
+        assert!(Event::between(&state.db, "foo", until, since)
+            .await?
+            .is_empty());
+
+        Ok(())
+    })
+}
+
+#[apply(state)]
+fn test_event_log_requires_parent<DB>(state: State<DB>) -> TestResult
+where
+    DB: Database + Send + Sync,
+{
+    smol::run(async {
+        assert!(Event::append(&state.db, "foo", "orphan").await.is_err());
+
+        Model::new("foo", 1)?.save(&state.db).await?;
+        let event = Event::append(&state.db, "foo", "hello").await?;
+        assert_eq!(event.pk(), "event#model#foo");
+        assert!(event.sk().starts_with("at#"));
+
+        Ok(())
+    })
+}
+
+#[apply(state)]
+fn test_query_with_pages<DB>(state: State<DB>) -> TestResult
+where
+    DB: Database + Send + Sync,
+{
+    smol::run(async {
+        insert_log(&state.db).await?;
+        let pk = Event::partition_of("foo");
+
+        // Descending pages of five, following `last_evaluated_key`
+        let mut pages = vec![];
+        let mut exclusive_start_key = None;
+        loop {
+            let options = QueryOptions {
+                descending: true,
+                limit: Some(5),
+                exclusive_start_key,
+                ..Default::default()
+            };
+            let res = state
+                .db
+                .query_with(None, pk.clone(), SortKeyCondition::All, options)
+                .await?;
+            let page = ItemCollection::new(res.items.unwrap_or_default());
+            pages.push(page.entities::<Event>()?.len());
+
+            exclusive_start_key = res.last_evaluated_key;
+            if exclusive_start_key.is_none() {
+                break;
+            }
+        }
+        assert_eq!(pages.iter().sum::<usize>(), 12);
+        assert_eq!(pages[..2], [5, 5]);
+
+        let low = Event::key_of(start() + Duration::minutes(2)).build();
+        let high = Event::key_of(start() + Duration::minutes(4)).build();
+        let res = state
+            .db
+            .query_with(
+                None,
+                pk,
+                SortKeyCondition::Between(low, high),
+                QueryOptions::default(),
+            )
+            .await?;
+        let between = ItemCollection::new(res.items.unwrap_or_default()).entities::<Event>()?;
+        assert_eq!(messages(&between), vec!["2", "3"]);
+
+        Ok(())
+    })
+}
+
+#[apply(state)]
+fn test_query_with_deleted_start<DB>(state: State<DB>) -> TestResult
+where
+    DB: Database + Send + Sync,
+{
+    smol::run(async {
+        insert_log(&state.db).await?;
+        let pk = Event::partition_of("foo");
+
+        for descending in &[false, true] {
+            let options = QueryOptions {
+                descending: *descending,
+                limit: Some(3),
+                ..Default::default()
+            };
+            let res = state
+                .db
+                .query_with(None, pk.clone(), SortKeyCondition::All, options)
+                .await?;
+            let start = res.last_evaluated_key.expect("a next page");
+
+            // The next page follows the start key, though its item is gone
+            let (start_pk, start_sk) = start.key();
+            let delete = state.db.delete(start_pk, start_sk);
+            state.db.transact_write_items(vec![delete]).await?;
+
+            let options = QueryOptions {
+                descending: *descending,
+                limit: Some(2),
+                exclusive_start_key: Some(start),
+                ..Default::default()
+            };
+            let res = state
+                .db
+                .query_with(None, pk.clone(), SortKeyCondition::All, options)
+                .await?;
+            let page = ItemCollection::new(res.items.unwrap_or_default()).entities::<Event>()?;
+            let expected = if *descending {
+                vec!["8", "7"]
+            } else {
+                vec!["3", "4"]
+            };
+            assert_eq!(messages(&page), expected);
+        }
+
+        Ok(())
+    })
+}
+
+#[apply(state)]
+fn test_event_log_deleted_with_model<DB>(state: State<DB>) -> TestResult
+where
+    DB: Database + Send + Sync,
+{
+    smol::run(async {
+        insert_log(&state.db).await?;
+        Model::delete(&state.db, "foo").await?;
+        assert!(Event::latest(&state.db, "foo", 20).await?.is_empty());
+
+        insert_log(&state.db).await?;
+        assert_eq!(Model::delete_cascade(&state.db, "foo").await?, 13);
+        assert!(Event::latest(&state.db, "foo", 20).await?.is_empty());
+
+        // A soft deleted Model keeps its log, until it is restored
+        insert_log(&state.db).await?;
+        let audit = history::Audit::new(None);
+        Model::soft_delete(&state.db, "foo", &audit).await?;
+        assert_eq!(Event::latest(&state.db, "foo", 20).await?.len(), 12);
+
+        Ok(())
+    })
+}
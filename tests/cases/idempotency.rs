@@ -0,0 +1,148 @@
+use chrono::{Duration, TimeZone, Utc};
+use rstest::rstest;
+use rstest_reuse::*;
+use std::sync::Arc;
+
+use clock::ManualClock;
+use single_table::*;
+use traits::{Database, TransactOptions};
+use types::{RusotoError, TransactWriteItemsError};
+
+use super::*;
+
+fn with_token(token: &str) -> TransactOptions {
+    TransactOptions {
+        client_request_token: Some(token.to_string()),
+    }
+}
+
+fn is_mismatch(result: types::TransactWriteItemsResult) -> bool {
+    matches!(
+        result,
+        Err(RusotoError::Service(
+            TransactWriteItemsError::IdempotentParameterMismatch(_)
+        ))
+    )
+}
+
+#[apply(state)]
+fn test_idempotent_save<DB>(state: State<DB>) -> TestResult
+where
+    DB: Database + Send + Sync,
+{
+    let options = SaveOptions {
+        update_parent_count: true,
+        idempotency_key: Some("save-bar".to_string()),
+    };
+
+    smol::run(async {
+        let foo = Model::new("foo", 1)?;
+        foo.clone().save(&state.db).await?;
+
+        // A retry succeeds, even though the SubModel now exists, and is only counted once
+        let mut bar = SubModel::new("bar", foo.clone())?;
+        bar.save_with(&state.db, options.clone()).await?;
+        bar.save_with(&state.db, options.clone()).await?;
+        assert_eq!(Model::get(&state.db, "foo").await?.submodel_count(), 1);
+
+        let mut baz = SubModel::new("baz", foo)?;
+        assert!(baz.save_with(&state.db, options).await.is_err());
+        assert!(SubModel::get(&state.db, "foo", "baz").await.is_err());
+
+        Ok(())
+    })
+}
+
+#[apply(state)]
+fn test_client_request_token_mismatch<DB>(state: State<DB>) -> TestResult
+where
+    DB: Database + Send + Sync,
+{
+    smol::run(async {
+        let foo = Model::new("foo", 1)?.to_hashmap()?;
+        let bar = Model::new("bar", 2)?.to_hashmap()?;
+
+        state
+            .db
+            .transact_write_items_with(vec![state.db.put(foo.clone())], with_token("put"))
+            .await?;
+        state
+            .db
+            .transact_write_items_with(vec![state.db.put(foo)], with_token("put"))
+            .await?;
+
+        let mismatch = state
+            .db
+            .transact_write_items_with(vec![state.db.put(bar)], with_token("put"))
+            .await;
+        assert!(is_mismatch(mismatch));
+        assert!(Model::get(&state.db, "bar").await.is_err());
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_client_request_token_expiry() -> TestResult {
+    let start = Utc.ymd(2020, 7, 1).and_hms(12, 0, 0);
+    let clock = Arc::new(ManualClock::new(start));
+    let db = mem::memorydb().with_clock(clock.clone());
+
+    smol::run(async {
+        let foo = Model::new("foo", 1)?;
+        let put_new = || vec![db.put_new(foo.to_hashmap().expect("serialize"))];
+
+        db.transact_write_items_with(put_new(), with_token("create-foo"))
+            .await?;
+
+        // Replayed within the window, the put is not applied again so its condition holds
+        clock.advance(Duration::minutes(9));
+        db.transact_write_items_with(put_new(), with_token("create-foo"))
+            .await?;
+        let other = vec![db.put(Model::new("bar", 2)?.to_hashmap()?)];
+        assert!(is_mismatch(
+            db.transact_write_items_with(other.clone(), with_token("create-foo"))
+                .await
+        ));
+
+        // Once the token has expired, it is a new request
+        clock.advance(Duration::minutes(2));
+        assert!(db
+            .transact_write_items_with(put_new(), with_token("create-foo"))
+            .await
+            .is_err());
+        db.transact_write_items_with(other, with_token("create-foo"))
+            .await?;
+        Model::get(&db, "bar").await?;
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_cancelled_request_can_be_retried() -> TestResult {
+    let db = mem::memorydb();
+
+    smol::run(async {
+        let foo = Model::new("foo", 1)?;
+        let bar = SubModel::new("bar", foo.clone())?;
+        let transact_items = || {
+            vec![
+                db.condition_check_exists(&foo.pk(), &foo.sk(), Model::MODEL),
+                db.put(bar.to_hashmap().expect("serialize")),
+            ]
+        };
+
+        assert!(db
+            .transact_write_items_with(transact_items(), with_token("save-bar"))
+            .await
+            .is_err());
+
+        foo.clone().save(&db).await?;
+        db.transact_write_items_with(transact_items(), with_token("save-bar"))
+            .await?;
+        SubModel::get(&db, "foo", "bar").await?;
+
+        Ok(())
+    })
+}
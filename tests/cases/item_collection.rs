@@ -0,0 +1,54 @@
+use rstest::rstest;
+use rstest_reuse::*;
+
+use single_table::*;
+use traits::Database;
+
+use super::*;
+
+#[apply(state)]
+fn test_get_with_children<DB>(state: State<DB>) -> TestResult
+where
+    DB: Database + Send + Sync,
+{
+    insert_models(&state.db)?;
+
+    let (model, children) = smol::run(Model::get_with_children(&state.db, "foo"))?;
+    assert_eq!(model.name(), "foo");
+    assert_eq!(children.len(), 2);
+    assert_eq!(children[0].name(), "bar");
+    assert_eq!(children[1].name(), "baz");
+
+    Ok(())
+}
+
+#[apply(state)]
+fn test_get_with_children_none<DB>(state: State<DB>) -> TestResult
+where
+    DB: Database + Send + Sync,
+{
+    insert_models(&state.db)?;
+
+    assert!(smol::run(Model::get_with_children(&state.db, "bar")).is_err());
+
+    Ok(())
+}
+
+#[apply(state)]
+fn test_item_collection<DB>(state: State<DB>) -> TestResult
+where
+    DB: Database + Send + Sync,
+{
+    insert_models(&state.db)?;
+
+    let collection = smol::run(ItemCollection::query(&state.db, "model#foo"))?;
+    assert_eq!(collection.len(), 3);
+
+    let models: Vec<Model> = collection.entities()?;
+    assert_eq!(models.len(), 1);
+
+    let submodel: Option<SubModel> = collection.entity()?;
+    assert_eq!(submodel.ok_or("submodel")?.name(), "bar");
+
+    Ok(())
+}
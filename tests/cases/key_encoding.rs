@@ -0,0 +1,238 @@
+use chrono::{DateTime, TimeZone, Utc};
+use proptest::prelude::*;
+use rstest::rstest;
+use rstest_reuse::*;
+use std::collections;
+use uuid::Uuid;
+
+use keys::{KeyError, Segment};
+use single_table::*;
+use sortable::SortKey;
+use traits::{Database, Key};
+
+use super::*;
+
+#[test]
+fn test_key_escaping() {
+    let key = KeyBuilder::new()
+        .segment("model", "a#submodel#b")
+        .segment("submodel", "100%")
+        .build();
+    assert_eq!(key, "model#a%23submodel%23b#submodel#100%25");
+
+    let mut parser = KeyParser::new(&key);
+    assert_eq!(parser.segment("model"), Ok("a#submodel#b".to_string()));
+    assert_eq!(parser.segment("submodel"), Ok("100%".to_string()));
+    assert_eq!(parser.finish(), Ok(()));
+}
+
+#[test]
+fn test_key_parse_errors() {
+    let mut parser = KeyParser::new("model#foo");
+    assert!(matches!(
+        parser.segment("submodel"),
+        Err(KeyError::UnexpectedLabel { .. })
+    ));
+
+    assert!(matches!(
+        keys::segments("model#foo#submodel"),
+        Err(KeyError::MissingValue(_))
+    ));
+    assert!(matches!(
+        keys::segments("model#100%"),
+        Err(KeyError::InvalidEscape(_))
+    ));
+
+    let mut parser = KeyParser::new("model#foo#submodel#bar");
+    assert!(parser.segment("model").is_ok());
+    assert!(matches!(
+        parser.finish(),
+        Err(KeyError::TrailingSegments(_))
+    ));
+}
+
+#[test]
+fn test_submodel_parent_name() -> TestResult {
+    let foo = Model::new("a#b", 1)?;
+    let bar = SubModel::new("bar", foo)?;
+    assert_eq!(bar.parent_name()?, "a#b");
+
+    Ok(())
+}
+
+#[apply(state)]
+fn test_model_names_do_not_collide<DB>(state: State<DB>) -> TestResult
+where
+    DB: Database + Send + Sync,
+{
+    insert_models(&state.db)?;
+
+    // Used to share a key with the SubModel bar of foo
+    let mut impostor = Model::new("foo#submodel#bar", 2)?;
+    smol::run(impostor.save(&state.db))?;
+
+    let bar = smol::run(SubModel::get(&state.db, "foo", "bar"))?;
+    assert_eq!(bar.name(), "bar");
+
+    let (foo, children) = smol::run(Model::get_with_children(&state.db, "foo"))?;
+    assert_eq!(foo.value(), 1);
+    assert_eq!(children.len(), 2);
+
+    let impostor = smol::run(Model::get(&state.db, "foo#submodel#bar"))?;
+    assert_eq!(impostor.value(), 2);
+
+    Ok(())
+}
+
+#[test]
+fn test_sortable_segments() {
+    let at = Utc.ymd(2020, 7, 1).and_hms(12, 0, 0);
+    let key = KeyBuilder::new()
+        .sortable("n", &-1i64)
+        .sortable("at", &at)
+        .build();
+    assert_eq!(key, "n#7fffffffffffffff#at#2020-07-01T12:00:00.000000000Z");
+
+    let mut parser = KeyParser::new(&key);
+    assert_eq!(parser.sortable::<i64>("n"), Ok(-1));
+    assert_eq!(parser.sortable("at"), Ok(at));
+    assert_eq!(parser.finish(), Ok(()));
+
+    assert!(matches!(
+        u64::decode("2a"),
+        Err(KeyError::InvalidSortKey(_))
+    ));
+    assert!(matches!(
+        i64::decode("000000000000002A"),
+        Err(KeyError::InvalidSortKey(_))
+    ));
+    assert!(matches!(
+        Uuid::decode("936DA01F9ABD4d9d80C702AF85C822A8"),
+        Err(KeyError::InvalidSortKey(_))
+    ));
+}
+
+// Keys come back from MemoryDB in the order of its BTreeMap, the byte order DynamoDB uses
+fn sorted_by_memorydb<T: SortKey>(values: &[T]) -> Vec<T> {
+    let db = mem::memorydb();
+    smol::run(async {
+        for value in values {
+            let sk = KeyBuilder::new().sortable("value", value).build();
+            db.put_item(traits::make_key("sortable", sk))
+                .await
+                .expect("put");
+        }
+
+        let res = db
+            .query(None, "sortable".to_string(), "value#".to_string())
+            .await
+            .expect("query");
+        res.items
+            .unwrap_or_default()
+            .iter()
+            .map(|item| {
+                let (_, sk) = item.key();
+                KeyParser::new(&sk).sortable("value").expect("sortable")
+            })
+            .collect()
+    })
+}
+
+fn distinct<T: SortKey>(values: &[T]) -> usize {
+    let encoded: collections::HashSet<String> = values.iter().map(SortKey::encode).collect();
+    encoded.len()
+}
+
+// Years 0 to 9999, to the nanosecond
+fn datetime() -> impl Strategy<Value = DateTime<Utc>> {
+    (-62_167_219_200i64..253_402_300_800, 0u32..1_000_000_000)
+        .prop_map(|(secs, nanos)| Utc.timestamp(secs, nanos))
+}
+
+fn uuid() -> impl Strategy<Value = Uuid> {
+    any::<[u8; 16]>().prop_map(Uuid::from_bytes)
+}
+
+proptest! {
+    #[test]
+    fn test_sortable_round_trip(
+        u in any::<u64>(),
+        i in any::<i64>(),
+        f in any::<f64>(),
+        at in datetime(),
+        id in uuid(),
+    ) {
+        prop_assert_eq!(u64::decode(&u.encode()), Ok(u));
+        prop_assert_eq!(i64::decode(&i.encode()), Ok(i));
+        // Compared as bits, so that NaNs and signed zeros are exact
+        prop_assert_eq!(f64::decode(&f.encode()).map(f64::to_bits), Ok(f.to_bits()));
+        prop_assert_eq!(DateTime::<Utc>::decode(&at.encode()), Ok(at));
+        prop_assert_eq!(Uuid::decode(&id.encode()), Ok(id));
+    }
+
+    #[test]
+    fn test_sortable_order(
+        (u1, u2) in (any::<u64>(), any::<u64>()),
+        (i1, i2) in (any::<i64>(), any::<i64>()),
+        (at1, at2) in (datetime(), datetime()),
+        (id1, id2) in (uuid(), uuid()),
+    ) {
+        prop_assert_eq!(u1.cmp(&u2), u1.encode().cmp(&u2.encode()));
+        prop_assert_eq!(i1.cmp(&i2), i1.encode().cmp(&i2.encode()));
+        prop_assert_eq!(at1.cmp(&at2), at1.encode().cmp(&at2.encode()));
+        prop_assert_eq!(id1.cmp(&id2), id1.encode().cmp(&id2.encode()));
+    }
+
+    #[test]
+    fn test_sortable_float_order(f1 in any::<f64>(), f2 in any::<f64>()) {
+        if f1 < f2 {
+            prop_assert!(f1.encode() < f2.encode());
+        }
+        if f1 > f2 {
+            prop_assert!(f1.encode() > f2.encode());
+        }
+    }
+
+    #[test]
+    fn test_sortable_memorydb_order(
+        integers in prop::collection::vec(any::<i64>(), 0..20),
+        floats in prop::collection::vec(any::<f64>().prop_filter("not NaN", |f| !f.is_nan()), 0..20),
+        datetimes in prop::collection::vec(datetime(), 0..20),
+    ) {
+        let sorted = sorted_by_memorydb(&integers);
+        prop_assert_eq!(sorted.len(), distinct(&integers));
+        prop_assert!(sorted.windows(2).all(|pair| pair[0] < pair[1]));
+
+        // Signed zeros are distinct keys, so only non-strict order holds
+        let sorted = sorted_by_memorydb(&floats);
+        prop_assert_eq!(sorted.len(), distinct(&floats));
+        prop_assert!(sorted.windows(2).all(|pair| pair[0] <= pair[1]));
+
+        let sorted = sorted_by_memorydb(&datetimes);
+        prop_assert_eq!(sorted.len(), distinct(&datetimes));
+        prop_assert!(sorted.windows(2).all(|pair| pair[0] < pair[1]));
+    }
+
+    #[test]
+    fn test_key_round_trip(values in prop::collection::vec(".*", 1..4)) {
+        let builder = values
+            .iter()
+            .fold(KeyBuilder::new(), |builder, value| builder.segment("label", value));
+        let key = builder.build();
+
+        let segments = keys::segments(&key).expect("segments");
+        let expected: Vec<Segment> = values
+            .into_iter()
+            .map(|value| Segment { label: "label".to_string(), value })
+            .collect();
+        prop_assert_eq!(segments, expected);
+    }
+
+    #[test]
+    fn test_escaped_prefix(value in ".*", suffix in ".*") {
+        // begins_with queries rely on escaping preserving prefixes
+        let prefix = KeyBuilder::new().segment("label", &value).build();
+        let key = KeyBuilder::new().segment("label", &(value + &suffix)).build();
+        prop_assert!(key.starts_with(&prefix));
+    }
+}
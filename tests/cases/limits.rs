@@ -0,0 +1,151 @@
+use rstest::rstest;
+use rstest_reuse::*;
+
+use single_table::*;
+use traits::Database;
+use types::{AttributeValue, RusotoError};
+use validation::{item_size, MAX_ITEM_BYTES, MAX_NESTING_DEPTH};
+
+use super::*;
+
+fn item(pk: &str, sk: &str) -> types::HashMap {
+    let mut item = types::HashMap::new();
+    item.insert("pk".to_string(), string(pk));
+    item.insert("sk".to_string(), string(sk));
+    item.insert("model".to_string(), string("blob"));
+    item
+}
+
+fn assert_invalid<T, E>(result: Result<T, RusotoError<E>>, expected: ValidationError)
+where
+    T: std::fmt::Debug,
+    E: std::fmt::Debug,
+{
+    match result {
+        Err(RusotoError::Validation(message)) => assert_eq!(message, expected.to_string()),
+        other => panic!("expected {:?}, got {:?}", expected, other),
+    }
+}
+
+#[test]
+fn test_entity_names() {
+    assert_eq!(
+        Model::new("", 1).err(),
+        Some(ValidationError::EmptyName("model"))
+    );
+    assert_eq!(
+        Model::new("x".repeat(2048), 1).err(),
+        Some(ValidationError::PartitionKeyTooLong)
+    );
+
+    let foo = Model::new("foo", 1).expect("foo");
+    assert_eq!(
+        SubModel::new("", foo.clone()).err(),
+        Some(ValidationError::EmptyName("submodel"))
+    );
+    assert_eq!(
+        SubModel::new("x".repeat(1024), foo).err(),
+        Some(ValidationError::SortKeyTooLong)
+    );
+}
+
+#[test]
+fn test_item_size() {
+    // Attribute names count towards the size, as well as values
+    let mut item = types::HashMap::new();
+    item.insert("pk".to_string(), string("abc"));
+    assert_eq!(item_size(&item), 5);
+
+    // Numbers take one byte per two significant digits, plus one
+    item.insert(
+        "n".to_string(),
+        AttributeValue {
+            n: Some("12300".to_string()),
+            ..Default::default()
+        },
+    );
+    assert_eq!(item_size(&item), 5 + 1 + 3);
+
+    // Lists have 3 bytes of overhead, and 1 byte per element
+    item.insert(
+        "l".to_string(),
+        AttributeValue {
+            l: Some(vec![string("a"), string("bc")]),
+            ..Default::default()
+        },
+    );
+    assert_eq!(item_size(&item), 5 + 4 + 1 + 3 + 2 + 3);
+}
+
+#[apply(state)]
+fn test_empty_key<DB>(state: State<DB>)
+where
+    DB: Database + Send + Sync,
+{
+    assert_invalid(
+        smol::run(state.db.put_item(item("", "blob"))),
+        ValidationError::EmptyKey("pk".to_string()),
+    );
+    assert_invalid(
+        smol::run(state.db.get_item("blob", "")),
+        ValidationError::EmptyKey("sk".to_string()),
+    );
+}
+
+#[apply(state)]
+fn test_key_too_long<DB>(state: State<DB>)
+where
+    DB: Database + Send + Sync,
+{
+    assert_invalid(
+        smol::run(state.db.put_item(item(&"x".repeat(2049), "blob"))),
+        ValidationError::PartitionKeyTooLong,
+    );
+    assert_invalid(
+        smol::run(state.db.put_item(item("blob", &"x".repeat(1025)))),
+        ValidationError::SortKeyTooLong,
+    );
+}
+
+#[apply(state)]
+fn test_item_too_large<DB>(state: State<DB>) -> TestResult
+where
+    DB: Database + Send + Sync,
+{
+    let mut blob = item("blob", "blob");
+    let overhead = item_size(&blob) + "data".len();
+    blob.insert(
+        "data".to_string(),
+        string(&"x".repeat(MAX_ITEM_BYTES - overhead)),
+    );
+    smol::run(state.db.put_item(blob.clone()))?;
+
+    blob.insert("data".to_string(), string(&"x".repeat(MAX_ITEM_BYTES)));
+    assert_invalid(
+        smol::run(state.db.put_item(blob)),
+        ValidationError::ItemTooLarge,
+    );
+
+    Ok(())
+}
+
+#[apply(state)]
+fn test_nesting_too_deep<DB>(state: State<DB>)
+where
+    DB: Database + Send + Sync,
+{
+    let mut nested = string("deep");
+    for _ in 0..=MAX_NESTING_DEPTH {
+        nested = AttributeValue {
+            l: Some(vec![nested]),
+            ..Default::default()
+        };
+    }
+
+    let mut deep = item("deep", "deep");
+    deep.insert("nested".to_string(), nested);
+    assert_invalid(
+        smol::run(state.db.put_item(deep)),
+        ValidationError::NestingTooDeep,
+    );
+}
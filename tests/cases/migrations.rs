@@ -0,0 +1,168 @@
+use rstest::rstest;
+use rstest_reuse::*;
+
+use migrate::{MigrateOptions, MigrationState, Migrations};
+use single_table::*;
+use traits::{Database, Key};
+
+use super::*;
+
+// SubModels were once keyed by their parent and name
+fn legacy_submodel(parent: &Model, name: &str) -> Result<types::HashMap, Box<dyn Error>> {
+    let mut item = SubModel::new(name, parent.clone())?.to_hashmap()?;
+    let sk = format!("{}#submodel#{}", parent.sk(), name);
+    item.insert("sk".to_string(), string(&sk));
+    Ok(item)
+}
+
+fn tag(item: &types::HashMap) -> Option<types::HashMap> {
+    let mut item = item.clone();
+    item.insert("tagged".to_string(), string("yes"));
+    Some(item)
+}
+
+#[apply(state)]
+fn test_migrate_legacy_submodels<DB>(state: State<DB>) -> TestResult
+where
+    DB: Database + Send + Sync,
+{
+    let migrations = Migrations::default();
+    let names = vec!["a", "b", "c", "d", "e"];
+
+    smol::run(async {
+        let mut foo = Model::new("foo", 1)?;
+        foo.save(&state.db).await?;
+        for name in &names {
+            state.db.put_item(legacy_submodel(&foo, name)?).await?;
+        }
+
+        let before = MigrationState::load(&state.db).await?;
+        assert_eq!(before.version(), 0);
+        assert_eq!(migrations.pending(&before).len(), 1);
+
+        // A dry run only counts
+        let options = MigrateOptions {
+            dry_run: true,
+            page_size: 2,
+        };
+        let reports = migrations.up(&state.db, &options).await?;
+        assert_eq!(reports[0].scanned, 6);
+        assert_eq!(reports[0].rewritten, 5);
+        assert!(SubModel::get(&state.db, "foo", "a").await.is_err());
+        assert_eq!(MigrationState::load(&state.db).await?.version(), 0);
+
+        let options = MigrateOptions {
+            dry_run: false,
+            page_size: 2,
+        };
+        let reports = migrations.up(&state.db, &options).await?;
+        assert_eq!(reports.len(), 1);
+        assert_eq!(reports[0].version, 1);
+        assert_eq!(reports[0].rewritten, 5);
+
+        let children = SubModel::children_of(&state.db, "foo").await?;
+        let migrated: Vec<String> = children.iter().map(SubModel::name).collect();
+        assert_eq!(migrated, names);
+
+        let legacy = state
+            .db
+            .get_item("model#foo", "model#foo#submodel#a")
+            .await?;
+        assert!(legacy.item.is_none());
+
+        // Nothing is left to do
+        let after = MigrationState::load(&state.db).await?;
+        assert_eq!(after.version(), 1);
+        assert!(migrations.pending(&after).is_empty());
+        assert!(migrations.up(&state.db, &options).await?.is_empty());
+
+        Ok(())
+    })
+}
+
+#[apply(state)]
+fn test_migrate_resume<DB>(state: State<DB>) -> TestResult
+where
+    DB: Database + Send + Sync,
+{
+    let migrations = Migrations::empty().register(1, "tag", tag);
+
+    smol::run(async {
+        for name in &["a", "b", "c", "d", "e", "f"] {
+            Model::new(*name, 1)?.save(&state.db).await?;
+        }
+
+        // Interrupted after the first page
+        let first_page = state.db.scan_from(None::<String>, Some(2), None).await?;
+        let (pk, sk) = first_page.last_evaluated_key.expect("more pages").key();
+
+        let key = MigrationState::key();
+        let mut interrupted = types::HashMap::new();
+        interrupted.insert("pk".to_string(), string(&key));
+        interrupted.insert("sk".to_string(), string(&key));
+        interrupted.insert("model".to_string(), string(MigrationState::MODEL));
+        interrupted.insert("version".to_string(), number(0));
+        interrupted.insert("in_progress".to_string(), number(1));
+        interrupted.insert("cursor_pk".to_string(), string(&pk));
+        interrupted.insert("cursor_sk".to_string(), string(&sk));
+        state.db.put_item(interrupted).await?;
+
+        let state_before = MigrationState::load(&state.db).await?;
+        assert_eq!(state_before.in_progress(), Some(1));
+
+        let options = MigrateOptions {
+            dry_run: false,
+            page_size: 2,
+        };
+        let reports = migrations.up(&state.db, &options).await?;
+        assert_eq!(reports[0].rewritten, 4);
+
+        // Only the items after the cursor were rewritten
+        let skipped: Vec<types::HashMap> = first_page.items.unwrap_or_default();
+        for item in &skipped {
+            let (pk, sk) = item.key();
+            let item = state.db.get_item(pk, sk).await?.item.expect("item");
+            assert!(!item.contains_key("tagged"));
+        }
+
+        let res = state.db.scan(None::<String>, None).await?;
+        let tagged = res
+            .items
+            .unwrap_or_default()
+            .iter()
+            .filter(|item| item.contains_key("tagged"))
+            .count();
+        assert_eq!(tagged, 4);
+
+        let state_after = MigrationState::load(&state.db).await?;
+        assert_eq!(state_after.version(), 1);
+        assert_eq!(state_after.in_progress(), None);
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_legacy_submodel_rewrite() -> TestResult {
+    let foo = Model::new("foo", 1)?;
+    let legacy = legacy_submodel(&foo, "bar")?;
+
+    let migrated = migrate::submodel_sk_by_name(&legacy).expect("rewritten");
+    assert_eq!(
+        migrated.key(),
+        ("model#foo".to_string(), "submodel#bar".to_string())
+    );
+
+    // Legacy names were not escaped, but are in their new key
+    for (name, sk) in &[("50%off", "submodel#50%25off"), ("a#b", "submodel#a%23b")] {
+        let legacy = legacy_submodel(&foo, name)?;
+        let migrated = migrate::submodel_sk_by_name(&legacy).expect("rewritten");
+        assert_eq!(migrated.key(), ("model#foo".to_string(), sk.to_string()));
+    }
+
+    // Already migrated items, and other entities, are left alone
+    assert!(migrate::submodel_sk_by_name(&migrated).is_none());
+    assert!(migrate::submodel_sk_by_name(&foo.to_hashmap()?).is_none());
+
+    Ok(())
+}
@@ -1,16 +1,74 @@
 use async_trait::async_trait;
 use futures_intrusive::sync::{Semaphore, SemaphoreReleaser};
 use once_cell::sync::Lazy;
+use rstest::rstest;
+use rstest_reuse::*;
 use smol_timeout::TimeoutExt;
 use std::{error::Error, time::Duration};
 
 use single_table::*;
 use traits::{Database, Key};
 
-mod database;
-
 type TestResult = Result<(), Box<dyn Error>>;
 
+struct State<DB>
+where
+    DB: Database + Send + Sync,
+{
+    db: TemporaryDatabase<DB>,
+}
+
+#[cfg(feature = "external_database")]
+impl State<ddb::DDB> {
+    fn new() -> Self {
+        Self { db: dynamodb() }
+    }
+}
+
+impl State<mem::MemoryDB> {
+    fn new() -> Self {
+        Self { db: memorydb() }
+    }
+}
+
+// The template must be defined before the test modules that apply it
+#[template]
+#[rstest(state,
+    #[cfg(feature = "external_database")]
+    case::ddb(State::<ddb::DDB>::new()),
+    case::mem(State::<mem::MemoryDB>::new()),
+)]
+fn state<DB>(state: State<DB>)
+where
+    DB: Database + Send + Sync,
+{
+}
+
+mod cascade;
+mod changes;
+mod consistency;
+mod consumed_capacity;
+mod counters;
+mod database;
+mod decoding;
+mod differential;
+mod edges;
+mod event_log;
+mod idempotency;
+mod item_collection;
+mod key_encoding;
+mod limits;
+mod migrations;
+mod replay;
+mod reverse_lookup;
+mod revisions;
+mod schema_versions;
+mod sharding;
+mod snapshots;
+mod transactions;
+mod ttl;
+mod unique_constraints;
+
 #[cfg(feature = "external_database")]
 pub use rusoto_dynamodb::DynamoDbClient;
 
@@ -136,11 +194,31 @@ impl<DB: Database + Send + Sync> Database for TemporaryDatabase<DB> {
         self.0.scan(index, limit).await
     }
 
+    async fn scan_from<S: Into<String> + Send>(
+        &self,
+        index: Option<S>,
+        limit: Option<i64>,
+        exclusive_start_key: Option<types::HashMap>,
+    ) -> types::ScanResult {
+        let _sem = self.acquire().await;
+        self.0.scan_from(index, limit, exclusive_start_key).await
+    }
+
     async fn get_item<S: Into<String> + Send>(&self, pk: S, sk: S) -> types::GetItemResult {
         let _sem = self.acquire().await;
         self.0.get_item(pk, sk).await
     }
 
+    async fn get_item_with<S: Into<String> + Send>(
+        &self,
+        pk: S,
+        sk: S,
+        options: traits::ReadOptions,
+    ) -> types::GetItemResult {
+        let _sem = self.acquire().await;
+        self.0.get_item_with(pk, sk, options).await
+    }
+
     async fn put_item<H: Into<types::HashMap> + Key + Send>(
         &self,
         hashmap: H,
@@ -159,6 +237,28 @@ impl<DB: Database + Send + Sync> Database for TemporaryDatabase<DB> {
         self.0.query(index, pk, sk).await
     }
 
+    async fn query_with<S: Into<String> + Send>(
+        &self,
+        index: Option<S>,
+        pk: S,
+        condition: traits::SortKeyCondition,
+        options: traits::QueryOptions,
+    ) -> types::QueryResult {
+        let _sem = self.acquire().await;
+        self.0.query_with(index, pk, condition, options).await
+    }
+
+    async fn increment<S: Into<String> + Send>(
+        &self,
+        pk: S,
+        sk: S,
+        attribute: S,
+        by: i64,
+    ) -> types::UpdateItemResult {
+        let _sem = self.acquire().await;
+        self.0.increment(pk, sk, attribute, by).await
+    }
+
     async fn transact_write_items(
         &self,
         transact_items: Vec<types::TransactWriteItem>,
@@ -166,12 +266,56 @@ impl<DB: Database + Send + Sync> Database for TemporaryDatabase<DB> {
         let _sem = self.acquire().await;
         self.0.transact_write_items(transact_items).await
     }
+
+    async fn transact_get_items(
+        &self,
+        keys: Vec<(String, String)>,
+    ) -> types::TransactGetItemsResult {
+        let _sem = self.acquire().await;
+        self.0.transact_get_items(keys).await
+    }
+
+    async fn transact_write_items_with(
+        &self,
+        transact_items: Vec<types::TransactWriteItem>,
+        options: traits::TransactOptions,
+    ) -> types::TransactWriteItemsResult {
+        let _sem = self.acquire().await;
+        self.0
+            .transact_write_items_with(transact_items, options)
+            .await
+    }
+
+    async fn update_time_to_live<S: Into<String> + Send>(
+        &self,
+        attribute_name: S,
+        enabled: bool,
+    ) -> types::UpdateTimeToLiveResult {
+        let _sem = self.acquire().await;
+        self.0.update_time_to_live(attribute_name, enabled).await
+    }
+
+    async fn describe_time_to_live(&self) -> types::DescribeTimeToLiveResult {
+        let _sem = self.acquire().await;
+        self.0.describe_time_to_live().await
+    }
+}
+
+fn string(s: &str) -> types::AttributeValue {
+    types::string(s)
+}
+
+fn number(n: i64) -> types::AttributeValue {
+    types::AttributeValue {
+        n: Some(n.to_string()),
+        ..Default::default()
+    }
 }
 
 fn insert_models(db: &impl Database) -> TestResult {
-    let foo: Model = Model::new("foo", 1);
-    let bar: SubModel = SubModel::new("bar", foo.clone());
-    let baz: SubModel = SubModel::new("baz", foo.clone());
+    let foo: Model = Model::new("foo", 1)?;
+    let bar: SubModel = SubModel::new("bar", foo.clone())?;
+    let baz: SubModel = SubModel::new("baz", foo.clone())?;
 
     let items: Vec<types::HashMap> = vec![
         serde_dynamodb::to_hashmap(&foo)?,
@@ -0,0 +1,63 @@
+use chrono::{TimeZone, Utc};
+
+use clock::FixedClock;
+use single_table::*;
+use traits::{Database, QueryOptions, SortKeyCondition};
+
+use super::*;
+
+// Fixtures are recorded with a fixed table name so that request bodies are stable
+const TABLE_NAME: &str = "single-table-replay";
+const FIXTURE: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/replay.json");
+
+async fn scenario(db: &impl Database) -> TestResult {
+    // Request bodies must match the fixture exactly
+    let clock = FixedClock::new(Utc.ymd(2020, 7, 1).and_hms_nano(12, 0, 0, 123_456_789));
+
+    db.create_table().await?;
+
+    let mut foo = Model::new_with_clock("foo", 1, &clock)?;
+    foo.save(db).await?;
+
+    let mut bar = SubModel::new_with_clock("bar", foo.clone(), &clock)?;
+    bar.save(db).await?;
+
+    let items = db.query(None, "model#foo", "submodel#").await?;
+    assert_eq!(items.count, Some(1));
+
+    // An empty prefix selects the whole partition
+    let all = SortKeyCondition::BeginsWith(String::new());
+    let items = db
+        .query_with(None, "model#foo", all, QueryOptions::default())
+        .await?;
+    assert_eq!(items.count, Some(2));
+
+    let model = Model::get(db, "foo").await?;
+    assert_eq!(model.name(), "foo");
+    assert_eq!(model.value(), 1);
+
+    db.delete_table().await?;
+    Ok(())
+}
+
+#[test]
+fn test_replay() -> TestResult {
+    let db = record::replaying(TABLE_NAME, FIXTURE)?;
+    smol::run(scenario(&db))
+}
+
+// Re-record the fixture against dynamodb-local with
+//
+//     $ cargo test --features external_database test_record -- --ignored
+#[cfg(feature = "external_database")]
+#[test]
+#[ignore]
+fn test_record() -> TestResult {
+    let endpoint_url = env::ensure_var("AWS_ENDPOINT_URL");
+    let region = env::resolve_region(None, Some(endpoint_url))?;
+
+    let (db, recording) = record::recording(region, TABLE_NAME, FIXTURE)?;
+    smol::run(scenario(&db))?;
+
+    recording.save()
+}
@@ -0,0 +1,53 @@
+use rstest::rstest;
+use rstest_reuse::*;
+
+use single_table::*;
+use traits::Database;
+
+use super::*;
+
+#[apply(state)]
+fn test_parents_of<DB>(state: State<DB>) -> TestResult
+where
+    DB: Database + Send + Sync,
+{
+    insert_models(&state.db)?;
+
+    smol::run(async {
+        let mut qux = Model::new("qux", 2)?;
+        qux.save(&state.db).await?;
+        SubModel::new("bar", qux)?.save(&state.db).await?;
+
+        Ok::<_, Box<dyn Error>>(())
+    })?;
+
+    let parents = smol::run(SubModel::parents_of(&state.db, "bar"))?;
+    assert_eq!(parents, vec!["foo", "qux"]);
+
+    let parents = smol::run(SubModel::parents_of(&state.db, "baz"))?;
+    assert_eq!(parents, vec!["foo"]);
+
+    assert!(smol::run(SubModel::parents_of(&state.db, "missing"))?.is_empty());
+
+    Ok(())
+}
+
+#[apply(state)]
+fn test_query_inverted<DB>(state: State<DB>) -> TestResult
+where
+    DB: Database + Send + Sync,
+{
+    insert_models(&state.db)?;
+
+    // The Model itself is in the inverted partition of its own key
+    let items = smol::run(state.db.query(Some("inverted"), "model#foo", ""))?;
+    assert_eq!(items.count, Some(1));
+
+    let items = smol::run(state.db.query(Some("inverted"), "submodel#bar", "model#f"))?;
+    assert_eq!(items.count, Some(1));
+
+    let items = smol::run(state.db.query(Some("inverted"), "submodel#bar", "model#q"))?;
+    assert_eq!(items.count, Some(0));
+
+    Ok(())
+}
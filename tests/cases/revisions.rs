@@ -0,0 +1,180 @@
+use chrono::{Duration, TimeZone, Utc};
+use rstest::rstest;
+use rstest_reuse::*;
+
+use clock::ManualClock;
+use history::{Audit, Revision};
+use single_table::*;
+use traits::Database;
+
+use super::*;
+
+fn audit(by: &str) -> Audit {
+    Audit::new(Some(by.to_string()))
+}
+
+#[apply(state)]
+fn test_save_with_history<DB>(state: State<DB>) -> TestResult
+where
+    DB: Database + Send + Sync,
+{
+    smol::run(async {
+        let mut foo = Model::new("foo", 1)?;
+        assert_eq!(foo.save_with_history(&state.db, &audit("alice")).await?, 1);
+
+        let mut foo = Model::get(&state.db, "foo").await?;
+        assert_eq!(foo.revision(), Some(1));
+        let mut changed = Model::new("foo", 2)?.replacing(&foo);
+        assert_eq!(
+            changed.save_with_history(&state.db, &audit("bob")).await?,
+            2
+        );
+
+        // A stale copy cannot overwrite a newer revision
+        assert!(foo
+            .save_with_history(&state.db, &audit("carol"))
+            .await
+            .is_err());
+        assert_eq!(Model::get(&state.db, "foo").await?.value(), 2);
+
+        let history = Revision::history(&state.db, "foo").await?;
+        let revisions: Vec<(u64, i32, Option<&str>)> = history
+            .iter()
+            .map(|revision| {
+                (
+                    revision.revision(),
+                    revision.model().value(),
+                    revision.changed_by(),
+                )
+            })
+            .collect();
+        assert_eq!(revisions, vec![(1, 1, Some("alice")), (2, 2, Some("bob"))]);
+
+        // Revisions are not mistaken for the Model itself
+        let (model, children) = Model::get_with_children(&state.db, "foo").await?;
+        assert_eq!(model.value(), 2);
+        assert!(children.is_empty());
+
+        Ok(())
+    })
+}
+
+#[apply(state)]
+fn test_soft_delete_and_restore<DB>(state: State<DB>) -> TestResult
+where
+    DB: Database + Send + Sync,
+{
+    smol::run(async {
+        let mut foo = Model::new("foo", 1)?.with_email("a@example.com")?;
+        foo.save_with_history(&state.db, &audit("alice")).await?;
+
+        assert_eq!(
+            Model::soft_delete(&state.db, "foo", &audit("bob")).await?,
+            2
+        );
+        assert!(Model::get(&state.db, "foo").await.is_err());
+
+        let history = Revision::history(&state.db, "foo").await?;
+        assert_eq!(history.len(), 2);
+        assert!(history[1].is_deleted());
+        assert_eq!(history[1].model().email(), Some("a@example.com"));
+
+        // The email was released with the Model
+        Model::new("bar", 1)?
+            .with_email("a@example.com")?
+            .save(&state.db)
+            .await?;
+        assert!(Model::restore(&state.db, "foo", None, &audit("carol"))
+            .await
+            .is_err());
+        Model::delete(&state.db, "bar").await?;
+
+        let restored = Model::restore(&state.db, "foo", None, &audit("carol")).await?;
+        assert_eq!(restored.revision(), Some(3));
+        assert_eq!(restored.email(), Some("a@example.com"));
+        assert_eq!(Model::get(&state.db, "foo").await?.value(), 1);
+
+        // A tombstone cannot be restored
+        assert!(Model::restore(&state.db, "foo", Some(2), &audit("carol"))
+            .await
+            .is_err());
+
+        Ok(())
+    })
+}
+
+#[apply(state)]
+fn test_history_after_recreate<DB>(state: State<DB>) -> TestResult
+where
+    DB: Database + Send + Sync,
+{
+    smol::run(async {
+        let mut foo = Model::new("foo", 1)?;
+        foo.save_with_history(&state.db, &audit("alice")).await?;
+        Model::soft_delete(&state.db, "foo", &audit("alice")).await?;
+
+        // A new Model with the same name continues the history
+        let mut foo = Model::new("foo", 2)?;
+        assert_eq!(foo.save_with_history(&state.db, &audit("bob")).await?, 3);
+
+        let restored = Model::restore(&state.db, "foo", Some(1), &audit("bob")).await?;
+        assert_eq!(restored.revision(), Some(4));
+        assert_eq!(restored.value(), 1);
+
+        Ok(())
+    })
+}
+
+#[apply(state)]
+fn test_delete_with_history<DB>(state: State<DB>) -> TestResult
+where
+    DB: Database + Send + Sync,
+{
+    smol::run(async {
+        let mut foo = Model::new("foo", 1)?;
+        for _ in 0..3 {
+            foo.save_with_history(&state.db, &audit("alice")).await?;
+        }
+        assert_eq!(Revision::latest(&state.db, "foo").await?, 3);
+        assert_eq!(Revision::latest(&state.db, "bar").await?, 0);
+
+        // Deleting would leave the revisions behind
+        assert!(Model::delete(&state.db, "foo").await.is_err());
+        assert!(Model::delete_cascade(&state.db, "foo").await.is_err());
+        assert_eq!(Model::get(&state.db, "foo").await?.revision(), Some(3));
+
+        assert_eq!(
+            Model::soft_delete(&state.db, "foo", &audit("bob")).await?,
+            4
+        );
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_revision_keys() -> TestResult {
+    let changed_at = Utc.ymd(2020, 7, 1).and_hms(12, 0, 0);
+    let clock = ManualClock::new(changed_at);
+    let audit = Audit::new_with_clock(None, &clock);
+
+    assert_eq!(Revision::key_of("foo", 12), "model#foo#v#000000000000000c");
+
+    let db = mem::memorydb();
+    let mut foo = Model::new("foo", 1)?.expire_at(changed_at + Duration::days(1));
+    smol::run(foo.save_with_history(&db, &audit))?;
+
+    let item = smol::run(db.get_item("model#foo", "model#foo#v#0000000000000001"))?
+        .item
+        .expect("revision");
+    assert_eq!(collection::model(&item), Some(Revision::MODEL));
+    assert!(!item.contains_key(TTL_ATTRIBUTE));
+
+    let revision = Revision::from_hashmap(&item)?;
+    assert_eq!(revision.changed_at(), changed_at);
+    assert_eq!(revision.changed_by(), None);
+    assert_eq!(revision.model().sk(), "model#foo");
+    assert_eq!(revision.model().expires_at(), None);
+
+    Ok(())
+}
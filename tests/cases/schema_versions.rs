@@ -0,0 +1,95 @@
+use chrono::{TimeZone, Utc};
+use rstest::rstest;
+use rstest_reuse::*;
+
+use single_table::*;
+use traits::Database;
+
+use super::*;
+
+const CREATED_AT: &str = "2020-07-01T12:00:00Z";
+const UPDATED_AT: &str = "2020-07-02T12:00:00Z";
+
+fn attribute(item: &mut types::HashMap, name: &str, value: types::AttributeValue) {
+    item.insert(name.to_string(), value);
+}
+
+// A Model as written before `schema_version` was introduced
+fn version_1() -> types::HashMap {
+    let mut item = types::HashMap::new();
+    attribute(&mut item, "name", string("foo"));
+    attribute(&mut item, "a_number", number(1));
+    attribute(&mut item, "created_at", string(CREATED_AT));
+    attribute(&mut item, "pk", string("model#foo"));
+    attribute(&mut item, "sk", string("model#foo"));
+    attribute(&mut item, "model", string("model"));
+    item
+}
+
+// Version 2 added `updated_at`
+fn version_2() -> types::HashMap {
+    let mut item = version_1();
+    attribute(&mut item, "updated_at", string(UPDATED_AT));
+    attribute(&mut item, schema::VERSION_ATTRIBUTE, number(2));
+    item
+}
+
+#[test]
+fn test_upcast_version_1() -> TestResult {
+    assert_eq!(schema::version(&version_1())?, 1);
+
+    let model = Model::from_hashmap(&version_1())?;
+    assert_eq!(model.name(), "foo");
+    assert_eq!(model.updated_at(), model.created_at());
+    assert_eq!(model.schema_version(), Model::SCHEMA_VERSION);
+
+    Ok(())
+}
+
+#[test]
+fn test_decode_version_2() -> TestResult {
+    let model = Model::from_hashmap(&version_2())?;
+    assert_eq!(model.created_at(), Utc.ymd(2020, 7, 1).and_hms(12, 0, 0));
+    assert_eq!(model.updated_at(), Utc.ymd(2020, 7, 2).and_hms(12, 0, 0));
+    assert_eq!(model.schema_version(), 2);
+
+    Ok(())
+}
+
+#[test]
+fn test_reject_newer_versions() {
+    let mut item = version_2();
+    attribute(
+        &mut item,
+        schema::VERSION_ATTRIBUTE,
+        number(i64::from(Model::SCHEMA_VERSION) + 1),
+    );
+    assert!(Model::from_hashmap(&item).is_err());
+
+    attribute(&mut item, schema::VERSION_ATTRIBUTE, number(0));
+    assert!(Model::from_hashmap(&item).is_err());
+}
+
+#[apply(state)]
+fn test_save_writes_version<DB>(state: State<DB>) -> TestResult
+where
+    DB: Database + Send + Sync,
+{
+    smol::run(async {
+        // Reading an old item and saving it again upgrades it in the table
+        state.db.put_item(version_1()).await?;
+        let mut model = Model::get(&state.db, "foo").await?;
+        model.save(&state.db).await?;
+
+        let item = state
+            .db
+            .get_item("model#foo", "model#foo")
+            .await?
+            .item
+            .expect("model");
+        assert_eq!(schema::version(&item)?, Model::SCHEMA_VERSION);
+        assert_eq!(item["updated_at"], string(CREATED_AT));
+
+        Ok(())
+    })
+}
@@ -0,0 +1,274 @@
+use rstest::rstest;
+use rstest_reuse::*;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+
+use single_table::*;
+use traits::{Database, QueryOptions};
+
+use super::*;
+
+const SHARDS: u32 = 4;
+
+fn item(pk: &str, sk: &str) -> types::HashMap {
+    let mut item = types::HashMap::new();
+    item.insert("pk".to_string(), string(pk));
+    item.insert("sk".to_string(), string(sk));
+    item.insert("model".to_string(), string(SubModel::MODEL));
+    item
+}
+
+// A child spread across shards, to exercise the fan-out of reads and deletes of its parent
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct Reading {
+    pk: String,
+    sk: String,
+    model: String,
+    name: String,
+}
+
+impl Reading {
+    fn new(parent: &str, name: &str) -> Self {
+        let sk = Self::key_of(name).build();
+        Self {
+            pk: shard::key(Model::key_of(parent), &sk, Self::SHARDS),
+            sk,
+            model: Self::MODEL.to_string(),
+            name: name.to_string(),
+        }
+    }
+}
+
+impl Entity for Reading {
+    const MODEL: &'static str = "reading";
+    const SHARDS: u32 = SHARDS;
+
+    fn from_hashmap(hashmap: &types::HashMap) -> serde_dynamodb::error::Result<Self> {
+        serde_dynamodb::from_hashmap(hashmap.to_owned())
+    }
+}
+
+impl Child for Reading {
+    fn key_of(name: &str) -> KeyBuilder {
+        KeyBuilder::new().segment(Self::MODEL, name)
+    }
+
+    fn prefix() -> String {
+        KeyBuilder::new().label_prefix(Self::MODEL)
+    }
+}
+
+// The Model `foo` with its SubModels, and a Reading called each of `names`
+fn insert_readings(db: &impl Database, names: &[String]) -> TestResult {
+    insert_models(db)?;
+
+    smol::run(async {
+        for name in names {
+            db.put_item(serde_dynamodb::to_hashmap(&Reading::new("foo", name))?)
+                .await?;
+        }
+        Ok(())
+    })
+}
+
+fn attribute<'a>(item: &'a types::HashMap, name: &str) -> &'a str {
+    item[name].s.as_deref().expect("string attribute")
+}
+
+#[test]
+fn test_shard_of() {
+    // Shards must not change between releases, or items would be lost
+    let shards: Vec<u32> = ["submodel#a", "submodel#b", "submodel#c", "submodel#d"]
+        .iter()
+        .map(|sk| shard::shard_of(sk, SHARDS))
+        .collect();
+    assert_eq!(shards, vec![2, 3, 0, 1]);
+
+    assert_eq!(shard::shard_of("submodel#a", 1), 0);
+}
+
+#[test]
+fn test_shard_keys() {
+    assert_eq!(
+        shard::key(Model::key_of("foo"), "submodel#a", SHARDS),
+        "model#foo#shard#2"
+    );
+    assert_eq!(
+        shard::key(Model::key_of("foo"), "submodel#a", 1),
+        "model#foo"
+    );
+
+    assert_eq!(
+        shard::partitions(Model::key_of("foo"), SHARDS),
+        vec![
+            "model#foo#shard#0",
+            "model#foo#shard#1",
+            "model#foo#shard#2",
+            "model#foo#shard#3",
+        ]
+    );
+    assert_eq!(
+        shard::partitions(Model::key_of("foo"), 1),
+        vec!["model#foo"]
+    );
+}
+
+#[apply(state)]
+fn test_sharded_query<DB>(state: State<DB>) -> TestResult
+where
+    DB: Database + Send + Sync,
+{
+    // Written out of order, to check the merge
+    let names = ["h", "c", "a", "f", "d", "b", "g", "e"];
+
+    smol::run(async {
+        for name in &names {
+            let sk = SubModel::key_of(name).build();
+            let pk = shard::key(Model::key_of("foo"), &sk, SHARDS);
+            state.db.put_item(item(&pk, &sk)).await?;
+        }
+
+        // Items of other partitions, or without the prefix, are not included
+        state
+            .db
+            .put_item(item("model#foo#shard#0", "edge#bar"))
+            .await?;
+        state
+            .db
+            .put_item(item("model#bar#shard#0", "submodel#a"))
+            .await?;
+
+        let partitions = shard::partitions(Model::key_of("foo"), SHARDS);
+        let res = shard::query(&state.db, partitions, SubModel::prefix()).await?;
+        assert_eq!(res.count, Some(8));
+
+        let items = res.items.unwrap_or_default();
+        let sks: Vec<&str> = items.iter().map(|item| attribute(item, "sk")).collect();
+        assert_eq!(
+            sks,
+            vec![
+                "submodel#a",
+                "submodel#b",
+                "submodel#c",
+                "submodel#d",
+                "submodel#e",
+                "submodel#f",
+                "submodel#g",
+                "submodel#h",
+            ]
+        );
+
+        // Every shard took some of the writes
+        let pks: BTreeSet<&str> = items.iter().map(|item| attribute(item, "pk")).collect();
+        assert_eq!(pks.len(), SHARDS as usize);
+
+        // Every page of every shard is read, rather than resuming from one shard's key
+        let partitions = shard::partitions(Model::key_of("foo"), SHARDS);
+        let options = QueryOptions {
+            limit: Some(1),
+            ..Default::default()
+        };
+        let res = shard::query_with(&state.db, partitions, SubModel::prefix(), options).await?;
+        assert_eq!(res.count, Some(8));
+        assert!(res.last_evaluated_key.is_none());
+
+        Ok(())
+    })
+}
+
+#[apply(state)]
+fn test_unsharded_children<DB>(state: State<DB>) -> TestResult
+where
+    DB: Database + Send + Sync,
+{
+    insert_models(&state.db)?;
+
+    // Without sharding, the SubModels stay in the partition of their parent
+    assert_eq!(SubModel::partitions("foo"), vec!["model#foo"]);
+
+    let children = smol::run(SubModel::children_of(&state.db, "foo"))?;
+    let names: Vec<String> = children.iter().map(SubModel::name).collect();
+    assert_eq!(names, vec!["bar", "baz"]);
+
+    Ok(())
+}
+
+#[apply(state)]
+fn test_sharded_get_with<DB>(state: State<DB>) -> TestResult
+where
+    DB: Database + Send + Sync,
+{
+    // Written out of order, to check the merge
+    let names: Vec<String> = ["h", "c", "a", "f", "d", "b", "g", "e"]
+        .iter()
+        .map(|name| name.to_string())
+        .collect();
+    insert_readings(&state.db, &names)?;
+
+    let (model, readings) = smol::run(Model::get_with::<Reading, _>(&state.db, "foo"))?;
+    assert_eq!(model.name(), "foo");
+
+    // The SubModels in the Model's own partition are not included
+    let got: Vec<&str> = readings.iter().map(|r| r.name.as_str()).collect();
+    assert_eq!(got, vec!["a", "b", "c", "d", "e", "f", "g", "h"]);
+
+    let pks: BTreeSet<&str> = readings.iter().map(|r| r.pk.as_str()).collect();
+    assert_eq!(pks.len(), SHARDS as usize);
+
+    Ok(())
+}
+
+#[apply(state)]
+fn test_sharded_snapshot<DB>(state: State<DB>) -> TestResult
+where
+    DB: Database + Send + Sync,
+{
+    let names: Vec<String> = ["a", "b", "c", "d"].iter().map(|n| n.to_string()).collect();
+    insert_readings(&state.db, &names)?;
+
+    let wanted: Vec<String> = ["d", "z", "a"].iter().map(|n| n.to_string()).collect();
+    let (model, readings) = smol::run(Model::get_snapshot_with::<Reading, _>(
+        &state.db, "foo", &wanted,
+    ))?;
+    assert_eq!(model.name(), "foo");
+
+    assert_eq!(
+        readings,
+        vec![
+            Some(Reading::new("foo", "d")),
+            None,
+            Some(Reading::new("foo", "a"))
+        ]
+    );
+
+    Ok(())
+}
+
+#[apply(state)]
+fn test_sharded_delete_cascade<DB>(state: State<DB>) -> TestResult
+where
+    DB: Database + Send + Sync,
+{
+    // More than fit in one transaction, across every shard
+    let names: Vec<String> = (0..traits::MAX_TRANSACTION_ITEMS + 20)
+        .map(|i| format!("{:03}", i))
+        .collect();
+    insert_readings(&state.db, &names)?;
+
+    smol::run(async {
+        let deleted = Model::delete_cascade_with::<Reading, _>(&state.db, "foo").await?;
+        assert_eq!(deleted, names.len() + 1);
+
+        let partitions = shard::partitions(Model::key_of("foo"), SHARDS);
+        let res = shard::query(&state.db, partitions, Reading::prefix()).await?;
+        assert_eq!(res.count, Some(0));
+
+        assert!(Model::get(&state.db, "foo").await.is_err());
+
+        // Only Readings were cascaded, the SubModels are left behind
+        let children = SubModel::children_of(&state.db, "foo").await?;
+        assert_eq!(children.len(), 2);
+
+        Ok(())
+    })
+}
@@ -0,0 +1,126 @@
+use rstest::rstest;
+use rstest_reuse::*;
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread,
+};
+
+use single_table::*;
+use traits::{Database, Key};
+
+use super::*;
+
+#[apply(state)]
+fn test_get_snapshot<DB>(state: State<DB>) -> TestResult
+where
+    DB: Database + Send + Sync,
+{
+    smol::run(async {
+        let foo = Model::new("foo", 1)?;
+        foo.clone().save(&state.db).await?;
+        SubModel::new("bar", foo.clone())?.save(&state.db).await?;
+        SubModel::new("baz", foo)?.save(&state.db).await?;
+
+        // In the order asked for, with a gap where there is no SubModel
+        let names = vec!["baz".to_string(), "qux".to_string(), "bar".to_string()];
+        let (model, children) = Model::get_snapshot(&state.db, "foo", &names).await?;
+        assert_eq!(model.name(), "foo");
+        assert_eq!(children.len(), 3);
+        assert_eq!(
+            children[0].as_ref().map(SubModel::name).as_deref(),
+            Some("baz")
+        );
+        assert!(children[1].is_none());
+        assert_eq!(
+            children[2].as_ref().map(SubModel::name).as_deref(),
+            Some("bar")
+        );
+
+        let (_, children) = Model::get_snapshot(&state.db, "foo", &[]).await?;
+        assert!(children.is_empty());
+
+        assert!(Model::get_snapshot(&state.db, "qux", &names).await.is_err());
+
+        Ok(())
+    })
+}
+
+#[apply(state)]
+fn test_transact_get_items_limits<DB>(state: State<DB>) -> TestResult
+where
+    DB: Database + Send + Sync,
+{
+    smol::run(async {
+        let (pk, sk) = Model::new("foo", 1)?.key();
+        assert!(state
+            .db
+            .transact_get_items(vec![(pk.clone(), sk.clone()), (pk, sk)])
+            .await
+            .is_err());
+
+        let too_many = (0..=traits::MAX_TRANSACTION_ITEMS)
+            .map(|i| Model::new(i.to_string(), 1).map(|model| model.key()))
+            .collect::<Result<Vec<_>, _>>()?;
+        assert!(state.db.transact_get_items(too_many).await.is_err());
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_transact_get_items_is_consistent() -> TestResult {
+    let db = Arc::new(mem::memorydb());
+    let done = Arc::new(AtomicBool::new(false));
+    let keys = vec![Model::new("foo", 0)?.key(), Model::new("bar", 0)?.key()];
+
+    // Both Models are always written together, so a snapshot never sees them differ.
+    // The writer and the reader each have a thread, so that their requests interleave
+    let writer = {
+        let (db, done) = (db.clone(), done.clone());
+        thread::spawn(move || {
+            let written = smol::run(async {
+                for i in 0..200 {
+                    Transaction::new(&*db)
+                        .put(&Model::new("foo", i)?)
+                        .put(&Model::new("bar", i)?)
+                        .run()
+                        .await?;
+                }
+                Ok::<_, Box<dyn Error>>(())
+            });
+            done.store(true, Ordering::SeqCst);
+            written.map_err(|e| e.to_string())
+        })
+    };
+    let reader = thread::spawn(move || {
+        let read = smol::run(async {
+            loop {
+                let finished = done.load(Ordering::SeqCst);
+
+                let res = db.transact_get_items(keys.clone()).await?;
+                let values = res
+                    .responses
+                    .unwrap_or_default()
+                    .into_iter()
+                    .filter_map(|response| response.item)
+                    .map(|item| Model::from_hashmap(&item).map(|model| model.value()))
+                    .collect::<Result<Vec<_>, _>>()?;
+                if values.len() == 2 {
+                    assert_eq!(values[0], values[1]);
+                }
+
+                if finished {
+                    return Ok::<_, Box<dyn Error>>(());
+                }
+            }
+        });
+        read.map_err(|e| e.to_string())
+    });
+
+    writer.join().expect("writer panicked")?;
+    reader.join().expect("reader panicked")?;
+    Ok(())
+}
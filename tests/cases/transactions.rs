@@ -0,0 +1,207 @@
+use rstest::rstest;
+use rstest_reuse::*;
+
+use single_table::*;
+use traits::Database;
+use transaction::{OperationKind, TransactionError};
+
+use super::*;
+
+#[apply(state)]
+fn test_transaction_builder<DB>(state: State<DB>) -> TestResult
+where
+    DB: Database + Send + Sync,
+{
+    smol::run(async {
+        let foo = Model::new("foo", 1)?;
+        let bar = SubModel::new("bar", foo.clone())?;
+
+        Transaction::new(&state.db)
+            .put_new(&foo)
+            .put(&bar)
+            .client_request_token("create-foo")
+            .run()
+            .await?;
+
+        let (_, children) = Model::get_with_children(&state.db, "foo").await?;
+        assert_eq!(children.len(), 1);
+
+        Transaction::new(&state.db)
+            .check_exists::<Model>(&foo.pk(), &foo.sk())
+            .add::<Model>(&foo.pk(), &foo.sk(), SUBMODEL_COUNT_ATTRIBUTE, 1)
+            .delete(&bar)
+            .run()
+            .await
+            .expect_err("two operations on foo");
+
+        Transaction::new(&state.db)
+            .add::<Model>(&foo.pk(), &foo.sk(), SUBMODEL_COUNT_ATTRIBUTE, 1)
+            .delete(&bar)
+            .run()
+            .await?;
+
+        let (foo, children) = Model::get_with_children(&state.db, "foo").await?;
+        assert_eq!(foo.submodel_count(), 1);
+        assert!(children.is_empty());
+
+        Ok(())
+    })
+}
+
+#[apply(state)]
+fn test_transaction_cancellations<DB>(state: State<DB>) -> TestResult
+where
+    DB: Database + Send + Sync,
+{
+    smol::run(async {
+        let foo = Model::new("foo", 1)?;
+        let mut bar = Model::new("bar", 2)?;
+        bar.save(&state.db).await?;
+
+        let result = Transaction::new(&state.db)
+            .check_exists::<Model>(&foo.pk(), &foo.sk())
+            .put(&Model::new("baz", 3)?)
+            .put_new(&bar)
+            .run()
+            .await;
+
+        // Only the operations that failed are reported, with their position
+        let cancellations = match result {
+            Err(TransactionError::Cancelled(cancellations)) => cancellations,
+            other => panic!("expected a cancellation, got {:?}", other),
+        };
+        assert_eq!(cancellations.len(), 2);
+        assert_eq!(cancellations[0].index, 0);
+        assert_eq!(
+            cancellations[0].operation.kind,
+            OperationKind::ConditionCheck
+        );
+        assert_eq!(cancellations[0].operation.pk, "model#foo");
+        assert_eq!(cancellations[0].reason, "ConditionalCheckFailed");
+        assert_eq!(cancellations[1].index, 2);
+        assert_eq!(cancellations[1].operation.kind, OperationKind::PutNew);
+
+        assert!(Model::get(&state.db, "baz").await.is_err());
+
+        Ok(())
+    })
+}
+
+#[apply(state)]
+fn test_transaction_unique_email<DB>(state: State<DB>) -> TestResult
+where
+    DB: Database + Send + Sync,
+{
+    smol::run(async {
+        let foo = Model::new("foo", 1)?.with_email("a@example.com")?;
+        let put = Transaction::new(&state.db).put_new(&foo);
+
+        // The guard of the email is claimed in the same transaction
+        let kinds: Vec<OperationKind> = put.operations().iter().map(|op| op.kind).collect();
+        assert_eq!(kinds, vec![OperationKind::PutNew, OperationKind::PutNew]);
+        put.run().await?;
+
+        let bar = Model::new("bar", 2)?.with_email("a@example.com")?;
+        assert!(Transaction::new(&state.db).put(&bar).run().await.is_err());
+        assert!(Model::get(&state.db, "bar").await.is_err());
+
+        // Putting over foo without reading it would orphan the guard of its email
+        let foo = Model::new("foo", 3)?;
+        assert!(Transaction::new(&state.db).put(&foo).run().await.is_err());
+
+        let foo = Model::get(&state.db, "foo").await?.without_email();
+        Transaction::new(&state.db).put(&foo).run().await?;
+        Model::new("bar", 2)?
+            .with_email("a@example.com")?
+            .save(&state.db)
+            .await?;
+
+        Ok(())
+    })
+}
+
+#[apply(state)]
+fn test_transaction_update<DB>(state: State<DB>) -> TestResult
+where
+    DB: Database + Send + Sync,
+{
+    smol::run(async {
+        let mut foo = Model::new("foo", 1)?;
+        foo.save(&state.db).await?;
+        Transaction::new(&state.db)
+            .add::<Model>(&foo.pk(), &foo.sk(), SUBMODEL_COUNT_ATTRIBUTE, 2)
+            .run()
+            .await?;
+
+        // The count is kept, though `foo` was read before it was added to
+        Transaction::new(&state.db).update(&foo).run().await?;
+        assert_eq!(Model::get(&state.db, "foo").await?.submodel_count(), 2);
+
+        let result = Transaction::new(&state.db)
+            .update(&Model::new("bar", 2)?)
+            .run()
+            .await;
+        let cancellations = match result {
+            Err(TransactionError::Cancelled(cancellations)) => cancellations,
+            other => panic!("expected a cancellation, got {:?}", other),
+        };
+        assert_eq!(cancellations[0].operation.kind, OperationKind::Update);
+        assert_eq!(cancellations[0].reason, "ConditionalCheckFailed");
+        assert!(Model::get(&state.db, "bar").await.is_err());
+
+        Ok(())
+    })
+}
+
+#[apply(state)]
+fn test_transaction_put_keeps_count<DB>(state: State<DB>) -> TestResult
+where
+    DB: Database + Send + Sync,
+{
+    let options = SaveOptions {
+        update_parent_count: true,
+        ..Default::default()
+    };
+
+    smol::run(async {
+        let foo = Model::new("foo", 1)?;
+        Transaction::new(&state.db).put_new(&foo).run().await?;
+        SubModel::new("bar", foo.clone())?
+            .save_with(&state.db, options)
+            .await?;
+
+        // `foo` was read before its SubModel was counted
+        Transaction::new(&state.db).put(&foo).run().await?;
+        assert_eq!(Model::get(&state.db, "foo").await?.submodel_count(), 1);
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_transaction_limits() -> TestResult {
+    let db = mem::memorydb();
+
+    let empty = Transaction::new(&db);
+    assert!(matches!(empty.validate(), Err(TransactionError::Empty)));
+
+    let mut too_many = Transaction::new(&db);
+    for i in 0..=traits::MAX_TRANSACTION_ITEMS {
+        too_many = too_many.put(&Model::new(i.to_string(), 1)?);
+    }
+    assert!(matches!(
+        smol::run(too_many.run()),
+        Err(TransactionError::TooManyOperations(101))
+    ));
+    assert!(smol::run(Model::get(&db, "0")).is_err());
+
+    let foo = Model::new("foo", 1)?;
+    let duplicate = Transaction::new(&db).put(&foo).delete(&foo);
+    assert_eq!(duplicate.len(), 2);
+    assert!(matches!(
+        duplicate.validate(),
+        Err(TransactionError::DuplicateItem(..))
+    ));
+
+    Ok(())
+}
@@ -0,0 +1,106 @@
+use chrono::{Duration, TimeZone, Utc};
+use rstest::rstest;
+use rstest_reuse::*;
+use std::sync::Arc;
+
+use clock::ManualClock;
+use single_table::*;
+use traits::Database;
+
+use super::*;
+
+#[apply(state)]
+fn test_time_to_live<DB>(state: State<DB>) -> TestResult
+where
+    DB: Database + Send + Sync,
+{
+    let res = smol::run(state.db.describe_time_to_live())?;
+    let description = res.time_to_live_description.ok_or("description")?;
+    assert_eq!(description.time_to_live_status.as_deref(), Some("DISABLED"));
+
+    let res = smol::run(state.db.update_time_to_live(TTL_ATTRIBUTE, true))?;
+    let specification = res.time_to_live_specification.ok_or("specification")?;
+    assert_eq!(specification.attribute_name, TTL_ATTRIBUTE);
+    assert!(specification.enabled);
+
+    let res = smol::run(state.db.describe_time_to_live())?;
+    let description = res.time_to_live_description.ok_or("description")?;
+    assert_eq!(description.attribute_name.as_deref(), Some(TTL_ATTRIBUTE));
+
+    Ok(())
+}
+
+#[test]
+fn test_sweep_expired() -> TestResult {
+    let start = Utc.ymd(2020, 7, 1).and_hms(12, 0, 0);
+    let clock = Arc::new(ManualClock::new(start));
+    let db = mem::memorydb().with_clock(clock.clone());
+
+    let mut session = Model::new("session", 1)?.expire_at(start + Duration::minutes(30));
+    let mut forever = Model::new("forever", 2)?;
+
+    smol::run(async {
+        db.update_time_to_live(TTL_ATTRIBUTE, true).await?;
+        session.save(&db).await?;
+        forever.save(&db).await?;
+
+        // Not yet expired
+        clock.advance(Duration::minutes(29));
+        assert_eq!(db.sweep_expired().await, 0);
+        assert!(Model::get(&db, "session").await.is_ok());
+
+        clock.advance(Duration::minutes(2));
+        assert_eq!(db.sweep_expired().await, 1);
+        assert!(Model::get(&db, "session").await.is_err());
+        assert!(Model::get(&db, "forever").await.is_ok());
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_sweep_disabled() -> TestResult {
+    let start = Utc.ymd(2020, 7, 1).and_hms(12, 0, 0);
+    let clock = Arc::new(ManualClock::new(start));
+    let db = mem::memorydb().with_clock(clock.clone());
+
+    let mut session = Model::new("session", 1)?.expire_at(start);
+
+    smol::run(async {
+        session.save(&db).await?;
+
+        // TTL has not been enabled on the table
+        clock.advance(Duration::days(1));
+        assert_eq!(db.sweep_expired().await, 0);
+        assert!(Model::get(&db, "session").await.is_ok());
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_sweeper_uses_clock() -> TestResult {
+    let start = Utc.ymd(2020, 7, 1).and_hms(12, 0, 0);
+    let clock = Arc::new(ManualClock::new(start));
+    let db = Arc::new(mem::memorydb().with_clock(clock.clone()));
+
+    let mut session = Model::new("session", 1)?.expire_at(start + Duration::minutes(30));
+
+    smol::run(async {
+        db.update_time_to_live(TTL_ATTRIBUTE, true).await?;
+        session.save(&*db).await?;
+
+        // Sweeps happen in wall-clock time, but expiry is decided by the clock,
+        // which has not moved
+        let sweeper = mem::spawn_sweeper(db.clone(), std::time::Duration::from_millis(1));
+        smol::Timer::after(std::time::Duration::from_millis(20)).await;
+        assert!(Model::get(&*db, "session").await.is_ok());
+        drop(sweeper);
+
+        clock.advance(Duration::minutes(31));
+        assert_eq!(db.sweep_expired().await, 1);
+        assert!(Model::get(&*db, "session").await.is_err());
+
+        Ok(())
+    })
+}
@@ -0,0 +1,136 @@
+use rstest::rstest;
+use rstest_reuse::*;
+
+use single_table::*;
+use traits::Database;
+
+use super::*;
+
+async fn save_with_email(db: &impl Database, name: &str, email: &str) -> TestResult {
+    Model::new(name, 1)?.with_email(email)?.save(db).await
+}
+
+fn conflict(result: TestResult) -> Option<UniqueConflict> {
+    result.err()?.downcast_ref::<UniqueConflict>().cloned()
+}
+
+#[apply(state)]
+fn test_unique_conflict<DB>(state: State<DB>) -> TestResult
+where
+    DB: Database + Send + Sync,
+{
+    smol::run(async {
+        save_with_email(&state.db, "foo", "a@example.com").await?;
+
+        let duplicate = save_with_email(&state.db, "bar", "a@example.com").await;
+        assert_eq!(
+            conflict(duplicate),
+            Some(UniqueConflict {
+                attribute: EMAIL_ATTRIBUTE.to_string(),
+                value: "a@example.com".to_string(),
+            })
+        );
+        assert!(Model::get(&state.db, "bar").await.is_err());
+
+        // Saving the owner again keeps its claim
+        let mut foo = Model::get(&state.db, "foo").await?;
+        foo.save(&state.db).await?;
+        assert_eq!(foo.email(), Some("a@example.com"));
+
+        Ok(())
+    })
+}
+
+#[apply(state)]
+fn test_unique_release<DB>(state: State<DB>) -> TestResult
+where
+    DB: Database + Send + Sync,
+{
+    smol::run(async {
+        save_with_email(&state.db, "foo", "a@example.com").await?;
+
+        // Changing the email releases the old one
+        let mut foo = Model::get(&state.db, "foo")
+            .await?
+            .with_email("b@example.com")?;
+        foo.save(&state.db).await?;
+        save_with_email(&state.db, "bar", "a@example.com").await?;
+        assert!(conflict(save_with_email(&state.db, "baz", "b@example.com").await).is_some());
+
+        // As does removing it
+        let mut bar = Model::get(&state.db, "bar").await?.without_email();
+        bar.save(&state.db).await?;
+        save_with_email(&state.db, "baz", "a@example.com").await?;
+
+        // And deleting its owner
+        Model::delete(&state.db, "foo").await?;
+        save_with_email(&state.db, "qux", "b@example.com").await?;
+
+        Ok(())
+    })
+}
+
+#[apply(state)]
+fn test_unique_overwrite<DB>(state: State<DB>) -> TestResult
+where
+    DB: Database + Send + Sync,
+{
+    smol::run(async {
+        save_with_email(&state.db, "foo", "a@example.com").await?;
+
+        // A new Model can't be saved over one with an email, which would orphan its guard
+        assert!(Model::new("foo", 2)?.save(&state.db).await.is_err());
+        let foo = Model::get(&state.db, "foo").await?;
+        assert_eq!(foo.email(), Some("a@example.com"));
+        assert!(conflict(save_with_email(&state.db, "bar", "a@example.com").await).is_some());
+
+        // Unless it replaces that Model, releasing the guard
+        Model::new("foo", 2)?
+            .replacing(&foo)
+            .save(&state.db)
+            .await?;
+        assert_eq!(Model::get(&state.db, "foo").await?.email(), None);
+        save_with_email(&state.db, "bar", "a@example.com").await?;
+
+        Ok(())
+    })
+}
+
+#[apply(state)]
+fn test_unique_guard<DB>(state: State<DB>) -> TestResult
+where
+    DB: Database + Send + Sync,
+{
+    smol::run(async {
+        save_with_email(&state.db, "foo", "a#b@example.com").await?;
+
+        let key = unique::Guard::key_of(EMAIL_ATTRIBUTE, "a#b@example.com");
+        assert_eq!(key, "unique#email#a%23b@example.com");
+
+        let hashmap = state
+            .db
+            .get_item(key.clone(), key)
+            .await?
+            .item
+            .expect("guard");
+        match Registry::default().decode(&hashmap)? {
+            Item::Guard(guard) => {
+                assert_eq!(guard.value(), "a#b@example.com");
+                let foo = Model::new("foo", 1)?;
+                assert_eq!(guard.owner(), (foo.pk(), foo.sk()));
+            }
+            item => panic!("expected a guard: {:?}", item),
+        }
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_unique_validation() {
+    let model = Model::new("foo", 1).expect("valid name");
+    assert_eq!(
+        model.with_email("").err(),
+        Some(ValidationError::EmptyName(EMAIL_ATTRIBUTE))
+    );
+}